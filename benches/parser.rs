@@ -0,0 +1,72 @@
+//! Benchmarks of the parser on large generated sources: many small functions, long expressions
+//! and deeply nested blocks. Run them with `cargo bench --bench parser`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use std::fmt::Write;
+
+/// Functions of a few statements each, like most code.
+fn many_functions(count: usize) -> String {
+    let mut source = String::new();
+    for index in 0..count {
+        writeln!(
+            source,
+            "// function {index}\n\
+             fn f{index}(a: int, b: bool) -> int {{\n    \
+                 let x = a * {index} + (a - 1) / 2;\n    \
+                 while x > 0 {{\n        x = x - f{index}(x, b);\n    }}\n    \
+                 if b {{ return x; }} else {{ return a; }}\n\
+             }}\n",
+            index = index
+        )
+        .unwrap();
+    }
+    source
+}
+
+/// One function returning a sum of `terms` products.
+fn long_expression(terms: usize) -> String {
+    let mut source = String::from("fn main() -> int {\n    return 1");
+    for index in 0..terms {
+        write!(source, " + {} * x{} - (y / 3)", index + 1, index).unwrap();
+    }
+    source.push_str(";\n}\n");
+    source
+}
+
+/// One function with `depth` nested blocks.
+fn nested_blocks(depth: usize) -> String {
+    let mut source = String::from("fn main() {\n");
+    for _ in 0..depth {
+        source.push_str("if x < 1 {\n");
+    }
+    for _ in 0..depth {
+        source.push_str("x = x + 1;\n}\n");
+    }
+    source.push_str("}\n");
+    source
+}
+
+fn bench_parser(c: &mut Criterion) {
+    let inputs = [
+        ("many_functions", many_functions(2_000)),
+        ("long_expression", long_expression(5_000)),
+        ("nested_blocks", nested_blocks(200)),
+    ];
+
+    let mut group = c.benchmark_group("parse");
+    for (name, source) in &inputs {
+        assert!(hai::parse(source).is_ok(), "{} doesn't parse", name);
+        group.throughput(Throughput::Bytes(source.len() as u64));
+        group.bench_with_input(BenchmarkId::new("cst", name), source, |b, source| {
+            b.iter(|| hai::parser::parse(source))
+        });
+        group.bench_with_input(BenchmarkId::new("ast", name), source, |b, source| {
+            b.iter(|| hai::parse(source))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_parser);
+criterion_main!(benches);
@@ -0,0 +1,36 @@
+//! Benchmarks of the virtual machine against the tree-walking interpreter, on the loops and calls
+//! of `examples_hai/loops.hai`. Run them with `cargo bench --bench vm`.
+//!
+//! Parsing and compiling to bytecode happen before the measures, only the execution is timed.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use hai::vm::compiler::compile_module;
+use hai::vm::machine::Machine;
+
+const SOURCE: &str = include_str!("../examples_hai/loops.hai");
+
+fn bench_vm(c: &mut Criterion) {
+    let program = hai::parse(SOURCE).unwrap();
+    let expected = hai::interpreter::run_main(&program).unwrap();
+
+    let mut group = c.benchmark_group("loops");
+    group.sample_size(20);
+    group.bench_function("interpreter", |b| {
+        b.iter(|| hai::interpreter::run_main(&program))
+    });
+    for opt_level in 0..=2 {
+        let module = hai::compile(SOURCE, opt_level).unwrap();
+        let bytecode = compile_module(&module).unwrap();
+        assert_eq!(Machine::new(&bytecode).run("main", &[]), Ok(expected));
+        group.bench_with_input(
+            BenchmarkId::new("vm", opt_level),
+            &bytecode,
+            |b, bytecode| b.iter(|| Machine::new(bytecode).run("main", &[])),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_vm);
+criterion_main!(benches);
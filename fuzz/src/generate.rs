@@ -0,0 +1,584 @@
+//! Random well-typed programs.
+//!
+//! Programs are generated in a small model of their own, rather than in an `ast::Program`, so
+//! that the shrinker can rewrite them freely; they reach the compiler as source code. Generated
+//! programs follow the rules of the convertor and always terminate, though they may trap:
+//!
+//! - functions only call the functions before them, so there is no recursion,
+//! - loops count up to a small bound with a counter that nothing else assigns,
+//! - divisors are any integer expression, zero and -1 being picked on purpose so that the runners
+//!   are compared on their traps too,
+//! - variables are only used in the scope they are declared in, where they can be shadowed.
+
+use hai::ast::{BinOp, Type};
+
+use std::fmt;
+
+/// A xorshift generator, good enough to pick programs and reproducible from its seed.
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // splitmix the seed, so that close seeds give unrelated programs and zero is valid
+        let mut state = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        state = (state ^ (state >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        state = (state ^ (state >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        Rng((state ^ (state >> 31)) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Returns a number in `0..bound`.
+    pub fn below(&mut self, bound: u32) -> u32 {
+        (self.next() % u64::from(bound)) as u32
+    }
+
+    /// Returns true `numerator` times out of `denominator`.
+    pub fn chance(&mut self, numerator: u32, denominator: u32) -> bool {
+        self.below(denominator) < numerator
+    }
+
+    pub fn choose<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len() as u32) as usize]
+    }
+}
+
+/// Limits on the size of generated programs.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Functions besides `main`.
+    pub functions: u32,
+    pub parameters: u32,
+    /// Statements of a block, before the final return.
+    pub statements: u32,
+    /// Nested `if`, `while` and blocks.
+    pub nesting: u32,
+    /// Nested operators and calls in an expression.
+    pub expression_depth: u32,
+    pub loop_count: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            functions: 4,
+            parameters: 3,
+            statements: 5,
+            nesting: 2,
+            expression_depth: 3,
+            loop_count: 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    /// The functions in declaration order, `main` last.
+    pub functions: Vec<Function>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub parameters: Vec<(String, Type)>,
+    pub return_ty: Type,
+    pub body: Vec<Statement>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    Let {
+        name: String,
+        expression: Expression,
+    },
+    Assign {
+        name: String,
+        expression: Expression,
+    },
+    If {
+        condition: Expression,
+        then_body: Vec<Statement>,
+        else_body: Option<Vec<Statement>>,
+    },
+    /// A `while` loop running `count` times, written with its counter declared before it and
+    /// incremented at the end of its body.
+    Loop {
+        counter: String,
+        count: u32,
+        body: Vec<Statement>,
+    },
+    Block(Vec<Statement>),
+    Return(Option<Expression>),
+    Expression(Expression),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    Integer(i32),
+    Boolean(bool),
+    Variable(String),
+    Binary(BinOp, Box<Expression>, Box<Expression>),
+    Call(String, Vec<Expression>),
+}
+
+/// Generates the program of `seed`.
+pub fn generate(seed: u64, config: &Config) -> Program {
+    let mut generator = Generator {
+        rng: Rng::new(seed),
+        config,
+        signatures: Vec::new(),
+        scopes: Vec::new(),
+        next_variable: 0,
+        next_counter: 0,
+    };
+
+    let mut functions = Vec::new();
+    let count = generator.rng.below(config.functions + 1);
+    for index in 0..count {
+        let parameter_count = generator.rng.below(config.parameters + 1);
+        let parameters = (0..parameter_count)
+            .map(|index| (format!("p{}", index), generator.value_type()))
+            .collect();
+        let return_ty = match generator.rng.below(4) {
+            0 => Type::Unit,
+            1 => Type::Boolean,
+            _ => Type::Int,
+        };
+        functions.push(generator.function(format!("f{}", index), parameters, return_ty));
+    }
+    functions.push(generator.function("main".to_string(), Vec::new(), Type::Int));
+
+    Program { functions }
+}
+
+#[derive(Debug, Clone)]
+struct Variable {
+    name: String,
+    ty: Type,
+    /// Loop counters can't be assigned, or the loop could run forever.
+    counter: bool,
+}
+
+struct Generator<'c> {
+    rng: Rng,
+    config: &'c Config,
+    /// The functions generated so far, which are the ones that can be called.
+    signatures: Vec<(String, Vec<Type>, Type)>,
+    scopes: Vec<Vec<Variable>>,
+    next_variable: usize,
+    next_counter: usize,
+}
+
+impl<'c> Generator<'c> {
+    fn value_type(&mut self) -> Type {
+        if self.rng.chance(2, 3) {
+            Type::Int
+        } else {
+            Type::Boolean
+        }
+    }
+
+    fn function(
+        &mut self,
+        name: String,
+        parameters: Vec<(String, Type)>,
+        return_ty: Type,
+    ) -> Function {
+        self.scopes.push(
+            parameters
+                .iter()
+                .map(|(name, ty)| Variable {
+                    name: name.clone(),
+                    ty: *ty,
+                    counter: false,
+                })
+                .collect(),
+        );
+        self.next_variable = 0;
+        self.next_counter = 0;
+
+        let mut body = self.statements(return_ty, self.config.nesting);
+        if return_ty != Type::Unit {
+            body.push(Statement::Return(Some(self.expression(return_ty))));
+        } else if self.rng.chance(1, 4) {
+            body.push(Statement::Return(None));
+        }
+
+        self.scopes.pop();
+        self.signatures.push((
+            name.clone(),
+            parameters.iter().map(|&(_, ty)| ty).collect(),
+            return_ty,
+        ));
+        Function {
+            name,
+            parameters,
+            return_ty,
+            body,
+        }
+    }
+
+    /// Returns the variables that can be referred to, the innermost declaration of each name.
+    fn visible(&self) -> Vec<&Variable> {
+        let mut visible: Vec<&Variable> = Vec::new();
+        for variable in self
+            .scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter().rev())
+        {
+            if visible.iter().all(|other| other.name != variable.name) {
+                visible.push(variable);
+            }
+        }
+        visible
+    }
+
+    fn declare(&mut self, name: String, ty: Type, counter: bool) {
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .push(Variable { name, ty, counter });
+    }
+
+    /// Generates the statements of a block in a new scope.
+    fn block(&mut self, return_ty: Type, nesting: u32) -> Vec<Statement> {
+        self.scopes.push(Vec::new());
+        let statements = self.statements(return_ty, nesting);
+        self.scopes.pop();
+        statements
+    }
+
+    fn statements(&mut self, return_ty: Type, nesting: u32) -> Vec<Statement> {
+        let count = self.rng.below(self.config.statements + 1);
+        (0..count)
+            .map(|_| self.statement(return_ty, nesting))
+            .collect()
+    }
+
+    fn statement(&mut self, return_ty: Type, nesting: u32) -> Statement {
+        loop {
+            match self.rng.below(12) {
+                0..=3 => {
+                    let ty = self.value_type();
+                    let expression = self.expression(ty);
+                    // shadow a variable now and then, with a value of any type
+                    let shadowed = self
+                        .visible()
+                        .into_iter()
+                        .filter(|variable| !variable.counter)
+                        .map(|variable| variable.name.clone())
+                        .collect::<Vec<_>>();
+                    let name = if !shadowed.is_empty() && self.rng.chance(1, 6) {
+                        self.rng.choose(&shadowed).clone()
+                    } else {
+                        self.next_variable += 1;
+                        format!("v{}", self.next_variable - 1)
+                    };
+                    self.declare(name.clone(), ty, false);
+                    return Statement::Let { name, expression };
+                }
+                4 | 5 => {
+                    let assignable = self
+                        .visible()
+                        .into_iter()
+                        .filter(|variable| !variable.counter)
+                        .map(|variable| (variable.name.clone(), variable.ty))
+                        .collect::<Vec<_>>();
+                    if assignable.is_empty() {
+                        continue;
+                    }
+                    let (name, ty) = self.rng.choose(&assignable).clone();
+                    let expression = self.expression(ty);
+                    return Statement::Assign { name, expression };
+                }
+                6 | 7 if nesting > 0 => {
+                    let condition = self.expression(Type::Boolean);
+                    let then_body = self.block(return_ty, nesting - 1);
+                    let else_body = if self.rng.chance(1, 2) {
+                        Some(self.block(return_ty, nesting - 1))
+                    } else {
+                        None
+                    };
+                    return Statement::If {
+                        condition,
+                        then_body,
+                        else_body,
+                    };
+                }
+                8 if nesting > 0 => {
+                    let counter = format!("i{}", self.next_counter);
+                    self.next_counter += 1;
+                    let count = self.rng.below(self.config.loop_count + 1);
+                    self.declare(counter.clone(), Type::Int, true);
+                    let body = self.block(return_ty, nesting - 1);
+                    return Statement::Loop {
+                        counter,
+                        count,
+                        body,
+                    };
+                }
+                9 if nesting > 0 => return Statement::Block(self.block(return_ty, nesting - 1)),
+                10 if !self.signatures.is_empty() => {
+                    let index = self.rng.below(self.signatures.len() as u32) as usize;
+                    return Statement::Expression(self.call(index, self.config.expression_depth));
+                }
+                // an early return, only in nested code so that most of the function runs
+                11 if nesting < self.config.nesting => {
+                    let expression = match return_ty {
+                        Type::Unit => None,
+                        ty => Some(self.expression(ty)),
+                    };
+                    return Statement::Return(expression);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn expression(&mut self, ty: Type) -> Expression {
+        self.expression_at(ty, self.config.expression_depth)
+    }
+
+    fn expression_at(&mut self, ty: Type, depth: u32) -> Expression {
+        let variables: Vec<String> = self
+            .visible()
+            .into_iter()
+            .filter(|variable| variable.ty == ty)
+            .map(|variable| variable.name.clone())
+            .collect();
+        let functions: Vec<usize> = (0..self.signatures.len())
+            .filter(|&index| self.signatures[index].2 == ty)
+            .collect();
+
+        loop {
+            let choice = if depth == 0 {
+                self.rng.below(2)
+            } else {
+                self.rng.below(6)
+            };
+            match choice {
+                0 if !variables.is_empty() => {
+                    return Expression::Variable(self.rng.choose(&variables).clone())
+                }
+                0 | 1 => return self.literal(ty),
+                2 | 3 if ty == Type::Int => {
+                    let op = *self.rng.choose(&[BinOp::Add, BinOp::Sub, BinOp::Mul]);
+                    let lhs = self.expression_at(Type::Int, depth - 1);
+                    let rhs = self.expression_at(Type::Int, depth - 1);
+                    return Expression::Binary(op, Box::new(lhs), Box::new(rhs));
+                }
+                2 | 3 => {
+                    let (op, operand_ty) = if self.rng.chance(2, 3) {
+                        let op = *self.rng.choose(&[
+                            BinOp::Equal,
+                            BinOp::NotEqual,
+                            BinOp::Less,
+                            BinOp::LessEqual,
+                            BinOp::Greater,
+                            BinOp::GreaterEqual,
+                        ]);
+                        (op, Type::Int)
+                    } else {
+                        let op = *self.rng.choose(&[BinOp::Equal, BinOp::NotEqual]);
+                        (op, Type::Boolean)
+                    };
+                    let lhs = self.expression_at(operand_ty, depth - 1);
+                    let rhs = self.expression_at(operand_ty, depth - 1);
+                    return Expression::Binary(op, Box::new(lhs), Box::new(rhs));
+                }
+                4 if ty == Type::Int => {
+                    let lhs = self.expression_at(Type::Int, depth - 1);
+                    let divisor = match self.rng.below(12) {
+                        0..=7 => Expression::Integer(self.rng.below(9) as i32 + 1),
+                        // the divisors that trap, -1 only when dividing the smallest integer
+                        8 => Expression::Integer(*self.rng.choose(&[0, -1])),
+                        _ => self.expression_at(Type::Int, depth - 1),
+                    };
+                    return Expression::Binary(BinOp::Divide, Box::new(lhs), Box::new(divisor));
+                }
+                5 if !functions.is_empty() => {
+                    let index = *self.rng.choose(&functions);
+                    return self.call(index, depth - 1);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn call(&mut self, index: usize, depth: u32) -> Expression {
+        let (name, parameters, _) = self.signatures[index].clone();
+        let args = parameters
+            .into_iter()
+            .map(|ty| self.expression_at(ty, depth))
+            .collect();
+        Expression::Call(name, args)
+    }
+
+    fn literal(&mut self, ty: Type) -> Expression {
+        match ty {
+            Type::Boolean => Expression::Boolean(self.rng.chance(1, 2)),
+            _ => {
+                let value = match self.rng.below(8) {
+                    0..=3 => self.rng.below(10),
+                    4 | 5 => self.rng.below(1000),
+                    6 => *self.rng.choose(&[
+                        255,
+                        256,
+                        65_535,
+                        1 << 30,
+                        i32::MAX as u32,
+                        i32::MIN as u32,
+                        u32::MAX,
+                    ]),
+                    _ => self.rng.below(i32::MAX as u32),
+                };
+                Expression::Integer(value as i32)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, function) in self.functions.iter().enumerate() {
+            if index != 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", function)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let parameters: Vec<String> = self
+            .parameters
+            .iter()
+            .map(|(name, ty)| format!("{}: {}", name, ty))
+            .collect();
+        write!(f, "fn {}({})", self.name, parameters.join(", "))?;
+        if self.return_ty != Type::Unit {
+            write!(f, " -> {}", self.return_ty)?;
+        }
+        writeln!(f, " {{")?;
+        write_statements(f, &self.body, 1)?;
+        writeln!(f, "}}")
+    }
+}
+
+fn write_statements(f: &mut fmt::Formatter, statements: &[Statement], depth: usize) -> fmt::Result {
+    for statement in statements {
+        write_statement(f, statement, depth)?;
+    }
+    Ok(())
+}
+
+fn write_statement(f: &mut fmt::Formatter, statement: &Statement, depth: usize) -> fmt::Result {
+    let indent = "    ".repeat(depth);
+    match *statement {
+        Statement::Let {
+            ref name,
+            ref expression,
+        } => writeln!(f, "{}let {} = {};", indent, name, expression),
+        Statement::Assign {
+            ref name,
+            ref expression,
+        } => writeln!(f, "{}{} = {};", indent, name, expression),
+        Statement::If {
+            ref condition,
+            ref then_body,
+            ref else_body,
+        } => {
+            writeln!(f, "{}if {} {{", indent, condition)?;
+            write_statements(f, then_body, depth + 1)?;
+            if let Some(else_body) = else_body {
+                writeln!(f, "{}}} else {{", indent)?;
+                write_statements(f, else_body, depth + 1)?;
+            }
+            writeln!(f, "{}}}", indent)
+        }
+        Statement::Loop {
+            ref counter,
+            count,
+            ref body,
+        } => {
+            writeln!(f, "{}let {} = 0;", indent, counter)?;
+            writeln!(f, "{}while {} < {} {{", indent, counter, count)?;
+            write_statements(f, body, depth + 1)?;
+            writeln!(f, "{}    {} = {} + 1;", indent, counter, counter)?;
+            writeln!(f, "{}}}", indent)
+        }
+        Statement::Block(ref statements) => {
+            writeln!(f, "{}{{", indent)?;
+            write_statements(f, statements, depth + 1)?;
+            writeln!(f, "{}}}", indent)
+        }
+        Statement::Return(None) => writeln!(f, "{}return;", indent),
+        Statement::Return(Some(ref expression)) => {
+            writeln!(f, "{}return {};", indent, expression)
+        }
+        Statement::Expression(ref expression) => writeln!(f, "{}{};", indent, expression),
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            // there are no negative literals, the smallest integer can't even be negated
+            Expression::Integer(i32::MIN) => write!(f, "(0 - {} - 1)", i32::MAX),
+            Expression::Integer(value) if value < 0 => write!(f, "(0 - {})", -value),
+            Expression::Integer(value) => write!(f, "{}", value),
+            Expression::Boolean(value) => write!(f, "{}", value),
+            Expression::Variable(ref name) => write!(f, "{}", name),
+            Expression::Binary(op, ref lhs, ref rhs) => {
+                // nested operators are parenthesized, which is always right whatever the
+                // precedences
+                for (index, operand) in [lhs, rhs].iter().enumerate() {
+                    if index != 0 {
+                        write!(f, " {} ", op)?;
+                    }
+                    match ***operand {
+                        Expression::Binary(..) => write!(f, "({})", operand)?,
+                        _ => write!(f, "{}", operand)?,
+                    }
+                }
+                Ok(())
+            }
+            Expression::Call(ref name, ref args) => {
+                let args: Vec<String> = args.iter().map(ToString::to_string).collect();
+                write!(f, "{}({})", name, args.join(", "))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_programs_compile() {
+        let config = Config::default();
+        for seed in 0..200 {
+            let source = generate(seed, &config).to_string();
+            if let Err(error) = hai::compile(&source, 0) {
+                panic!("seed {} doesn't compile: {}\n{}", seed, error, source);
+            }
+        }
+    }
+
+    #[test]
+    fn seeds_are_reproducible() {
+        let config = Config::default();
+        assert_eq!(generate(7, &config), generate(7, &config));
+        assert_ne!(generate(7, &config), generate(8, &config));
+    }
+}
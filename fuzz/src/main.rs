@@ -0,0 +1,186 @@
+//! Differential testing of the compiler on random programs.
+//!
+//! Each seed gives a well-typed program, run by the AST interpreter, the virtual machine and the
+//! native backends at every optimization level. When a runner disagrees with the interpreter, the
+//! program is shrunk to a small one on which it still does, and both are written to the output
+//! directory.
+
+mod generate;
+mod run;
+mod shrink;
+
+use generate::Config;
+use run::{Outcome, Runner};
+
+use std::path::{Path, PathBuf};
+
+const USAGE: &str = "\
+usage: hai-fuzz [options]
+
+Runs random programs through every backend and reports the ones on which they disagree.
+
+options:
+    --seed <n>          first seed (default 0)
+    --count <n>         number of programs (default 100)
+    --no-native         only compare the virtual machine, not the executables built with `cc`
+    --out <dir>         directory for the failing programs (default `fuzz-failures`)
+    -h, --help          print this message";
+
+#[derive(Debug)]
+struct Options {
+    seed: u64,
+    count: u64,
+    native: bool,
+    out: PathBuf,
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", USAGE);
+        return;
+    }
+    let options = match parse_args(args) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("error: {}", message);
+            eprintln!("run `hai-fuzz --help` for the usage");
+            std::process::exit(2);
+        }
+    };
+
+    let work_dir = std::env::temp_dir().join(format!("hai-fuzz-{}", std::process::id()));
+    if let Err(error) = std::fs::create_dir_all(&work_dir) {
+        eprintln!("error: can't create `{}`: {}", work_dir.display(), error);
+        std::process::exit(3);
+    }
+
+    let config = Config::default();
+    let runners = Runner::all(options.native);
+    let (mut failures, mut skipped) = (0, 0);
+    for seed in options.seed..options.seed + options.count {
+        let program = generate::generate(seed, &config);
+        let source = program.to_string();
+
+        let reference = match run::reference(&source) {
+            Some(reference) => reference,
+            None => {
+                skipped += 1;
+                continue;
+            }
+        };
+
+        let failed = mismatches(&source, &reference, &runners, &work_dir);
+        if failed.is_empty() {
+            continue;
+        }
+        failures += 1;
+        println!(
+            "seed {}: {} disagree with the interpreter",
+            seed,
+            failed.len()
+        );
+        for (runner, outcome) in &failed {
+            println!(
+                "    {}: {:?}, expected {:?}",
+                runner.name(),
+                outcome,
+                runner.expected(&reference)
+            );
+        }
+
+        // the program is shrunk on the runners that failed, the others are likely to agree
+        let failing: Vec<Runner> = failed.iter().map(|&(runner, _)| runner).collect();
+        let shrunk = shrink::shrink(program, |candidate| {
+            let source = candidate.to_string();
+            if hai::compile(&source, 0).is_err() {
+                return false;
+            }
+            match run::reference(&source) {
+                Some(Outcome::Failure(_)) | None => false,
+                Some(reference) => !mismatches(&source, &reference, &failing, &work_dir).is_empty(),
+            }
+        });
+        let shrunk = shrunk.to_string();
+
+        let path = options.out.join(format!("seed-{}.hai", seed));
+        let shrunk_path = options.out.join(format!("seed-{}.min.hai", seed));
+        if let Err(error) = write(&path, &source).and_then(|()| write(&shrunk_path, &shrunk)) {
+            eprintln!("error: {}", error);
+            std::process::exit(3);
+        }
+        println!("    shrunk to {}:\n{}", shrunk_path.display(), shrunk);
+    }
+
+    let _ = std::fs::remove_dir_all(&work_dir);
+    println!(
+        "{} programs, {} failed, {} skipped as too slow",
+        options.count, failures, skipped
+    );
+    if failures != 0 {
+        std::process::exit(1);
+    }
+}
+
+fn parse_args(args: Vec<String>) -> Result<Options, String> {
+    let mut options = Options {
+        seed: 0,
+        count: 100,
+        native: true,
+        out: PathBuf::from("fuzz-failures"),
+    };
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--seed" | "--count" => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("missing number after `{}`", arg))?;
+                let value = value
+                    .parse()
+                    .map_err(|_| format!("invalid number `{}`", value))?;
+                if arg == "--seed" {
+                    options.seed = value;
+                } else {
+                    options.count = value;
+                }
+            }
+            "--no-native" => options.native = false,
+            "--out" => {
+                let path = args.next().ok_or("missing directory after `--out`")?;
+                options.out = PathBuf::from(path);
+            }
+            _ => return Err(format!("unknown option `{}`", arg)),
+        }
+    }
+    Ok(options)
+}
+
+/// Returns the runners whose outcome on `source` isn't the one expected from `reference`.
+fn mismatches(
+    source: &str,
+    reference: &Outcome,
+    runners: &[Runner],
+    work_dir: &Path,
+) -> Vec<(Runner, Outcome)> {
+    runners
+        .iter()
+        .filter_map(|runner| {
+            let outcome = runner.run(source, work_dir);
+            if outcome == runner.expected(reference) {
+                None
+            } else {
+                Some((*runner, outcome))
+            }
+        })
+        .collect()
+}
+
+fn write(path: &Path, content: &str) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|error| format!("can't create `{}`: {}", parent.display(), error))?;
+    }
+    std::fs::write(path, content)
+        .map_err(|error| format!("can't write `{}`: {}", path.display(), error))
+}
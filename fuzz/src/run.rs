@@ -0,0 +1,215 @@
+//! The ways to run a program: the interpreter as the reference, and the runners expected to
+//! agree with it.
+
+use hai::backend;
+use hai::interpreter;
+use hai::vm;
+
+use std::os::unix::process::ExitStatusExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// How long a native executable may run, generated programs take milliseconds.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The signal killing native executables on a division trap, raised by the hardware on x86-64
+/// and by a helper in the C backend.
+const SIGFPE: i32 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// The bytecode virtual machine, run on the IR.
+    Vm,
+    /// The x86-64 backend, assembled and linked by `cc`.
+    Assembler,
+    /// The x86-64 backend with its own encoder, linked by `cc`.
+    IntegratedAssembler,
+    /// The C backend, compiled by `cc`.
+    C,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Runner {
+    pub backend: Backend,
+    pub opt_level: u32,
+}
+
+/// The result of a run. Traps are compared without their message, which differs between the
+/// runners.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// The value returned by `main`.
+    Value(i32),
+    /// A division by zero, or of the smallest integer by -1.
+    Trap,
+    /// The program couldn't be compiled or run, for this reason.
+    Failure(String),
+}
+
+/// Runs `source` with the interpreter, or returns `None` if it takes too long to tell what the
+/// other runners should give.
+pub fn reference(source: &str) -> Option<Outcome> {
+    let program = match hai::parse(source) {
+        Ok(program) => program,
+        Err(error) => return Some(Outcome::Failure(error.to_string())),
+    };
+    match interpreter::run_main(&program) {
+        Ok(value) => Some(Outcome::Value(value)),
+        Err(interpreter::Error::OutOfFuel) => None,
+        Err(interpreter::Error::Trap(_)) => Some(Outcome::Trap),
+        Err(interpreter::Error::Runtime(message)) => Some(Outcome::Failure(message)),
+    }
+}
+
+impl Runner {
+    /// Returns the runners to compare to the reference, the native ones only if `native` is set.
+    pub fn all(native: bool) -> Vec<Runner> {
+        let mut backends = vec![Backend::Vm];
+        if native {
+            backends.extend(&[Backend::Assembler, Backend::IntegratedAssembler, Backend::C]);
+        }
+        let mut runners = Vec::new();
+        for backend in backends {
+            for opt_level in 0..=2 {
+                runners.push(Runner { backend, opt_level });
+            }
+        }
+        runners
+    }
+
+    pub fn name(&self) -> String {
+        let backend = match self.backend {
+            Backend::Vm => "vm",
+            Backend::Assembler => "asm",
+            Backend::IntegratedAssembler => "obj",
+            Backend::C => "c",
+        };
+        format!("{} -O{}", backend, self.opt_level)
+    }
+
+    /// Returns the outcome expected from this runner given the one of the reference. Native
+    /// executables only give the low byte of the result, as their exit status.
+    pub fn expected(&self, reference: &Outcome) -> Outcome {
+        match (self.backend, reference) {
+            (Backend::Assembler, &Outcome::Value(value))
+            | (Backend::IntegratedAssembler, &Outcome::Value(value))
+            | (Backend::C, &Outcome::Value(value)) => Outcome::Value(value & 0xff),
+            _ => reference.clone(),
+        }
+    }
+
+    /// Runs `source`, using `work_dir` for the files of the native toolchain.
+    pub fn run(&self, source: &str, work_dir: &Path) -> Outcome {
+        match self.try_run(source, work_dir) {
+            Ok(outcome) => outcome,
+            Err(message) => Outcome::Failure(message),
+        }
+    }
+
+    fn try_run(&self, source: &str, work_dir: &Path) -> Result<Outcome, String> {
+        let module = hai::compile(source, self.opt_level).map_err(|error| error.to_string())?;
+        let (extension, content) = match self.backend {
+            Backend::Vm => {
+                let bytecode = vm::compiler::compile_module(&module)?;
+                return match vm::machine::Machine::new(&bytecode).run("main", &[]) {
+                    Ok(value) => Ok(Outcome::Value(value)),
+                    Err(vm::machine::VmError::DivisionByZero)
+                    | Err(vm::machine::VmError::DivisionOverflow) => Ok(Outcome::Trap),
+                    Err(error) => Err(format!("runtime error: {}", error)),
+                };
+            }
+            Backend::Assembler => ("s", backend::x86_64::emit_module(&module).into_bytes()),
+            Backend::IntegratedAssembler => ("o", backend::x86_64::emit_object(&module)),
+            Backend::C => ("c", backend::c::emit_module(&module).into_bytes()),
+        };
+
+        let input_path = work_dir.join(format!("program.{}", extension));
+        let executable_path = work_dir.join("program");
+        std::fs::write(&input_path, content).map_err(|error| error.to_string())?;
+        let status = Command::new("cc")
+            .arg("-w")
+            .arg("-o")
+            .arg(&executable_path)
+            .arg(&input_path)
+            .stderr(Stdio::null())
+            .status()
+            .map_err(|error| format!("can't run `cc`: {}", error))?;
+        if !status.success() {
+            return Err("`cc` failed".to_string());
+        }
+        run_executable(&executable_path)
+    }
+}
+
+fn run_executable(path: &Path) -> Result<Outcome, String> {
+    let mut child = Command::new(path)
+        .spawn()
+        .map_err(|error| format!("can't run the executable: {}", error))?;
+    let start = Instant::now();
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => {
+                return match (status.code(), status.signal()) {
+                    (Some(code), _) => Ok(Outcome::Value(code)),
+                    (None, Some(SIGFPE)) => Ok(Outcome::Trap),
+                    (None, signal) => Err(format!("killed by signal {:?}", signal)),
+                };
+            }
+            Ok(None) if start.elapsed() > TIMEOUT => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err("timed out".to_string());
+            }
+            Ok(None) => std::thread::sleep(Duration::from_millis(1)),
+            Err(error) => return Err(error.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generate::{generate, Config};
+
+    #[test]
+    fn the_vm_agrees_with_the_interpreter() {
+        let config = Config::default();
+        let work_dir = std::env::temp_dir();
+        let mut traps = 0;
+        for seed in 0..50 {
+            let source = generate(seed, &config).to_string();
+            let reference = match reference(&source) {
+                Some(reference) => reference,
+                None => continue,
+            };
+            traps += (reference == Outcome::Trap) as u32;
+            for runner in Runner::all(false) {
+                let outcome = runner.run(&source, &work_dir);
+                assert_eq!(outcome, reference, "{} on seed {}", runner.name(), seed);
+            }
+        }
+        assert!(traps > 0, "no program traps");
+    }
+
+    #[test]
+    fn native_traps_are_recognized() {
+        let work_dir = std::env::temp_dir().join(format!("hai-fuzz-test-{}", std::process::id()));
+        std::fs::create_dir_all(&work_dir).unwrap();
+        for source in &[
+            "fn main() -> int {\n    let zero = 0;\n    return 1 / zero;\n}\n",
+            "fn main() -> int {\n    let min = 0 - 2147483647 - 1;\n    return min / (0 - 1);\n}\n",
+        ] {
+            assert_eq!(reference(source), Some(Outcome::Trap));
+            for runner in Runner::all(true) {
+                assert_eq!(
+                    runner.run(source, &work_dir),
+                    Outcome::Trap,
+                    "{}",
+                    runner.name()
+                );
+            }
+        }
+        let _ = std::fs::remove_dir_all(&work_dir);
+    }
+}
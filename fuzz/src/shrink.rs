@@ -0,0 +1,235 @@
+//! Reduction of failing programs.
+//!
+//! The shrinker tries smaller variants of a program, one change at a time, and keeps the first
+//! one that is still interesting until no variant is. Variants are not checked here: removing a
+//! `let` leaves the uses of its variable dangling, so the predicate must reject the programs the
+//! compiler rejects.
+
+use crate::generate::{Expression, Function, Program, Statement};
+
+/// Returns a program as small as the shrinker can make it while `interesting` holds.
+pub fn shrink<F: FnMut(&Program) -> bool>(mut program: Program, mut interesting: F) -> Program {
+    'progress: loop {
+        for candidate in variants(&program) {
+            if interesting(&candidate) {
+                program = candidate;
+                continue 'progress;
+            }
+        }
+        return program;
+    }
+}
+
+/// Returns the programs one change smaller than `program`, the biggest changes first.
+fn variants(program: &Program) -> Vec<Program> {
+    let mut variants = Vec::new();
+
+    // `main` is last and always kept
+    for index in 0..program.functions.len() - 1 {
+        let mut functions = program.functions.clone();
+        functions.remove(index);
+        variants.push(Program { functions });
+    }
+
+    for (index, function) in program.functions.iter().enumerate() {
+        for body in statement_lists(&function.body) {
+            let mut functions = program.functions.clone();
+            functions[index] = Function {
+                body,
+                ..function.clone()
+            };
+            variants.push(Program { functions });
+        }
+    }
+    variants
+}
+
+fn statement_lists(statements: &[Statement]) -> Vec<Vec<Statement>> {
+    let mut lists = Vec::new();
+    let mut replace = |index: usize, replacement: Vec<Statement>| {
+        let mut list = statements[..index].to_vec();
+        list.extend(replacement);
+        list.extend_from_slice(&statements[index + 1..]);
+        lists.push(list);
+    };
+
+    for index in 0..statements.len() {
+        replace(index, Vec::new());
+    }
+
+    // replace nested code by its content
+    for (index, statement) in statements.iter().enumerate() {
+        match *statement {
+            Statement::If {
+                ref then_body,
+                ref else_body,
+                ..
+            } => {
+                replace(index, then_body.clone());
+                if let Some(else_body) = else_body {
+                    replace(index, else_body.clone());
+                }
+            }
+            Statement::Loop { ref body, .. } | Statement::Block(ref body) => {
+                replace(index, body.clone())
+            }
+            _ => {}
+        }
+    }
+
+    for (index, statement) in statements.iter().enumerate() {
+        for variant in statement_variants(statement) {
+            replace(index, vec![variant]);
+        }
+    }
+    lists
+}
+
+fn statement_variants(statement: &Statement) -> Vec<Statement> {
+    match *statement {
+        Statement::Let {
+            ref name,
+            ref expression,
+        } => expression_variants(expression)
+            .into_iter()
+            .map(|expression| Statement::Let {
+                name: name.clone(),
+                expression,
+            })
+            .collect(),
+        Statement::Assign {
+            ref name,
+            ref expression,
+        } => expression_variants(expression)
+            .into_iter()
+            .map(|expression| Statement::Assign {
+                name: name.clone(),
+                expression,
+            })
+            .collect(),
+        Statement::If {
+            ref condition,
+            ref then_body,
+            ref else_body,
+        } => {
+            let mut variants = Vec::new();
+            let with = |condition: &Expression, then_body: &[Statement], else_body| Statement::If {
+                condition: condition.clone(),
+                then_body: then_body.to_vec(),
+                else_body,
+            };
+            if else_body.is_some() {
+                variants.push(with(condition, then_body, None));
+            }
+            for condition in expression_variants(condition) {
+                variants.push(with(&condition, then_body, else_body.clone()));
+            }
+            for then_body in statement_lists(then_body) {
+                variants.push(with(condition, &then_body, else_body.clone()));
+            }
+            if let Some(else_body) = else_body {
+                for else_body in statement_lists(else_body) {
+                    variants.push(with(condition, then_body, Some(else_body)));
+                }
+            }
+            variants
+        }
+        Statement::Loop {
+            ref counter,
+            count,
+            ref body,
+        } => {
+            let mut counts = vec![1, count.saturating_sub(1)];
+            counts.retain(|&smaller| smaller < count);
+            counts.dedup();
+            let mut variants: Vec<Statement> = counts
+                .into_iter()
+                .map(|count| Statement::Loop {
+                    counter: counter.clone(),
+                    count,
+                    body: body.clone(),
+                })
+                .collect();
+            for body in statement_lists(body) {
+                variants.push(Statement::Loop {
+                    counter: counter.clone(),
+                    count,
+                    body,
+                });
+            }
+            variants
+        }
+        Statement::Block(ref statements) => statement_lists(statements)
+            .into_iter()
+            .map(Statement::Block)
+            .collect(),
+        Statement::Return(Some(ref expression)) => expression_variants(expression)
+            .into_iter()
+            .map(|expression| Statement::Return(Some(expression)))
+            .collect(),
+        Statement::Expression(ref expression) => expression_variants(expression)
+            .into_iter()
+            .map(Statement::Expression)
+            .collect(),
+        Statement::Return(None) => Vec::new(),
+    }
+}
+
+fn expression_variants(expression: &Expression) -> Vec<Expression> {
+    // the type of the expression isn't known, the literal of the wrong type is rejected
+    let literals = vec![Expression::Integer(0), Expression::Boolean(false)];
+    match *expression {
+        Expression::Integer(value) => {
+            let mut smaller = vec![0, 1, value / 2];
+            smaller.retain(|&smaller| smaller.unsigned_abs() < value.unsigned_abs());
+            smaller.dedup();
+            smaller.into_iter().map(Expression::Integer).collect()
+        }
+        Expression::Boolean(true) => vec![Expression::Boolean(false)],
+        Expression::Boolean(false) => Vec::new(),
+        Expression::Variable(_) => literals,
+        Expression::Binary(op, ref lhs, ref rhs) => {
+            let mut variants = literals;
+            variants.push((**lhs).clone());
+            variants.push((**rhs).clone());
+            for lhs in expression_variants(lhs) {
+                variants.push(Expression::Binary(op, Box::new(lhs), rhs.clone()));
+            }
+            // a divisor can become zero, the runners are then compared on the trap
+            for rhs in expression_variants(rhs) {
+                variants.push(Expression::Binary(op, lhs.clone(), Box::new(rhs)));
+            }
+            variants
+        }
+        Expression::Call(ref name, ref args) => {
+            let mut variants = literals;
+            for (index, arg) in args.iter().enumerate() {
+                for arg in expression_variants(arg) {
+                    let mut args = args.clone();
+                    args[index] = arg;
+                    variants.push(Expression::Call(name.clone(), args));
+                }
+            }
+            variants
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generate::{generate, Config};
+
+    #[test]
+    fn shrinks_to_the_interesting_part() {
+        // a program is interesting while it compiles and multiplies somewhere
+        let shrunk = shrink(generate(3, &Config::default()), |program| {
+            let source = program.to_string();
+            hai::compile(&source, 0).is_ok() && source.contains(" * ")
+        });
+        assert_eq!(
+            shrunk.to_string(),
+            "fn main() -> int {\n    let v7 = 0 * 0;\n    return 0;\n}\n"
+        );
+    }
+}
@@ -0,0 +1,303 @@
+//! The analysis of an open document, and the language features computed from it.
+
+use hai::ast;
+use hai::ast::resolve::{DefinitionKind, Resolution};
+
+use lsp_types::{
+    CompletionItem, CompletionItemKind, Diagnostic, DiagnosticSeverity, DocumentSymbol, Hover,
+    HoverContents, MarkupContent, MarkupKind, Position, Range, SymbolKind,
+};
+
+/// Converts between byte offsets and LSP positions, whose characters are UTF-16 code units.
+#[derive(Debug, Clone)]
+struct LineIndex {
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    fn new(text: &str) -> Self {
+        let mut line_starts = vec![0];
+        line_starts.extend(text.match_indices('\n').map(|(index, _)| index + 1));
+        LineIndex { line_starts }
+    }
+
+    fn position(&self, text: &str, offset: usize) -> Position {
+        let offset = offset.min(text.len());
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let character: usize = text[self.line_starts[line]..offset]
+            .chars()
+            .map(char::len_utf16)
+            .sum();
+        Position::new(line as u32, character as u32)
+    }
+
+    fn offset(&self, text: &str, position: Position) -> usize {
+        let line_start = match self.line_starts.get(position.line as usize) {
+            Some(&start) => start,
+            None => return text.len(),
+        };
+
+        let mut character = 0;
+        for (index, c) in text[line_start..].char_indices() {
+            if character >= position.character as usize || c == '\n' {
+                return line_start + index;
+            }
+            character += c.len_utf16();
+        }
+        text.len()
+    }
+}
+
+#[derive(Debug)]
+struct Analysis {
+    program: ast::Program,
+    resolution: Resolution,
+}
+
+#[derive(Debug)]
+pub struct Document {
+    text: String,
+    lines: LineIndex,
+    /// The analysis of the last version that parsed, it may be older than the text.
+    analysis: Option<Analysis>,
+    up_to_date: bool,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Document {
+    pub fn new(text: String) -> Self {
+        let mut document = Document {
+            text: String::new(),
+            lines: LineIndex::new(""),
+            analysis: None,
+            up_to_date: false,
+            diagnostics: Vec::new(),
+        };
+        document.update(text);
+        document
+    }
+
+    pub fn update(&mut self, text: String) {
+        self.lines = LineIndex::new(&text);
+        self.text = text;
+        self.diagnostics.clear();
+
+        match hai::parse(&self.text) {
+            Ok(program) => {
+                match hai::lower(&program) {
+                    Ok(_) => {}
+                    Err(hai::Error::Semantic(errors)) => {
+                        for error in errors {
+                            let diagnostic = self.diagnostic(error.span, error.to_string());
+                            self.diagnostics.push(diagnostic);
+                        }
+                    }
+                    Err(error) => {
+                        let diagnostic = self.diagnostic(ast::Span::new(0, 0), error.to_string());
+                        self.diagnostics.push(diagnostic);
+                    }
+                }
+                let resolution = Resolution::new(&program);
+                self.analysis = Some(Analysis {
+                    program,
+                    resolution,
+                });
+                self.up_to_date = true;
+            }
+            Err(hai::Error::Syntax(errors)) => {
+                for error in errors {
+                    let message = match error.hint {
+                        Some(ref hint) => format!("{}\nhint: {}", error, hint),
+                        None => error.to_string(),
+                    };
+                    let diagnostic = self.diagnostic(error.span, message);
+                    self.diagnostics.push(diagnostic);
+                }
+                self.up_to_date = false;
+            }
+            Err(error) => {
+                // parsing only fails with syntax errors today, any other error still gets reported
+                let diagnostic = self.diagnostic(ast::Span::new(0, 0), error.to_string());
+                self.diagnostics.push(diagnostic);
+                self.up_to_date = false;
+            }
+        }
+    }
+
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.diagnostics.clone()
+    }
+
+    /// Returns the name defining the symbol at `position`.
+    pub fn definition(&self, position: Position) -> Option<Range> {
+        let analysis = self.current_analysis()?;
+        let offset = self.lines.offset(&self.text, position);
+        let definition = analysis.resolution.definition_at(offset)?;
+        Some(self.range(definition.span))
+    }
+
+    pub fn hover(&self, position: Position) -> Option<Hover> {
+        let analysis = self.current_analysis()?;
+        let offset = self.lines.offset(&self.text, position);
+        let definition = analysis.resolution.definition_at(offset)?;
+        let hovered = analysis
+            .resolution
+            .references
+            .iter()
+            .map(|reference| reference.span)
+            .find(|span| span.contains(offset))
+            .unwrap_or(definition.span);
+
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: format!("```hai\n{}\n```", definition.signature()),
+            }),
+            range: Some(self.range(hovered)),
+        })
+    }
+
+    pub fn symbols(&self) -> Vec<DocumentSymbol> {
+        let analysis = match self.current_analysis() {
+            Some(analysis) => analysis,
+            None => return Vec::new(),
+        };
+
+        let definitions = analysis
+            .resolution
+            .definitions
+            .iter()
+            .filter(|definition| definition.kind == DefinitionKind::Function);
+        analysis
+            .program
+            .declarations
+            .iter()
+            .zip(definitions)
+            .map(|(declaration, definition)| {
+                let ast::Declaration::FunctionDeclaration { span, .. } = *declaration;
+                #[allow(deprecated)]
+                DocumentSymbol {
+                    name: definition.name.clone(),
+                    detail: Some(definition.signature()),
+                    kind: SymbolKind::FUNCTION,
+                    tags: None,
+                    deprecated: None,
+                    range: self.range(span),
+                    selection_range: self.range(definition.span),
+                    children: None,
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the names that can be used at `position`. The last analysis is used even if the
+    /// text changed since, as the code being typed rarely parses.
+    pub fn completions(&self, position: Position) -> Vec<CompletionItem> {
+        let analysis = match self.analysis {
+            Some(ref analysis) => analysis,
+            None => return Vec::new(),
+        };
+
+        let offset = self.lines.offset(&self.text, position);
+        analysis
+            .resolution
+            .visible_at(offset)
+            .into_iter()
+            .map(|definition| CompletionItem {
+                label: definition.name.clone(),
+                kind: Some(match definition.kind {
+                    DefinitionKind::Function => CompletionItemKind::FUNCTION,
+                    DefinitionKind::Parameter | DefinitionKind::Variable => {
+                        CompletionItemKind::VARIABLE
+                    }
+                }),
+                detail: Some(definition.signature()),
+                ..CompletionItem::default()
+            })
+            .collect()
+    }
+
+    fn current_analysis(&self) -> Option<&Analysis> {
+        if self.up_to_date {
+            self.analysis.as_ref()
+        } else {
+            None
+        }
+    }
+
+    fn range(&self, span: ast::Span) -> Range {
+        Range::new(
+            self.lines.position(&self.text, span.start),
+            self.lines.position(&self.text, span.end),
+        )
+    }
+
+    fn diagnostic(&self, span: ast::Span, message: String) -> Diagnostic {
+        Diagnostic {
+            range: self.range(span),
+            severity: Some(DiagnosticSeverity::ERROR),
+            source: Some("hai".to_string()),
+            message,
+            ..Diagnostic::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positions_count_utf16_code_units() {
+        let text = "a\n\u{e9}\u{1f600}b\n";
+        let lines = LineIndex::new(text);
+        let b = text.find('b').unwrap();
+        assert_eq!(lines.position(text, b), Position::new(1, 3));
+        assert_eq!(lines.offset(text, Position::new(1, 3)), b);
+        assert_eq!(lines.offset(text, Position::new(1, 40)), text.len() - 1);
+    }
+
+    #[test]
+    fn features_follow_the_scopes() {
+        let text = "fn f(x: int) -> int {\n    let y = x + 1;\n    return f(y);\n}\n";
+        let document = Document::new(text.to_string());
+        assert!(document.diagnostics().is_empty());
+
+        let definition = document.definition(Position::new(2, 13)).unwrap();
+        assert_eq!(definition.start, Position::new(1, 8));
+
+        let hover = document.hover(Position::new(2, 11)).unwrap();
+        match hover.contents {
+            HoverContents::Markup(content) => {
+                assert!(content.value.contains("fn f(x: int) -> int"))
+            }
+            _ => panic!("unexpected hover {:?}", hover),
+        }
+
+        let labels = |position| -> Vec<String> {
+            document
+                .completions(position)
+                .into_iter()
+                .map(|item| item.label)
+                .collect()
+        };
+        assert_eq!(labels(Position::new(1, 12)), ["f", "x"]);
+        assert_eq!(labels(Position::new(2, 4)), ["f", "x", "y"]);
+        assert_eq!(document.symbols().len(), 1);
+    }
+
+    #[test]
+    fn errors_are_reported_on_their_code() {
+        let document = Document::new("fn main() {\n    let a = b;\n}\n".to_string());
+        let diagnostics = document.diagnostics();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].range.start, Position::new(1, 12));
+
+        let document = Document::new("fn main( {}\nfn f() { return 1 }".to_string());
+        let diagnostics = document.diagnostics();
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].range.start, Position::new(0, 9));
+        assert_eq!(diagnostics[1].range.start, Position::new(1, 17));
+        assert!(diagnostics[1].message.contains("hint: add a `;`"));
+    }
+}
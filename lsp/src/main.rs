@@ -0,0 +1,307 @@
+//! A language server for hai, speaking the LSP over the standard input and output.
+
+mod document;
+
+use document::Document;
+
+use lsp_server::{Connection, ExtractError, Message, Notification, Request, RequestId, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, PublishDiagnostics,
+};
+use lsp_types::request::{Completion, DocumentSymbolRequest, GotoDefinition, HoverRequest};
+use lsp_types::{
+    CompletionOptions, CompletionResponse, DocumentSymbolResponse, GotoDefinitionResponse,
+    HoverProviderCapability, Location, OneOf, PublishDiagnosticsParams, ServerCapabilities,
+    TextDocumentSyncCapability, TextDocumentSyncKind, Url,
+};
+
+use std::collections::HashMap;
+use std::error::Error;
+
+type Result<T> = std::result::Result<T, Box<dyn Error + Sync + Send>>;
+
+fn main() -> Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        definition_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        completion_provider: Some(CompletionOptions::default()),
+        ..ServerCapabilities::default()
+    };
+    connection.initialize(serde_json::to_value(capabilities)?)?;
+
+    Server {
+        connection: &connection,
+        documents: HashMap::new(),
+    }
+    .run()?;
+
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
+
+struct Server<'a> {
+    connection: &'a Connection,
+    documents: HashMap<Url, Document>,
+}
+
+impl<'a> Server<'a> {
+    fn run(&mut self) -> Result<()> {
+        for message in &self.connection.receiver {
+            match message {
+                Message::Request(request) => {
+                    if self.connection.handle_shutdown(&request)? {
+                        return Ok(());
+                    }
+                    self.handle_request(request)?;
+                }
+                Message::Notification(notification) => self.handle_notification(notification)?,
+                Message::Response(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_request(&mut self, request: Request) -> Result<()> {
+        // kept to reply to a request whose parameters are invalid
+        let id = request.id.clone();
+        let request = match cast::<GotoDefinition>(request) {
+            Ok((id, params)) => {
+                let params = params.text_document_position_params;
+                let (uri, position) = (params.text_document.uri, params.position);
+                let response = self
+                    .documents
+                    .get(&uri)
+                    .and_then(|document| document.definition(position))
+                    .map(|range| GotoDefinitionResponse::Scalar(Location::new(uri, range)));
+                return self.respond(id, response);
+            }
+            Err(Cast::Other(request)) => request,
+            Err(Cast::Invalid(error)) => return self.respond_invalid_params(id, error),
+        };
+        let request = match cast::<HoverRequest>(request) {
+            Ok((id, params)) => {
+                let params = params.text_document_position_params;
+                let response = self
+                    .documents
+                    .get(&params.text_document.uri)
+                    .and_then(|document| document.hover(params.position));
+                return self.respond(id, response);
+            }
+            Err(Cast::Other(request)) => request,
+            Err(Cast::Invalid(error)) => return self.respond_invalid_params(id, error),
+        };
+        let request = match cast::<DocumentSymbolRequest>(request) {
+            Ok((id, params)) => {
+                let response = self
+                    .documents
+                    .get(&params.text_document.uri)
+                    .map(|document| DocumentSymbolResponse::Nested(document.symbols()));
+                return self.respond(id, response);
+            }
+            Err(Cast::Other(request)) => request,
+            Err(Cast::Invalid(error)) => return self.respond_invalid_params(id, error),
+        };
+        let request = match cast::<Completion>(request) {
+            Ok((id, params)) => {
+                let params = params.text_document_position;
+                let response = self
+                    .documents
+                    .get(&params.text_document.uri)
+                    .map(|document| {
+                        CompletionResponse::Array(document.completions(params.position))
+                    });
+                return self.respond(id, response);
+            }
+            Err(Cast::Other(request)) => request,
+            Err(Cast::Invalid(error)) => return self.respond_invalid_params(id, error),
+        };
+
+        let response = Response::new_err(
+            request.id,
+            lsp_server::ErrorCode::MethodNotFound as i32,
+            format!("unsupported request `{}`", request.method),
+        );
+        self.connection.sender.send(Message::Response(response))?;
+        Ok(())
+    }
+
+    fn handle_notification(&mut self, notification: Notification) -> Result<()> {
+        let notification = match cast_notification::<DidOpenTextDocument>(notification) {
+            Ok(params) => {
+                let document = params.text_document;
+                self.documents
+                    .insert(document.uri.clone(), Document::new(document.text));
+                return self.publish_diagnostics(document.uri);
+            }
+            Err(Cast::Other(notification)) => notification,
+            Err(Cast::Invalid(error)) => {
+                log_invalid_notification(&error);
+                return Ok(());
+            }
+        };
+        let notification = match cast_notification::<DidChangeTextDocument>(notification) {
+            Ok(params) => {
+                let uri = params.text_document.uri;
+                // the synchronization is full, so the last change holds the whole text
+                if let (Some(document), Some(change)) = (
+                    self.documents.get_mut(&uri),
+                    params.content_changes.into_iter().last(),
+                ) {
+                    document.update(change.text);
+                }
+                return self.publish_diagnostics(uri);
+            }
+            Err(Cast::Other(notification)) => notification,
+            Err(Cast::Invalid(error)) => {
+                log_invalid_notification(&error);
+                return Ok(());
+            }
+        };
+        match cast_notification::<DidCloseTextDocument>(notification) {
+            Ok(params) => {
+                self.documents.remove(&params.text_document.uri);
+            }
+            Err(Cast::Other(_)) => {}
+            Err(Cast::Invalid(error)) => log_invalid_notification(&error),
+        }
+        Ok(())
+    }
+
+    fn publish_diagnostics(&self, uri: Url) -> Result<()> {
+        let diagnostics = match self.documents.get(&uri) {
+            Some(document) => document.diagnostics(),
+            None => return Ok(()),
+        };
+        let params = PublishDiagnosticsParams::new(uri, diagnostics, None);
+        let notification = Notification::new(
+            <PublishDiagnostics as lsp_types::notification::Notification>::METHOD.to_string(),
+            params,
+        );
+        self.connection
+            .sender
+            .send(Message::Notification(notification))?;
+        Ok(())
+    }
+
+    fn respond<T: serde::Serialize>(&self, id: RequestId, result: T) -> Result<()> {
+        let response = Response::new_ok(id, result);
+        self.connection.sender.send(Message::Response(response))?;
+        Ok(())
+    }
+
+    fn respond_invalid_params(&self, id: RequestId, error: String) -> Result<()> {
+        let response = Response::new_err(id, lsp_server::ErrorCode::InvalidParams as i32, error);
+        self.connection.sender.send(Message::Response(response))?;
+        Ok(())
+    }
+}
+
+/// Notifications have no response, so invalid ones are only logged on the standard error.
+fn log_invalid_notification(error: &str) {
+    eprintln!("ignoring a notification: {}", error);
+}
+
+/// The reasons a message can't be cast to a method.
+enum Cast<M> {
+    /// The message has another method, and is given back.
+    Other(M),
+    /// The parameters don't match the method.
+    Invalid(String),
+}
+
+impl<M> From<ExtractError<M>> for Cast<M> {
+    fn from(error: ExtractError<M>) -> Self {
+        match error {
+            ExtractError::MethodMismatch(message) => Cast::Other(message),
+            ExtractError::JsonError { method, error } => {
+                Cast::Invalid(format!("invalid parameters for `{}`: {}", method, error))
+            }
+        }
+    }
+}
+
+/// Extracts the parameters of a request if it has the method of `R`.
+fn cast<R>(request: Request) -> std::result::Result<(RequestId, R::Params), Cast<Request>>
+where
+    R: lsp_types::request::Request,
+{
+    request.extract(R::METHOD).map_err(Cast::from)
+}
+
+fn cast_notification<N>(
+    notification: Notification,
+) -> std::result::Result<N::Params, Cast<Notification>>
+where
+    N: lsp_types::notification::Notification,
+{
+    notification.extract(N::METHOD).map_err(Cast::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use lsp_server::ErrorCode;
+    use serde_json::json;
+
+    /// Runs a server until the client has sent all the `messages`, and returns its responses.
+    fn exchange(messages: Vec<Message>) -> Vec<Response> {
+        let (server, client) = Connection::memory();
+        for message in messages {
+            client.sender.send(message).unwrap();
+        }
+        drop(client.sender);
+
+        Server {
+            connection: &server,
+            documents: HashMap::new(),
+        }
+        .run()
+        .unwrap();
+        drop(server);
+
+        client
+            .receiver
+            .iter()
+            .filter_map(|message| match message {
+                Message::Response(response) => Some(response),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_invalid_params() {
+        let request = |id: i32, method: &str, params| {
+            Message::Request(Request::new(id.into(), method.to_string(), params))
+        };
+        let responses = exchange(vec![
+            request(1, "textDocument/hover", json!({ "position": 3 })),
+            Message::Notification(Notification::new(
+                "textDocument/didOpen".to_string(),
+                json!({ "textDocument": null }),
+            )),
+            request(
+                2,
+                "textDocument/hover",
+                json!({
+                    "textDocument": { "uri": "file:///main.hai" },
+                    "position": { "line": 0, "character": 0 },
+                }),
+            ),
+        ]);
+
+        // the server keeps answering after invalid messages
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0].id, 1.into());
+        let error = responses[0].error.as_ref().unwrap();
+        assert_eq!(error.code, ErrorCode::InvalidParams as i32);
+        assert_eq!(responses[1].id, 2.into());
+        assert!(responses[1].error.is_none());
+    }
+}
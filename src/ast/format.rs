@@ -0,0 +1,422 @@
+//! Formatting of programs as canonical hai source.
+//!
+//! The syntax tree has no comments, so they are taken from the concrete syntax tree and placed
+//! around the statements they were next to: before a statement when they are on their own line, after it
+//! otherwise. Comments inside an expression are moved after its statement, and comments in the
+//! header of an `if`, a `while` or a function at the start of their block. Formatting a formatted
+//! program gives it back unchanged.
+
+use super::visitor::*;
+use super::*;
+use crate::cst;
+
+const INDENT: &str = "    ";
+
+/// Formats `program`, derived from `tree`.
+pub fn format_program(program: &Program, tree: &cst::Node) -> String {
+    let source = tree.to_string();
+    let mut formatter = Formatter {
+        source: &source,
+        comments: comments(tree),
+        next_comment: 0,
+        output: String::new(),
+        indent: 0,
+        last_end: 0,
+        at_block_start: true,
+        block_end: source.len(),
+        statement: None,
+    };
+    program.accept_program_visitor(&mut formatter);
+    formatter.output
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Comment {
+    span: Span,
+    is_line: bool,
+}
+
+fn comments(tree: &cst::Node) -> Vec<Comment> {
+    tree.tokens()
+        .into_iter()
+        .filter(|token| token.kind.is_comment())
+        .map(|token| Comment {
+            span: Span::new(token.offset, token.end()),
+            is_line: token.kind == cst::SyntaxKind::LineComment,
+        })
+        .collect()
+}
+
+/// Returns the binding strength of the operator, higher binds tighter.
+fn precedence(op: BinOp) -> u8 {
+    match op {
+        BinOp::Mul | BinOp::Divide => 3,
+        BinOp::Add | BinOp::Sub => 2,
+        BinOp::Equal
+        | BinOp::NotEqual
+        | BinOp::Less
+        | BinOp::LessEqual
+        | BinOp::Greater
+        | BinOp::GreaterEqual => 1,
+    }
+}
+
+#[derive(Debug)]
+struct Formatter<'s> {
+    source: &'s str,
+    comments: Vec<Comment>,
+    next_comment: usize,
+    output: String,
+    indent: usize,
+    /// The end of the last statement or comment written, to find the blank lines after it.
+    last_end: usize,
+    /// True until something is written in the current block, blank lines aren't kept there.
+    at_block_start: bool,
+    /// The end of the innermost block, comments after it belong to the code around the block.
+    block_end: usize,
+    statement: Option<StatementId>,
+}
+
+impl<'s> Formatter<'s> {
+    fn write(&mut self, text: &str) {
+        if self.output.is_empty() || self.output.ends_with('\n') {
+            for _ in 0..self.indent {
+                self.output.push_str(INDENT);
+            }
+        }
+        self.output.push_str(text);
+    }
+
+    fn newline(&mut self) {
+        self.output.push('\n');
+    }
+
+    /// Writes a blank line if there is one in the source between the last item and `start`.
+    fn blank_line(&mut self, start: usize) {
+        let gap = &self.source[self.last_end.min(start)..start];
+        if !self.at_block_start && gap.matches('\n').count() >= 2 {
+            self.newline();
+        }
+    }
+
+    fn write_comment(&mut self, comment: Comment) {
+        let source = self.source;
+        self.write(&source[comment.span.start..comment.span.end]);
+        self.last_end = comment.span.end;
+        self.next_comment += 1;
+    }
+
+    /// Writes the comments starting before `offset`, each on its own line.
+    fn leading_comments(&mut self, offset: usize) {
+        while let Some(&comment) = self.comments.get(self.next_comment) {
+            if comment.span.start >= offset {
+                break;
+            }
+            self.blank_line(comment.span.start);
+            self.write_comment(comment);
+            self.newline();
+            self.at_block_start = false;
+        }
+    }
+
+    /// Writes the comments starting before the end of the line where `offset` is, after what was
+    /// written on that line.
+    fn trailing_comments(&mut self, offset: usize) {
+        let line_end = self.source[offset..]
+            .find('\n')
+            .map_or(self.source.len(), |end| offset + end)
+            .min(self.block_end);
+        let mut after_line_comment = false;
+        while let Some(&comment) = self.comments.get(self.next_comment) {
+            if comment.span.start >= line_end {
+                break;
+            }
+            if after_line_comment {
+                self.newline();
+            } else {
+                self.output.push(' ');
+            }
+            self.write_comment(comment);
+            after_line_comment = comment.is_line;
+        }
+    }
+
+    fn format_statement(&mut self, program: &Program, statement: StatementId) {
+        let previous = self.statement.replace(statement);
+        program.accept_statement_visitor(self, statement);
+        self.statement = previous;
+    }
+
+    fn format_expression(&mut self, program: &Program, expression: ExpressionId) -> String {
+        program
+            .accept_expression_visitor(self, expression)
+            .unwrap_or_default()
+    }
+
+    /// Formats an operand of `op`, in parentheses if it binds less tightly. Operators are left
+    /// associative, so parentheses are also needed on the right for operators binding as tightly.
+    fn format_operand(
+        &mut self,
+        program: &Program,
+        op: BinOp,
+        operand: ExpressionId,
+        is_rhs: bool,
+    ) -> String {
+        let text = self.format_expression(program, operand);
+        let needs_parentheses = match program.get_expression(operand) {
+            Some(Expression::BinOp { op: inner, .. }) => {
+                precedence(*inner) < precedence(op)
+                    || (is_rhs && precedence(*inner) == precedence(op))
+            }
+            _ => false,
+        };
+        if needs_parentheses {
+            format!("({})", text)
+        } else {
+            text
+        }
+    }
+}
+
+impl<'s> Visitor for Formatter<'s> {
+    type ProgramItem = ();
+    type DeclarationItem = ();
+    type StatementItem = ();
+    type ExpressionItem = String;
+
+    fn visit_program(&mut self, program: &Program, declarations: &[Declaration]) -> Option<()> {
+        for (index, decl) in declarations.iter().enumerate() {
+            let Declaration::FunctionDeclaration { span, .. } = *decl;
+            if index != 0 {
+                self.newline();
+            }
+            self.at_block_start = true;
+            self.leading_comments(span.start);
+            self.blank_line(span.start);
+            program.accept_declaration_visitor(self, decl);
+            self.last_end = span.end;
+            self.trailing_comments(span.end);
+            self.newline();
+        }
+
+        let remaining = self.comments.len() - self.next_comment;
+        if remaining != 0 {
+            self.newline();
+            self.at_block_start = true;
+            self.leading_comments(self.source.len());
+        }
+        Some(())
+    }
+
+    fn visit_function_declaration(
+        &mut self,
+        program: &Program,
+        name: &str,
+        parameters: &[(String, Type)],
+        return_ty: &Type,
+        statement: StatementId,
+    ) -> Option<()> {
+        let parameters: Vec<String> = parameters
+            .iter()
+            .map(|(name, ty)| format!("{}: {}", name, ty))
+            .collect();
+        self.write(&format!("fn {}({})", name, parameters.join(", ")));
+        if *return_ty != Type::Unit {
+            self.write(&format!(" -> {}", return_ty));
+        }
+        self.write(" ");
+        self.format_statement(program, statement);
+        Some(())
+    }
+
+    fn visit_block_statement(
+        &mut self,
+        program: &Program,
+        statements: &[StatementId],
+    ) -> Option<()> {
+        let span = program.statement_span(self.statement.unwrap())?;
+        let statement_spans = statements
+            .iter()
+            .map(|&statement| program.statement_span(statement))
+            .collect::<Option<Vec<_>>>()?;
+        let has_comments = self
+            .comments
+            .get(self.next_comment)
+            .is_some_and(|comment| comment.span.start < span.end);
+        if statements.is_empty() && !has_comments {
+            self.write("{}");
+            return Some(());
+        }
+
+        self.write("{");
+        self.newline();
+        self.indent += 1;
+        self.at_block_start = true;
+        let block_end = std::mem::replace(&mut self.block_end, span.end);
+        for (&statement, statement_span) in statements.iter().zip(statement_spans) {
+            self.leading_comments(statement_span.start);
+            self.blank_line(statement_span.start);
+            self.at_block_start = false;
+
+            self.format_statement(program, statement);
+            self.last_end = statement_span.end;
+            self.trailing_comments(statement_span.end);
+            self.newline();
+        }
+        self.leading_comments(span.end);
+        self.block_end = block_end;
+        self.indent -= 1;
+        self.write("}");
+        self.last_end = span.end;
+        Some(())
+    }
+
+    fn visit_let_statement(
+        &mut self,
+        program: &Program,
+        identifier: &str,
+        expression: ExpressionId,
+    ) -> Option<()> {
+        let expression = self.format_expression(program, expression);
+        self.write(&format!("let {} = {};", identifier, expression));
+        Some(())
+    }
+
+    fn visit_return_statement(
+        &mut self,
+        program: &Program,
+        expression: Option<ExpressionId>,
+    ) -> Option<()> {
+        match expression {
+            Some(expression) => {
+                let expression = self.format_expression(program, expression);
+                self.write(&format!("return {};", expression));
+            }
+            None => self.write("return;"),
+        }
+        Some(())
+    }
+
+    fn visit_while_statement(
+        &mut self,
+        program: &Program,
+        condition: ExpressionId,
+        body: StatementId,
+    ) -> Option<()> {
+        let condition = self.format_expression(program, condition);
+        self.write(&format!("while {} ", condition));
+        self.format_statement(program, body);
+        Some(())
+    }
+
+    fn visit_if_statement(
+        &mut self,
+        program: &Program,
+        condition: ExpressionId,
+        then_statement: StatementId,
+        else_statement: Option<StatementId>,
+    ) -> Option<()> {
+        let condition = self.format_expression(program, condition);
+        self.write(&format!("if {} ", condition));
+        self.format_statement(program, then_statement);
+        if let Some(else_statement) = else_statement {
+            self.write(" else ");
+            self.format_statement(program, else_statement);
+        }
+        Some(())
+    }
+
+    fn visit_assign_statement(
+        &mut self,
+        program: &Program,
+        identifier: &str,
+        expression: ExpressionId,
+    ) -> Option<()> {
+        let expression = self.format_expression(program, expression);
+        self.write(&format!("{} = {};", identifier, expression));
+        Some(())
+    }
+
+    fn visit_expression_statement(
+        &mut self,
+        program: &Program,
+        expression: ExpressionId,
+    ) -> Option<()> {
+        let expression = self.format_expression(program, expression);
+        self.write(&format!("{};", expression));
+        Some(())
+    }
+
+    fn visit_binop_expression(
+        &mut self,
+        program: &Program,
+        op: BinOp,
+        lhs: ExpressionId,
+        rhs: ExpressionId,
+    ) -> Option<String> {
+        let lhs = self.format_operand(program, op, lhs, false);
+        let rhs = self.format_operand(program, op, rhs, true);
+        Some(format!("{} {} {}", lhs, op, rhs))
+    }
+
+    fn visit_func_call(
+        &mut self,
+        program: &Program,
+        func: ExpressionId,
+        args: &[ExpressionId],
+    ) -> Option<String> {
+        let func = self.format_expression(program, func);
+        let args: Vec<String> = args
+            .iter()
+            .map(|&arg| self.format_expression(program, arg))
+            .collect();
+        Some(format!("{}({})", func, args.join(", ")))
+    }
+
+    fn visit_integer(&mut self, _program: &Program, i: i32) -> Option<String> {
+        Some(i.to_string())
+    }
+
+    fn visit_boolean(&mut self, _program: &Program, b: bool) -> Option<String> {
+        Some(b.to_string())
+    }
+
+    fn visit_identifier(&mut self, _program: &Program, id: &str) -> Option<String> {
+        Some(id.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    fn format(source: &str) -> String {
+        crate::format(source).unwrap()
+    }
+
+    #[test]
+    fn parentheses_follow_precedence() {
+        let formatted = format("fn f()->int{return ((1+2)*3)-(4-(5*6)) + (7<8==true);}");
+        assert_eq!(
+            formatted,
+            "fn f() -> int {\n    return (1 + 2) * 3 - (4 - 5 * 6) + (7 < 8 == true);\n}\n"
+        );
+    }
+
+    #[test]
+    fn comments_are_kept() {
+        let source = "// a\nfn f(x:int){ // b\n  let y = /* c */ x;\n\n\n  if y<1 {} // d\n  /* e */ }\n// f\n";
+        let formatted = format(source);
+        assert_eq!(
+            formatted,
+            "// a\nfn f(x: int) {\n    // b\n    let y = x; /* c */\n\n    if y < 1 {} // d\n    /* e */\n}\n\n// f\n"
+        );
+    }
+
+    #[test]
+    fn formatting_is_idempotent() {
+        let source =
+            "fn a() {} fn b(x: bool) -> bool { while x { if x { x = false; } else if true \
+                      { return x; } } // end\n return x == (1 < 2); }";
+        let formatted = format(source);
+        assert_eq!(format(&formatted), formatted);
+    }
+}
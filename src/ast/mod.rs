@@ -1,36 +1,89 @@
 use id_arena::{Arena, Id};
 
+use std::fmt;
+
+pub mod format;
 pub mod pretty_print_visitor;
+pub mod resolve;
 pub mod visitor;
 
 pub type StatementId = Id<Statement>;
 pub type ExpressionId = Id<Expression>;
 
+/// A range of bytes in the source of a program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+
+    /// Returns the smallest span covering both `self` and `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+
+    /// Returns true if `offset` is inside the span or right after it, where a cursor still
+    /// touches it.
+    pub fn contains(self, offset: usize) -> bool {
+        self.start <= offset && offset <= self.end
+    }
+}
+
 #[derive(Debug)]
 pub struct Program {
     statement_arena: Arena<Statement>,
     expression_arena: Arena<Expression>,
+    statement_spans: Vec<Span>,
+    expression_spans: Vec<Span>,
 
     pub declarations: Vec<Declaration>,
 }
 
+impl Default for Program {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Program {
     pub fn new() -> Self {
         Program {
             statement_arena: Arena::new(),
             expression_arena: Arena::new(),
+            statement_spans: Vec::new(),
+            expression_spans: Vec::new(),
             declarations: Vec::new(),
         }
     }
 
-    pub fn create_statement(&mut self, statement: Statement) -> StatementId {
+    pub fn create_statement(&mut self, statement: Statement, span: Span) -> StatementId {
+        self.statement_spans.push(span);
         self.statement_arena.alloc(statement)
     }
 
-    pub fn create_expression(&mut self, expression: Expression) -> ExpressionId {
+    pub fn create_expression(&mut self, expression: Expression, span: Span) -> ExpressionId {
+        self.expression_spans.push(span);
         self.expression_arena.alloc(expression)
     }
 
+    /// Returns the span of the statement `id`, or `None` if it isn't a statement of this program.
+    pub fn statement_span(&self, id: StatementId) -> Option<Span> {
+        self.get_statement(id)?;
+        Some(self.statement_spans[id.index()])
+    }
+
+    /// Returns the span of the expression `id`, or `None` if it isn't an expression of this
+    /// program.
+    pub fn expression_span(&self, id: ExpressionId) -> Option<Span> {
+        self.get_expression(id)?;
+        Some(self.expression_spans[id.index()])
+    }
+
     pub fn get_statement(&self, id: StatementId) -> Option<&Statement> {
         self.statement_arena.get(id)
     }
@@ -55,8 +108,10 @@ impl Program {
             Declaration::FunctionDeclaration {
                 ref name,
                 ref parameters,
+                ref return_ty,
                 statement,
-            } => visitor.visit_function_declaration(self, name, parameters, statement),
+                ..
+            } => visitor.visit_function_declaration(self, name, parameters, return_ty, statement),
         }
     }
 
@@ -71,7 +126,24 @@ impl Program {
                 Statement::LetStatement {
                     ref identifier,
                     expression,
+                    ..
                 } => visitor.visit_let_statement(self, identifier, expression),
+                Statement::ReturnStatement(expression) => {
+                    visitor.visit_return_statement(self, expression)
+                }
+                Statement::WhileStatement { condition, body } => {
+                    visitor.visit_while_statement(self, condition, body)
+                }
+                Statement::IfStatement {
+                    condition,
+                    then_statement,
+                    else_statement,
+                } => visitor.visit_if_statement(self, condition, then_statement, else_statement),
+                Statement::AssignStatement {
+                    ref identifier,
+                    expression,
+                    ..
+                } => visitor.visit_assign_statement(self, identifier, expression),
                 Statement::ExpressionStatement(expr) => {
                     visitor.visit_expression_statement(self, expr)
                 }
@@ -95,12 +167,205 @@ impl Program {
                     visitor.visit_func_call(self, func, args)
                 }
                 Expression::Integer(i) => visitor.visit_integer(self, i),
+                Expression::Boolean(b) => visitor.visit_boolean(self, b),
                 Expression::Identifier(ref id) => visitor.visit_identifier(self, id),
             }
         } else {
             None
         }
     }
+
+    pub fn try_accept_program_visitor<V: visitor::TryVisitor>(
+        &self,
+        visitor: &mut V,
+    ) -> Result<V::ProgramItem, V::Error> {
+        visitor.visit_program(self, &self.declarations)
+    }
+
+    pub fn try_accept_declaration_visitor<V: visitor::TryVisitor>(
+        &self,
+        visitor: &mut V,
+        decl: &Declaration,
+    ) -> Result<V::DeclarationItem, V::Error> {
+        match *decl {
+            Declaration::FunctionDeclaration {
+                ref name,
+                ref parameters,
+                ref return_ty,
+                statement,
+                ..
+            } => visitor.visit_function_declaration(self, name, parameters, return_ty, statement),
+        }
+    }
+
+    /// Visits the statement `id`, or fails if it isn't a statement of this program.
+    pub fn try_accept_statement_visitor<V: visitor::TryVisitor>(
+        &self,
+        visitor: &mut V,
+        id: StatementId,
+    ) -> Result<V::StatementItem, V::Error> {
+        let stmt = match self.get_statement(id) {
+            Some(stmt) => stmt,
+            None => return Err(visitor.unknown_id(self, visitor::UnknownId::Statement(id))),
+        };
+        match *stmt {
+            Statement::BlockStatement(ref stmts) => visitor.visit_block_statement(self, stmts),
+            Statement::LetStatement {
+                ref identifier,
+                expression,
+                ..
+            } => visitor.visit_let_statement(self, identifier, expression),
+            Statement::ReturnStatement(expression) => {
+                visitor.visit_return_statement(self, expression)
+            }
+            Statement::WhileStatement { condition, body } => {
+                visitor.visit_while_statement(self, condition, body)
+            }
+            Statement::IfStatement {
+                condition,
+                then_statement,
+                else_statement,
+            } => visitor.visit_if_statement(self, condition, then_statement, else_statement),
+            Statement::AssignStatement {
+                ref identifier,
+                expression,
+                ..
+            } => visitor.visit_assign_statement(self, identifier, expression),
+            Statement::ExpressionStatement(expr) => visitor.visit_expression_statement(self, expr),
+        }
+    }
+
+    /// Visits the statements in order, stopping at the first error.
+    pub fn try_accept_statement_visitors<V: visitor::TryVisitor>(
+        &self,
+        visitor: &mut V,
+        ids: &[StatementId],
+    ) -> Result<Vec<V::StatementItem>, V::Error> {
+        ids.iter()
+            .map(|&id| self.try_accept_statement_visitor(visitor, id))
+            .collect()
+    }
+
+    /// Visits the expression `id`, or fails if it isn't an expression of this program.
+    pub fn try_accept_expression_visitor<V: visitor::TryVisitor>(
+        &self,
+        visitor: &mut V,
+        id: ExpressionId,
+    ) -> Result<V::ExpressionItem, V::Error> {
+        let expr = match self.get_expression(id) {
+            Some(expr) => expr,
+            None => return Err(visitor.unknown_id(self, visitor::UnknownId::Expression(id))),
+        };
+        match *expr {
+            Expression::BinOp { op, lhs, rhs } => {
+                visitor.visit_binop_expression(self, op, lhs, rhs)
+            }
+            Expression::FunctionCall { func, ref args } => {
+                visitor.visit_func_call(self, func, args)
+            }
+            Expression::Integer(i) => visitor.visit_integer(self, i),
+            Expression::Boolean(b) => visitor.visit_boolean(self, b),
+            Expression::Identifier(ref id) => visitor.visit_identifier(self, id),
+        }
+    }
+
+    /// Visits the expressions in order, stopping at the first error.
+    pub fn try_accept_expression_visitors<V: visitor::TryVisitor>(
+        &self,
+        visitor: &mut V,
+        ids: &[ExpressionId],
+    ) -> Result<Vec<V::ExpressionItem>, V::Error> {
+        ids.iter()
+            .map(|&id| self.try_accept_expression_visitor(visitor, id))
+            .collect()
+    }
+
+    pub fn accept_program_visitor_mut<V: visitor::VisitorMut>(&mut self, visitor: &mut V) {
+        // the declarations are out of the program while it is visited, to be borrowed apart
+        let mut declarations = std::mem::take(&mut self.declarations);
+        visitor.visit_program(self, &mut declarations);
+        self.declarations = declarations;
+    }
+
+    pub fn accept_declaration_visitor_mut<V: visitor::VisitorMut>(
+        &mut self,
+        visitor: &mut V,
+        decl: &Declaration,
+    ) {
+        match *decl {
+            Declaration::FunctionDeclaration {
+                ref name,
+                ref parameters,
+                ref return_ty,
+                statement,
+                ..
+            } => visitor.visit_function_declaration(self, name, parameters, return_ty, statement),
+        }
+    }
+
+    /// Visits the statement `id` and replaces it with the statement returned by the visitor.
+    pub fn accept_statement_visitor_mut<V: visitor::VisitorMut>(
+        &mut self,
+        visitor: &mut V,
+        id: StatementId,
+    ) {
+        let stmt = match self.get_statement(id) {
+            Some(stmt) => stmt.clone(),
+            None => return,
+        };
+        let stmt = match stmt {
+            Statement::BlockStatement(stmts) => visitor.visit_block_statement(self, id, stmts),
+            Statement::LetStatement {
+                identifier,
+                identifier_span,
+                expression,
+            } => visitor.visit_let_statement(self, id, identifier, identifier_span, expression),
+            Statement::ReturnStatement(expression) => {
+                visitor.visit_return_statement(self, id, expression)
+            }
+            Statement::WhileStatement { condition, body } => {
+                visitor.visit_while_statement(self, id, condition, body)
+            }
+            Statement::IfStatement {
+                condition,
+                then_statement,
+                else_statement,
+            } => visitor.visit_if_statement(self, id, condition, then_statement, else_statement),
+            Statement::AssignStatement {
+                identifier,
+                identifier_span,
+                expression,
+            } => visitor.visit_assign_statement(self, id, identifier, identifier_span, expression),
+            Statement::ExpressionStatement(expr) => {
+                visitor.visit_expression_statement(self, id, expr)
+            }
+        };
+        self.statement_arena[id] = stmt;
+    }
+
+    /// Visits the expression `id` and replaces it with the expression returned by the visitor.
+    pub fn accept_expression_visitor_mut<V: visitor::VisitorMut>(
+        &mut self,
+        visitor: &mut V,
+        id: ExpressionId,
+    ) {
+        let expr = match self.get_expression(id) {
+            Some(expr) => expr.clone(),
+            None => return,
+        };
+        let expr = match expr {
+            Expression::BinOp { op, lhs, rhs } => {
+                visitor.visit_binop_expression(self, id, op, lhs, rhs)
+            }
+            Expression::FunctionCall { func, args } => {
+                visitor.visit_func_call(self, id, func, args)
+            }
+            Expression::Integer(i) => visitor.visit_integer(self, id, i),
+            Expression::Boolean(b) => visitor.visit_boolean(self, id, b),
+            Expression::Identifier(name) => visitor.visit_identifier(self, id, name),
+        };
+        self.expression_arena[id] = expr;
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -108,15 +373,37 @@ pub enum Declaration {
     FunctionDeclaration {
         name: String,
         parameters: Vec<(String, Type)>,
+        return_ty: Type,
         statement: StatementId,
+        /// The whole declaration, then its name and each parameter name.
+        span: Span,
+        name_span: Span,
+        parameter_spans: Vec<Span>,
     },
 }
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug, Clone)]
 pub enum Statement {
     BlockStatement(Vec<StatementId>),
     LetStatement {
         identifier: String,
+        identifier_span: Span,
+        expression: ExpressionId,
+    },
+    ReturnStatement(Option<ExpressionId>),
+    WhileStatement {
+        condition: ExpressionId,
+        body: StatementId,
+    },
+    IfStatement {
+        condition: ExpressionId,
+        then_statement: StatementId,
+        else_statement: Option<StatementId>,
+    },
+    AssignStatement {
+        identifier: String,
+        identifier_span: Span,
         expression: ExpressionId,
     },
     ExpressionStatement(ExpressionId),
@@ -134,20 +421,55 @@ pub enum Expression {
         args: Vec<ExpressionId>,
     },
     Integer(i32),
+    Boolean(bool),
     Identifier(String),
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BinOp {
     Add,
     Sub,
     Mul,
     Divide,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
 }
 
-#[derive(Debug, Clone)]
+impl fmt::Display for BinOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match *self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Divide => "/",
+            BinOp::Equal => "==",
+            BinOp::NotEqual => "!=",
+            BinOp::Less => "<",
+            BinOp::LessEqual => "<=",
+            BinOp::Greater => ">",
+            BinOp::GreaterEqual => ">=",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Type {
     Unit,
     Int,
     Boolean,
 }
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Type::Unit => write!(f, "()"),
+            Type::Int => write!(f, "int"),
+            Type::Boolean => write!(f, "bool"),
+        }
+    }
+}
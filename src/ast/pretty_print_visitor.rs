@@ -1,23 +1,40 @@
 use super::visitor::*;
 use super::*;
 
+use std::fmt::Write;
+
 #[derive(Debug, Clone)]
 pub struct PrettyPrintVisitor {
     current_tab: usize,
+    output: String,
 }
 
 macro_rules! tab_pr {
     ($s:expr, $fmt:expr) => {
-        println!(concat!("{}", $fmt), "  ".repeat($s.current_tab))
+        writeln!($s.output, concat!("{}", $fmt), "  ".repeat($s.current_tab)).unwrap()
     };
     ($s:expr, $fmt:expr, $($arg:tt)*) => {
-        println!(concat!("{}", $fmt), "  ".repeat($s.current_tab), $($arg)*)
+        writeln!($s.output, concat!("{}", $fmt), "  ".repeat($s.current_tab), $($arg)*).unwrap()
     };
 }
 
+impl Default for PrettyPrintVisitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl PrettyPrintVisitor {
     pub fn new() -> Self {
-        PrettyPrintVisitor { current_tab: 0 }
+        PrettyPrintVisitor {
+            current_tab: 0,
+            output: String::new(),
+        }
+    }
+
+    /// Returns the tree printed so far.
+    pub fn into_string(self) -> String {
+        self.output
     }
 }
 
@@ -66,12 +83,18 @@ impl Visitor for PrettyPrintVisitor {
         program: &Program,
         name: &str,
         parameters: &[(String, Type)],
+        return_ty: &Type,
         statement: StatementId,
     ) -> Option<()> {
-        tab_pr!(self, "function_def (name: {})", name);
+        tab_pr!(
+            self,
+            "function_def (name: {}, return: {:?})",
+            name,
+            return_ty
+        );
 
         tab_block!(self, {
-            for &(ref param, ref ty) in parameters {
+            for (param, ty) in parameters {
                 tab_pr!(self, "param ({}, {:?})", param, ty);
             }
 
@@ -96,6 +119,73 @@ impl Visitor for PrettyPrintVisitor {
         Some(())
     }
 
+    fn visit_return_statement(
+        &mut self,
+        program: &Program,
+        expression: Option<ExpressionId>,
+    ) -> Option<()> {
+        tab_pr!(self, "return_stmt:");
+
+        tab_block!(self, {
+            if let Some(expression) = expression {
+                program.accept_expression_visitor(self, expression);
+            }
+        });
+
+        Some(())
+    }
+
+    fn visit_while_statement(
+        &mut self,
+        program: &Program,
+        condition: ExpressionId,
+        body: StatementId,
+    ) -> Option<()> {
+        tab_pr!(self, "while_stmt:");
+
+        tab_block!(self, {
+            program.accept_expression_visitor(self, condition);
+            program.accept_statement_visitor(self, body);
+        });
+
+        Some(())
+    }
+
+    fn visit_if_statement(
+        &mut self,
+        program: &Program,
+        condition: ExpressionId,
+        then_statement: StatementId,
+        else_statement: Option<StatementId>,
+    ) -> Option<()> {
+        tab_pr!(self, "if_stmt:");
+
+        tab_block!(self, {
+            program.accept_expression_visitor(self, condition);
+            program.accept_statement_visitor(self, then_statement);
+            if let Some(else_statement) = else_statement {
+                program.accept_statement_visitor(self, else_statement);
+            }
+        });
+
+        Some(())
+    }
+
+    fn visit_assign_statement(
+        &mut self,
+        program: &Program,
+        identifier: &str,
+        expression: ExpressionId,
+    ) -> Option<()> {
+        tab_pr!(self, "assign_stmt (id: {}) :", identifier);
+
+        tab_block!(self, {
+            program.accept_expression_visitor(self, expression);
+        });
+
+        Some(())
+    }
+
     fn visit_expression_statement(
         &mut self,
         program: &Program,
@@ -151,6 +241,12 @@ impl Visitor for PrettyPrintVisitor {
         Some(())
     }
 
+    fn visit_boolean(&mut self, _program: &Program, b: bool) -> Option<()> {
+        tab_pr!(self, "boolean: {}", b);
+
+        Some(())
+    }
+
     fn visit_identifier(&mut self, _program: &Program, id: &str) -> Option<()> {
         tab_pr!(self, "identifier: {}", id);
 
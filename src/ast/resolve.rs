@@ -0,0 +1,385 @@
+//! Name resolution, linking every identifier of a program to its definition.
+//!
+//! Unlike the convertor, the resolution goes on through errors and keeps the spans of names, so
+//! it is meant for editor tooling. Unknown names are left unresolved, the convertor reports them.
+
+use super::visitor::*;
+use super::*;
+
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefinitionKind {
+    Function,
+    Parameter,
+    Variable,
+}
+
+#[derive(Debug, Clone)]
+pub struct Definition {
+    pub name: String,
+    pub kind: DefinitionKind,
+    /// The type of a parameter or variable, or the return type of a function. It is unknown for
+    /// variables initialized with an expression referring to unknown names.
+    pub ty: Option<Type>,
+    /// The parameters of a function.
+    pub parameters: Vec<(String, Type)>,
+    /// The name in the definition.
+    pub span: Span,
+    /// The code in which the definition can be referred to.
+    pub scope: Span,
+}
+
+impl Definition {
+    /// Returns the definition as it would be written in the source, like `let x: int`.
+    pub fn signature(&self) -> String {
+        match self.kind {
+            DefinitionKind::Function => {
+                let parameters: Vec<String> = self
+                    .parameters
+                    .iter()
+                    .map(|(name, ty)| format!("{}: {}", name, ty))
+                    .collect();
+                let return_ty = self.ty.unwrap_or(Type::Unit);
+                format!(
+                    "fn {}({}) -> {}",
+                    self.name,
+                    parameters.join(", "),
+                    return_ty
+                )
+            }
+            DefinitionKind::Parameter | DefinitionKind::Variable => {
+                let prefix = if self.kind == DefinitionKind::Variable {
+                    "let "
+                } else {
+                    ""
+                };
+                match self.ty {
+                    Some(ty) => format!("{}{}: {}", prefix, self.name, ty),
+                    None => format!("{}{}", prefix, self.name),
+                }
+            }
+        }
+    }
+}
+
+/// A use of a name, pointing to an index in [`Resolution::definitions`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reference {
+    pub span: Span,
+    pub definition: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Resolution {
+    pub definitions: Vec<Definition>,
+    pub references: Vec<Reference>,
+}
+
+impl Resolution {
+    pub fn new(program: &Program) -> Self {
+        let mut resolver = Resolver::default();
+        program.accept_program_visitor(&mut resolver);
+        resolver.resolution
+    }
+
+    /// Returns the definition whose name, or a reference to it, is at `offset`.
+    pub fn definition_at(&self, offset: usize) -> Option<&Definition> {
+        let referenced = self
+            .references
+            .iter()
+            .find(|reference| reference.span.contains(offset))
+            .map(|reference| &self.definitions[reference.definition]);
+        referenced.or_else(|| {
+            self.definitions
+                .iter()
+                .find(|definition| definition.span.contains(offset))
+        })
+    }
+
+    /// Returns the definitions that can be referred to at `offset`, without the shadowed ones.
+    pub fn visible_at(&self, offset: usize) -> Vec<&Definition> {
+        let mut visible: HashMap<&str, &Definition> = HashMap::new();
+        for definition in &self.definitions {
+            if !definition.scope.contains(offset) {
+                continue;
+            }
+            // the innermost definition starts last
+            let entry = visible.entry(&definition.name).or_insert(definition);
+            if entry.scope.start <= definition.scope.start {
+                *entry = definition;
+            }
+        }
+
+        let mut visible: Vec<&Definition> = visible.into_values().collect();
+        visible.sort_by_key(|definition| definition.span.start);
+        visible
+    }
+}
+
+#[derive(Debug, Default)]
+struct Resolver {
+    resolution: Resolution,
+    functions: HashMap<String, usize>,
+    scopes: Vec<HashMap<String, usize>>,
+    /// The statement and expression being resolved, and the innermost block around them.
+    statement: Option<StatementId>,
+    expression: Option<ExpressionId>,
+    block_span: Span,
+}
+
+impl Resolver {
+    fn define(&mut self, definition: Definition) -> usize {
+        let index = self.resolution.definitions.len();
+        self.resolution.definitions.push(definition);
+        index
+    }
+
+    fn lookup_variable(&self, name: &str) -> Option<usize> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).cloned())
+    }
+
+    fn resolve_statement(&mut self, program: &Program, statement: StatementId) {
+        let previous = self.statement.replace(statement);
+        program.accept_statement_visitor(self, statement);
+        self.statement = previous;
+    }
+
+    fn resolve_expression(&mut self, program: &Program, expression: ExpressionId) -> Option<Type> {
+        let previous = self.expression.replace(expression);
+        let ty = program.accept_expression_visitor(self, expression);
+        self.expression = previous;
+        ty
+    }
+}
+
+impl Visitor for Resolver {
+    type ProgramItem = ();
+    type DeclarationItem = ();
+    type StatementItem = ();
+    type ExpressionItem = Type;
+
+    fn visit_program(&mut self, program: &Program, declarations: &[Declaration]) -> Option<()> {
+        let whole_program = Span::new(0, usize::MAX);
+        for decl in declarations {
+            let Declaration::FunctionDeclaration {
+                ref name,
+                ref parameters,
+                return_ty,
+                name_span,
+                ..
+            } = *decl;
+            let index = self.define(Definition {
+                name: name.clone(),
+                kind: DefinitionKind::Function,
+                ty: Some(return_ty),
+                parameters: parameters.clone(),
+                span: name_span,
+                scope: whole_program,
+            });
+            self.functions.entry(name.clone()).or_insert(index);
+        }
+
+        for decl in declarations {
+            let Declaration::FunctionDeclaration {
+                ref parameters,
+                statement,
+                ref parameter_spans,
+                ..
+            } = *decl;
+
+            let body_span = program.statement_span(statement)?;
+            let mut scope = HashMap::new();
+            for ((name, ty), &span) in parameters.iter().zip(parameter_spans) {
+                let index = self.define(Definition {
+                    name: name.clone(),
+                    kind: DefinitionKind::Parameter,
+                    ty: Some(*ty),
+                    parameters: Vec::new(),
+                    span,
+                    scope: body_span,
+                });
+                scope.insert(name.clone(), index);
+            }
+
+            self.scopes.push(scope);
+            self.resolve_statement(program, statement);
+            self.scopes.pop();
+        }
+        Some(())
+    }
+
+    fn visit_block_statement(
+        &mut self,
+        program: &Program,
+        statements: &[StatementId],
+    ) -> Option<()> {
+        let previous = self.block_span;
+        self.block_span = program.statement_span(self.statement.unwrap())?;
+        self.scopes.push(HashMap::new());
+        for &s in statements {
+            self.resolve_statement(program, s);
+        }
+        self.scopes.pop();
+        self.block_span = previous;
+        Some(())
+    }
+
+    fn visit_let_statement(
+        &mut self,
+        program: &Program,
+        identifier: &str,
+        expression: ExpressionId,
+    ) -> Option<()> {
+        let ty = self.resolve_expression(program, expression);
+
+        let statement = self.statement.unwrap();
+        let identifier_span = match program.get_statement(statement) {
+            Some(Statement::LetStatement {
+                identifier_span, ..
+            }) => *identifier_span,
+            _ => unreachable!(),
+        };
+        // the variable is only visible after its initializer
+        let statement_span = program.statement_span(statement)?;
+        let index = self.define(Definition {
+            name: identifier.to_string(),
+            kind: DefinitionKind::Variable,
+            ty,
+            parameters: Vec::new(),
+            span: identifier_span,
+            scope: Span::new(statement_span.end, self.block_span.end),
+        });
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(identifier.to_string(), index);
+        Some(())
+    }
+
+    fn visit_return_statement(
+        &mut self,
+        program: &Program,
+        expression: Option<ExpressionId>,
+    ) -> Option<()> {
+        if let Some(expression) = expression {
+            self.resolve_expression(program, expression);
+        }
+        Some(())
+    }
+
+    fn visit_while_statement(
+        &mut self,
+        program: &Program,
+        condition: ExpressionId,
+        body: StatementId,
+    ) -> Option<()> {
+        self.resolve_expression(program, condition);
+        self.resolve_statement(program, body);
+        Some(())
+    }
+
+    fn visit_if_statement(
+        &mut self,
+        program: &Program,
+        condition: ExpressionId,
+        then_statement: StatementId,
+        else_statement: Option<StatementId>,
+    ) -> Option<()> {
+        self.resolve_expression(program, condition);
+        self.resolve_statement(program, then_statement);
+        if let Some(else_statement) = else_statement {
+            self.resolve_statement(program, else_statement);
+        }
+        Some(())
+    }
+
+    fn visit_assign_statement(
+        &mut self,
+        program: &Program,
+        identifier: &str,
+        expression: ExpressionId,
+    ) -> Option<()> {
+        self.resolve_expression(program, expression);
+
+        let identifier_span = match program.get_statement(self.statement.unwrap()) {
+            Some(Statement::AssignStatement {
+                identifier_span, ..
+            }) => *identifier_span,
+            _ => unreachable!(),
+        };
+        if let Some(definition) = self.lookup_variable(identifier) {
+            self.resolution.references.push(Reference {
+                span: identifier_span,
+                definition,
+            });
+        }
+        Some(())
+    }
+
+    fn visit_expression_statement(
+        &mut self,
+        program: &Program,
+        expression: ExpressionId,
+    ) -> Option<()> {
+        self.resolve_expression(program, expression);
+        Some(())
+    }
+
+    fn visit_binop_expression(
+        &mut self,
+        program: &Program,
+        op: BinOp,
+        lhs: ExpressionId,
+        rhs: ExpressionId,
+    ) -> Option<Type> {
+        self.resolve_expression(program, lhs);
+        self.resolve_expression(program, rhs);
+        match op {
+            BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Divide => Some(Type::Int),
+            _ => Some(Type::Boolean),
+        }
+    }
+
+    fn visit_func_call(
+        &mut self,
+        program: &Program,
+        func: ExpressionId,
+        args: &[ExpressionId],
+    ) -> Option<Type> {
+        for &arg in args {
+            self.resolve_expression(program, arg);
+        }
+
+        let name = match program.get_expression(func) {
+            Some(Expression::Identifier(name)) => name,
+            _ => return None,
+        };
+        let definition = *self.functions.get(name)?;
+        self.resolution.references.push(Reference {
+            span: program.expression_span(func)?,
+            definition,
+        });
+        self.resolution.definitions[definition].ty
+    }
+
+    fn visit_integer(&mut self, _program: &Program, _i: i32) -> Option<Type> {
+        Some(Type::Int)
+    }
+
+    fn visit_boolean(&mut self, _program: &Program, _b: bool) -> Option<Type> {
+        Some(Type::Boolean)
+    }
+
+    fn visit_identifier(&mut self, program: &Program, id: &str) -> Option<Type> {
+        let definition = self.lookup_variable(id)?;
+        self.resolution.references.push(Reference {
+            span: program.expression_span(self.expression.unwrap())?,
+            definition,
+        });
+        self.resolution.definitions[definition].ty
+    }
+}
@@ -1,5 +1,7 @@
 use super::*;
 
+use std::fmt;
+
 pub trait Visitor: Sized {
     type ProgramItem;
     type DeclarationItem;
@@ -22,6 +24,7 @@ pub trait Visitor: Sized {
         program: &Program,
         _name: &str,
         _parameters: &[(String, Type)],
+        _return_ty: &Type,
         statement: StatementId,
     ) -> Option<Self::DeclarationItem> {
         program.accept_statement_visitor(self, statement);
@@ -49,6 +52,53 @@ pub trait Visitor: Sized {
         None
     }
 
+    fn visit_return_statement(
+        &mut self,
+        program: &Program,
+        expression: Option<ExpressionId>,
+    ) -> Option<Self::StatementItem> {
+        if let Some(expression) = expression {
+            program.accept_expression_visitor(self, expression);
+        }
+        None
+    }
+
+    fn visit_while_statement(
+        &mut self,
+        program: &Program,
+        condition: ExpressionId,
+        body: StatementId,
+    ) -> Option<Self::StatementItem> {
+        program.accept_expression_visitor(self, condition);
+        program.accept_statement_visitor(self, body);
+        None
+    }
+
+    fn visit_if_statement(
+        &mut self,
+        program: &Program,
+        condition: ExpressionId,
+        then_statement: StatementId,
+        else_statement: Option<StatementId>,
+    ) -> Option<Self::StatementItem> {
+        program.accept_expression_visitor(self, condition);
+        program.accept_statement_visitor(self, then_statement);
+        if let Some(else_statement) = else_statement {
+            program.accept_statement_visitor(self, else_statement);
+        }
+        None
+    }
+
+    fn visit_assign_statement(
+        &mut self,
+        program: &Program,
+        _identifier: &str,
+        expression: ExpressionId,
+    ) -> Option<Self::StatementItem> {
+        program.accept_expression_visitor(self, expression);
+        None
+    }
+
     fn visit_expression_statement(
         &mut self,
         program: &Program,
@@ -88,8 +138,586 @@ pub trait Visitor: Sized {
         None
     }
 
+    fn visit_boolean(&mut self, _program: &Program, _b: bool) -> Option<Self::ExpressionItem> {
+        // do nothing
+        None
+    }
+
     fn visit_identifier(&mut self, _program: &Program, _id: &str) -> Option<Self::ExpressionItem> {
         // do nothing
         None
     }
 }
+
+/// An id given to the `try_accept_*` methods of `Program` which isn't one of the program's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnknownId {
+    Statement(StatementId),
+    Expression(ExpressionId),
+}
+
+impl fmt::Display for UnknownId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            UnknownId::Statement(id) => write!(f, "unknown statement {}", id.index()),
+            UnknownId::Expression(id) => write!(f, "unknown expression {}", id.index()),
+        }
+    }
+}
+
+/// A visitor that can fail: each method returns its item or an error, which the `try_accept_*`
+/// methods of `Program` pass on so that a pass can stop with `?`. Unlike with `Visitor`, a
+/// missing item can't be mistaken for an error.
+///
+/// There are no default methods, as there would be no item to return: a visitor says what it
+/// does with every kind of node, and visits the children itself.
+pub trait TryVisitor: Sized {
+    type ProgramItem;
+    type DeclarationItem;
+    type StatementItem;
+    type ExpressionItem;
+    type Error;
+
+    /// Returns the error for an id which isn't one of the program's.
+    fn unknown_id(&mut self, program: &Program, id: UnknownId) -> Self::Error;
+
+    fn visit_program(
+        &mut self,
+        program: &Program,
+        declarations: &[Declaration],
+    ) -> Result<Self::ProgramItem, Self::Error>;
+
+    fn visit_function_declaration(
+        &mut self,
+        program: &Program,
+        name: &str,
+        parameters: &[(String, Type)],
+        return_ty: &Type,
+        statement: StatementId,
+    ) -> Result<Self::DeclarationItem, Self::Error>;
+
+    fn visit_block_statement(
+        &mut self,
+        program: &Program,
+        statements: &[StatementId],
+    ) -> Result<Self::StatementItem, Self::Error>;
+
+    fn visit_let_statement(
+        &mut self,
+        program: &Program,
+        identifier: &str,
+        expression: ExpressionId,
+    ) -> Result<Self::StatementItem, Self::Error>;
+
+    fn visit_return_statement(
+        &mut self,
+        program: &Program,
+        expression: Option<ExpressionId>,
+    ) -> Result<Self::StatementItem, Self::Error>;
+
+    fn visit_while_statement(
+        &mut self,
+        program: &Program,
+        condition: ExpressionId,
+        body: StatementId,
+    ) -> Result<Self::StatementItem, Self::Error>;
+
+    fn visit_if_statement(
+        &mut self,
+        program: &Program,
+        condition: ExpressionId,
+        then_statement: StatementId,
+        else_statement: Option<StatementId>,
+    ) -> Result<Self::StatementItem, Self::Error>;
+
+    fn visit_assign_statement(
+        &mut self,
+        program: &Program,
+        identifier: &str,
+        expression: ExpressionId,
+    ) -> Result<Self::StatementItem, Self::Error>;
+
+    fn visit_expression_statement(
+        &mut self,
+        program: &Program,
+        expression: ExpressionId,
+    ) -> Result<Self::StatementItem, Self::Error>;
+
+    fn visit_binop_expression(
+        &mut self,
+        program: &Program,
+        op: BinOp,
+        lhs: ExpressionId,
+        rhs: ExpressionId,
+    ) -> Result<Self::ExpressionItem, Self::Error>;
+
+    fn visit_func_call(
+        &mut self,
+        program: &Program,
+        func: ExpressionId,
+        args: &[ExpressionId],
+    ) -> Result<Self::ExpressionItem, Self::Error>;
+
+    fn visit_integer(
+        &mut self,
+        program: &Program,
+        i: i32,
+    ) -> Result<Self::ExpressionItem, Self::Error>;
+
+    fn visit_boolean(
+        &mut self,
+        program: &Program,
+        b: bool,
+    ) -> Result<Self::ExpressionItem, Self::Error>;
+
+    fn visit_identifier(
+        &mut self,
+        program: &Program,
+        id: &str,
+    ) -> Result<Self::ExpressionItem, Self::Error>;
+}
+
+/// A visitor rewriting the program, a folder: each statement and expression visited is replaced
+/// with the one returned by its method, in place in the arenas so that the ids stay valid. The
+/// methods get the id of the node, to read its span or to create new nodes with
+/// `Program::create_statement` and `Program::create_expression`.
+///
+/// By default the children are visited first and the node is rebuilt as it was, so a method only
+/// has to handle the nodes it rewrites, and sees children that were already rewritten.
+pub trait VisitorMut: Sized {
+    fn visit_program(&mut self, program: &mut Program, declarations: &mut Vec<Declaration>) {
+        for decl in declarations.iter() {
+            program.accept_declaration_visitor_mut(self, decl);
+        }
+    }
+
+    fn visit_function_declaration(
+        &mut self,
+        program: &mut Program,
+        _name: &str,
+        _parameters: &[(String, Type)],
+        _return_ty: &Type,
+        statement: StatementId,
+    ) {
+        program.accept_statement_visitor_mut(self, statement);
+    }
+
+    fn visit_block_statement(
+        &mut self,
+        program: &mut Program,
+        _id: StatementId,
+        statements: Vec<StatementId>,
+    ) -> Statement {
+        for &s in &statements {
+            program.accept_statement_visitor_mut(self, s);
+        }
+        Statement::BlockStatement(statements)
+    }
+
+    fn visit_let_statement(
+        &mut self,
+        program: &mut Program,
+        _id: StatementId,
+        identifier: String,
+        identifier_span: Span,
+        expression: ExpressionId,
+    ) -> Statement {
+        program.accept_expression_visitor_mut(self, expression);
+        Statement::LetStatement {
+            identifier,
+            identifier_span,
+            expression,
+        }
+    }
+
+    fn visit_return_statement(
+        &mut self,
+        program: &mut Program,
+        _id: StatementId,
+        expression: Option<ExpressionId>,
+    ) -> Statement {
+        if let Some(expression) = expression {
+            program.accept_expression_visitor_mut(self, expression);
+        }
+        Statement::ReturnStatement(expression)
+    }
+
+    fn visit_while_statement(
+        &mut self,
+        program: &mut Program,
+        _id: StatementId,
+        condition: ExpressionId,
+        body: StatementId,
+    ) -> Statement {
+        program.accept_expression_visitor_mut(self, condition);
+        program.accept_statement_visitor_mut(self, body);
+        Statement::WhileStatement { condition, body }
+    }
+
+    fn visit_if_statement(
+        &mut self,
+        program: &mut Program,
+        _id: StatementId,
+        condition: ExpressionId,
+        then_statement: StatementId,
+        else_statement: Option<StatementId>,
+    ) -> Statement {
+        program.accept_expression_visitor_mut(self, condition);
+        program.accept_statement_visitor_mut(self, then_statement);
+        if let Some(else_statement) = else_statement {
+            program.accept_statement_visitor_mut(self, else_statement);
+        }
+        Statement::IfStatement {
+            condition,
+            then_statement,
+            else_statement,
+        }
+    }
+
+    fn visit_assign_statement(
+        &mut self,
+        program: &mut Program,
+        _id: StatementId,
+        identifier: String,
+        identifier_span: Span,
+        expression: ExpressionId,
+    ) -> Statement {
+        program.accept_expression_visitor_mut(self, expression);
+        Statement::AssignStatement {
+            identifier,
+            identifier_span,
+            expression,
+        }
+    }
+
+    fn visit_expression_statement(
+        &mut self,
+        program: &mut Program,
+        _id: StatementId,
+        expression: ExpressionId,
+    ) -> Statement {
+        program.accept_expression_visitor_mut(self, expression);
+        Statement::ExpressionStatement(expression)
+    }
+
+    fn visit_binop_expression(
+        &mut self,
+        program: &mut Program,
+        _id: ExpressionId,
+        op: BinOp,
+        lhs: ExpressionId,
+        rhs: ExpressionId,
+    ) -> Expression {
+        program.accept_expression_visitor_mut(self, lhs);
+        program.accept_expression_visitor_mut(self, rhs);
+        Expression::BinOp { op, lhs, rhs }
+    }
+
+    fn visit_func_call(
+        &mut self,
+        program: &mut Program,
+        _id: ExpressionId,
+        func: ExpressionId,
+        args: Vec<ExpressionId>,
+    ) -> Expression {
+        program.accept_expression_visitor_mut(self, func);
+        for &e in &args {
+            program.accept_expression_visitor_mut(self, e);
+        }
+        Expression::FunctionCall { func, args }
+    }
+
+    fn visit_integer(&mut self, _program: &mut Program, _id: ExpressionId, i: i32) -> Expression {
+        Expression::Integer(i)
+    }
+
+    fn visit_boolean(&mut self, _program: &mut Program, _id: ExpressionId, b: bool) -> Expression {
+        Expression::Boolean(b)
+    }
+
+    fn visit_identifier(
+        &mut self,
+        _program: &mut Program,
+        _id: ExpressionId,
+        name: String,
+    ) -> Expression {
+        Expression::Identifier(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Folds the operations on integer literals.
+    struct ConstantFolder;
+
+    impl VisitorMut for ConstantFolder {
+        fn visit_binop_expression(
+            &mut self,
+            program: &mut Program,
+            _id: ExpressionId,
+            op: BinOp,
+            lhs: ExpressionId,
+            rhs: ExpressionId,
+        ) -> Expression {
+            program.accept_expression_visitor_mut(self, lhs);
+            program.accept_expression_visitor_mut(self, rhs);
+            let value = match (program.get_expression(lhs), program.get_expression(rhs)) {
+                (Some(&Expression::Integer(lhs)), Some(&Expression::Integer(rhs))) => match op {
+                    BinOp::Add => Some(lhs.wrapping_add(rhs)),
+                    BinOp::Sub => Some(lhs.wrapping_sub(rhs)),
+                    BinOp::Mul => Some(lhs.wrapping_mul(rhs)),
+                    _ => None,
+                },
+                _ => None,
+            };
+            match value {
+                Some(value) => Expression::Integer(value),
+                None => Expression::BinOp { op, lhs, rhs },
+            }
+        }
+    }
+
+    /// Gives every `if` an `else`, with a new empty block.
+    struct ElseAdder;
+
+    impl VisitorMut for ElseAdder {
+        fn visit_if_statement(
+            &mut self,
+            program: &mut Program,
+            id: StatementId,
+            condition: ExpressionId,
+            then_statement: StatementId,
+            else_statement: Option<StatementId>,
+        ) -> Statement {
+            program.accept_statement_visitor_mut(self, then_statement);
+            let else_statement = else_statement.unwrap_or_else(|| {
+                let span = program.statement_span(id).unwrap();
+                let span = Span::new(span.end, span.end);
+                program.create_statement(Statement::BlockStatement(Vec::new()), span)
+            });
+            program.accept_statement_visitor_mut(self, else_statement);
+            Statement::IfStatement {
+                condition,
+                then_statement,
+                else_statement: Some(else_statement),
+            }
+        }
+    }
+
+    /// Evaluates the returned expressions, which must be made of integer literals.
+    #[derive(Default)]
+    struct Evaluator {
+        values: Vec<i32>,
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct EvaluationError(String);
+
+    impl TryVisitor for Evaluator {
+        type ProgramItem = ();
+        type DeclarationItem = ();
+        type StatementItem = ();
+        type ExpressionItem = i32;
+        type Error = EvaluationError;
+
+        fn unknown_id(&mut self, _program: &Program, id: UnknownId) -> EvaluationError {
+            EvaluationError(id.to_string())
+        }
+
+        fn visit_program(
+            &mut self,
+            program: &Program,
+            declarations: &[Declaration],
+        ) -> Result<(), EvaluationError> {
+            for decl in declarations {
+                program.try_accept_declaration_visitor(self, decl)?;
+            }
+            Ok(())
+        }
+
+        fn visit_function_declaration(
+            &mut self,
+            program: &Program,
+            _name: &str,
+            _parameters: &[(String, Type)],
+            _return_ty: &Type,
+            statement: StatementId,
+        ) -> Result<(), EvaluationError> {
+            program.try_accept_statement_visitor(self, statement)
+        }
+
+        fn visit_block_statement(
+            &mut self,
+            program: &Program,
+            statements: &[StatementId],
+        ) -> Result<(), EvaluationError> {
+            program.try_accept_statement_visitors(self, statements)?;
+            Ok(())
+        }
+
+        fn visit_let_statement(
+            &mut self,
+            program: &Program,
+            _identifier: &str,
+            expression: ExpressionId,
+        ) -> Result<(), EvaluationError> {
+            program.try_accept_expression_visitor(self, expression)?;
+            Ok(())
+        }
+
+        fn visit_return_statement(
+            &mut self,
+            program: &Program,
+            expression: Option<ExpressionId>,
+        ) -> Result<(), EvaluationError> {
+            if let Some(expression) = expression {
+                let value = program.try_accept_expression_visitor(self, expression)?;
+                self.values.push(value);
+            }
+            Ok(())
+        }
+
+        fn visit_while_statement(
+            &mut self,
+            _program: &Program,
+            _condition: ExpressionId,
+            _body: StatementId,
+        ) -> Result<(), EvaluationError> {
+            Err(EvaluationError("loops aren't evaluated".to_string()))
+        }
+
+        fn visit_if_statement(
+            &mut self,
+            _program: &Program,
+            _condition: ExpressionId,
+            _then_statement: StatementId,
+            _else_statement: Option<StatementId>,
+        ) -> Result<(), EvaluationError> {
+            Err(EvaluationError("conditions aren't evaluated".to_string()))
+        }
+
+        fn visit_assign_statement(
+            &mut self,
+            program: &Program,
+            _identifier: &str,
+            expression: ExpressionId,
+        ) -> Result<(), EvaluationError> {
+            program.try_accept_expression_visitor(self, expression)?;
+            Ok(())
+        }
+
+        fn visit_expression_statement(
+            &mut self,
+            program: &Program,
+            expression: ExpressionId,
+        ) -> Result<(), EvaluationError> {
+            program.try_accept_expression_visitor(self, expression)?;
+            Ok(())
+        }
+
+        fn visit_binop_expression(
+            &mut self,
+            program: &Program,
+            op: BinOp,
+            lhs: ExpressionId,
+            rhs: ExpressionId,
+        ) -> Result<i32, EvaluationError> {
+            let lhs = program.try_accept_expression_visitor(self, lhs)?;
+            let rhs = program.try_accept_expression_visitor(self, rhs)?;
+            let value = match op {
+                BinOp::Add => Ok(lhs + rhs),
+                BinOp::Mul => Ok(lhs * rhs),
+                BinOp::Sub => Ok(lhs - rhs),
+                BinOp::Divide => lhs
+                    .checked_div(rhs)
+                    .ok_or_else(|| format!("division of {} by zero", lhs)),
+                _ => Err(format!("`{}` isn't an integer operation", op)),
+            };
+            value.map_err(EvaluationError)
+        }
+
+        fn visit_func_call(
+            &mut self,
+            _program: &Program,
+            _func: ExpressionId,
+            _args: &[ExpressionId],
+        ) -> Result<i32, EvaluationError> {
+            Err(EvaluationError("calls aren't evaluated".to_string()))
+        }
+
+        fn visit_integer(&mut self, _program: &Program, i: i32) -> Result<i32, EvaluationError> {
+            Ok(i)
+        }
+
+        fn visit_boolean(&mut self, _program: &Program, b: bool) -> Result<i32, EvaluationError> {
+            Err(EvaluationError(format!("`{}` isn't an integer", b)))
+        }
+
+        fn visit_identifier(
+            &mut self,
+            _program: &Program,
+            id: &str,
+        ) -> Result<i32, EvaluationError> {
+            Err(EvaluationError(format!("`{}` isn't a constant", id)))
+        }
+    }
+
+    fn evaluate(source: &str) -> Result<Vec<i32>, String> {
+        let mut evaluator = Evaluator::default();
+        crate::parse(source)
+            .unwrap()
+            .try_accept_program_visitor(&mut evaluator)
+            .map_err(|EvaluationError(message)| message)?;
+        Ok(evaluator.values)
+    }
+
+    fn print(program: &Program) -> String {
+        let mut visitor = crate::ast::pretty_print_visitor::PrettyPrintVisitor::new();
+        program.accept_program_visitor(&mut visitor);
+        visitor.into_string()
+    }
+
+    #[test]
+    fn expressions_are_rewritten_in_place() {
+        let mut program = crate::parse("fn main() -> int { return (1 + 2) * x - 3 * 4; }").unwrap();
+        program.accept_program_visitor_mut(&mut ConstantFolder);
+        let folded = crate::parse("fn main() -> int { return 3 * x - 12; }").unwrap();
+        assert_eq!(print(&program), print(&folded));
+    }
+
+    #[test]
+    fn statements_can_be_added() {
+        let source = "fn main() { if true { if false {} } }";
+        let mut program = crate::parse(source).unwrap();
+        program.accept_program_visitor_mut(&mut ElseAdder);
+        let desugared = crate::parse("fn main() { if true { if false {} else {} } else {} }");
+        assert_eq!(print(&program), print(&desugared.unwrap()));
+        assert!(crate::lower(&program).is_ok());
+    }
+
+    #[test]
+    fn errors_stop_the_visit() {
+        let source = "fn f() -> int { return 1 + 2 * 3; }\nfn g() -> int { return 10 / 3; }";
+        assert_eq!(evaluate(source), Ok(vec![7, 3]));
+        let source = "fn f(x: int) -> int { return 4 / (x - x); }";
+        assert_eq!(evaluate(source), Err("`x` isn't a constant".to_string()));
+        let source = "fn f() -> int { return 4 / (2 - 2); }\nfn g() -> int { return x; }";
+        assert_eq!(evaluate(source), Err("division of 4 by zero".to_string()));
+    }
+
+    #[test]
+    fn unknown_ids_are_errors() {
+        let program = crate::parse("fn f() -> int { return 1; }").unwrap();
+        let other = crate::parse("fn g() -> int { return 2; }").unwrap();
+        let Declaration::FunctionDeclaration { statement, .. } = other.declarations[0];
+        assert_eq!(
+            program.try_accept_statement_visitor(&mut Evaluator::default(), statement),
+            Err(EvaluationError(format!(
+                "unknown statement {}",
+                statement.index()
+            )))
+        );
+    }
+}
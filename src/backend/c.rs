@@ -0,0 +1,272 @@
+//! C99 backend, translating each function to a C function whose basic blocks are labels.
+//!
+//! Every hai function `f` becomes the C function `hai_fn_f`, apart from the `hai_` helpers of the
+//! runtime, and a C `main` calling the hai `main` is added when it has no parameters. Integer arithmetic goes through `uint32_t` to keep the
+//! wrapping semantics of the IR. Divisions go through `hai_div`, which raises `SIGFPE` on a zero
+//! divisor and on `INT32_MIN / -1` like the x86-64 backend, where C leaves them undefined.
+
+use crate::ir::*;
+
+use std::fmt::Write;
+
+const DIV_HELPER: &str = "\
+static inline int32_t hai_div(int32_t lhs, int32_t rhs) {
+    if (rhs == 0 || (lhs == INT32_MIN && rhs == -1)) {
+        raise(SIGFPE);
+        abort();
+    }
+    return lhs / rhs;
+}
+";
+
+pub fn emit_module(module: &Module) -> String {
+    let mut output = String::new();
+    writeln!(output, "#include <signal.h>").unwrap();
+    writeln!(output, "#include <stdbool.h>").unwrap();
+    writeln!(output, "#include <stdint.h>").unwrap();
+    writeln!(output, "#include <stdlib.h>").unwrap();
+    writeln!(output).unwrap();
+    writeln!(output, "typedef uint8_t hai_unit;").unwrap();
+    writeln!(output).unwrap();
+    output.push_str(DIV_HELPER);
+    writeln!(output).unwrap();
+
+    for function in &module.functions {
+        writeln!(output, "{};", prototype(function)).unwrap();
+    }
+
+    for function in &module.functions {
+        writeln!(output).unwrap();
+        emit_function(module, function, &mut output);
+    }
+
+    let has_entry_point = module
+        .functions
+        .iter()
+        .any(|function| function.name() == "main" && function.params().is_empty());
+    if has_entry_point {
+        writeln!(output).unwrap();
+        writeln!(output, "int main(void) {{").unwrap();
+        writeln!(output, "    return (int){}();", function_name("main")).unwrap();
+        writeln!(output, "}}").unwrap();
+    }
+    output
+}
+
+fn prototype(function: &Function) -> String {
+    let params: Vec<String> = function
+        .params()
+        .iter()
+        .enumerate()
+        .map(|(index, &ty)| format!("{} p{}", c_type(ty), index))
+        .collect();
+    let params = if params.is_empty() {
+        "void".to_string()
+    } else {
+        params.join(", ")
+    };
+    format!(
+        "{} {}({})",
+        c_type(function.return_ty()),
+        function_name(function.name()),
+        params
+    )
+}
+
+/// Returns the C name of the hai function `name`.
+fn function_name(name: &str) -> String {
+    format!("hai_fn_{}", name)
+}
+
+fn emit_function(module: &Module, function: &Function, output: &mut String) {
+    writeln!(output, "{} {{", prototype(function)).unwrap();
+
+    // declarations come first, so that the gotos never jump over one
+    for (index, ty) in value_types(module, function).into_iter().enumerate() {
+        if let Some(ty) = ty {
+            writeln!(output, "    {} v{};", c_type(ty), index).unwrap();
+        }
+    }
+    for (index, &ty) in function.variables().iter().enumerate() {
+        writeln!(output, "    {} var{} = 0;", c_type(ty), index).unwrap();
+    }
+
+    // only the blocks which are jumped to get a label, to avoid warnings about unused ones
+    let mut targets = vec![false; function.basic_blocks().len()];
+    for bb in function.basic_blocks() {
+        for succ in bb.terminator().successors() {
+            targets[succ.0] = true;
+        }
+    }
+
+    for (index, bb) in function.basic_blocks().iter().enumerate() {
+        if targets[index] {
+            writeln!(output, "{}:", BB(index)).unwrap();
+        }
+        for statement in bb.statements() {
+            match *statement {
+                Statement::WithDestination(value, ref operation) => {
+                    let expression = expression(module, operation);
+                    writeln!(output, "    v{} = {};", value.index(), expression).unwrap();
+                }
+                Statement::Operation(ref operation) => {
+                    writeln!(output, "    {};", expression(module, operation)).unwrap();
+                }
+            }
+        }
+
+        match *bb.terminator() {
+            Terminator::Return(value) => writeln!(output, "    return v{};", value.index()),
+            Terminator::Jump(target) => writeln!(output, "    goto {};", target),
+            Terminator::Branch(cond, then_bb, else_bb) => writeln!(
+                output,
+                "    if (v{}) goto {}; else goto {};",
+                cond.index(),
+                then_bb,
+                else_bb
+            ),
+            Terminator::Unreachable => writeln!(output, "    abort();"),
+        }
+        .unwrap();
+    }
+
+    writeln!(output, "}}").unwrap();
+}
+
+fn expression(module: &Module, operation: &Operation) -> String {
+    let wrapping = |op: &str, lhs: Value, rhs: Value| {
+        format!(
+            "(int32_t)((uint32_t)v{} {} (uint32_t)v{})",
+            lhs.index(),
+            op,
+            rhs.index()
+        )
+    };
+
+    match *operation {
+        Operation::Literal(ref literal) => match *literal {
+            Literal::Unit => "0".to_string(),
+            Literal::Int(i) if i == i32::MIN => "INT32_MIN".to_string(),
+            Literal::Int(i) => i.to_string(),
+            Literal::Boolean(b) => b.to_string(),
+        },
+        Operation::BinaryAdd(lhs, rhs) => wrapping("+", lhs, rhs),
+        Operation::BinarySub(lhs, rhs) => wrapping("-", lhs, rhs),
+        Operation::BinaryMul(lhs, rhs) => wrapping("*", lhs, rhs),
+        Operation::BinaryDivide(lhs, rhs) => {
+            format!("hai_div(v{}, v{})", lhs.index(), rhs.index())
+        }
+        Operation::BinaryShiftLeft(lhs, rhs) => format!(
+            "(int32_t)((uint32_t)v{} << (v{} & 31))",
+            lhs.index(),
+            rhs.index()
+        ),
+        Operation::Negate(value) => format!("(int32_t)(0u - (uint32_t)v{})", value.index()),
+        Operation::Compare(comparison, lhs, rhs) => {
+            let op = match comparison {
+                Comparison::Equal => "==",
+                Comparison::NotEqual => "!=",
+                Comparison::Less => "<",
+                Comparison::LessEqual => "<=",
+                Comparison::Greater => ">",
+                Comparison::GreaterEqual => ">=",
+            };
+            format!("v{} {} v{}", lhs.index(), op, rhs.index())
+        }
+        Operation::LoadParam(index) => format!("p{}", index),
+        Operation::LoadVariable(var) => format!("var{}", var.index()),
+        Operation::StoreVariable(var, value) => format!("var{} = v{}", var.index(), value.index()),
+        Operation::Call { callee, ref args } => {
+            let args: Vec<String> = args.iter().map(|arg| format!("v{}", arg.index())).collect();
+            format!(
+                "{}({})",
+                function_name(module.functions[callee].name()),
+                args.join(", ")
+            )
+        }
+    }
+}
+
+/// Returns the type of each value defined in `function`, indexed by value.
+fn value_types(module: &Module, function: &Function) -> Vec<Option<Type>> {
+    let mut types = vec![None; function.value_count()];
+    for bb in function.basic_blocks() {
+        for statement in bb.statements() {
+            if let Statement::WithDestination(value, ref operation) = *statement {
+                let ty = match *operation {
+                    Operation::Literal(Literal::Unit) => Type::Unit,
+                    Operation::Literal(Literal::Int(_))
+                    | Operation::BinaryAdd(..)
+                    | Operation::BinarySub(..)
+                    | Operation::BinaryMul(..)
+                    | Operation::BinaryDivide(..)
+                    | Operation::BinaryShiftLeft(..)
+                    | Operation::Negate(_) => Type::Int,
+                    Operation::Literal(Literal::Boolean(_)) | Operation::Compare(..) => {
+                        Type::Boolean
+                    }
+                    Operation::LoadParam(index) => function.params()[index],
+                    Operation::LoadVariable(var) => function.variables()[var.index()],
+                    Operation::StoreVariable(..) => Type::Unit,
+                    Operation::Call { callee, .. } => module.functions[callee].return_ty(),
+                };
+                types[value.index()] = Some(ty);
+            }
+        }
+    }
+    types
+}
+
+fn c_type(ty: Type) -> &'static str {
+    match ty {
+        Type::Unit => "hai_unit",
+        Type::Int => "int32_t",
+        Type::Boolean => "bool",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::process::Command;
+
+    /// Compiles the C code of `source` with the system C compiler, and returns the exit status
+    /// of the program.
+    fn run(source: &str) -> Option<i32> {
+        let module = crate::compile(source, 0).unwrap();
+        let dir = std::env::temp_dir().join(format!("hai-c-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let c_path = dir.join("main.c");
+        let executable = dir.join("main");
+        std::fs::write(&c_path, emit_module(&module)).unwrap();
+
+        let status = Command::new("cc")
+            .args(["-std=c99", "-o"])
+            .arg(&executable)
+            .arg(&c_path)
+            .status()
+            .unwrap();
+        assert!(status.success(), "the C code doesn't compile");
+        let code = Command::new(&executable).status().unwrap().code();
+        let _ = std::fs::remove_dir_all(&dir);
+        code
+    }
+
+    #[test]
+    fn test_functions_named_like_helpers() {
+        let source = "fn div(a: int, b: int) -> int {
+    return a - b;
+}
+
+fn unit() {
+}
+
+fn main() -> int {
+    unit();
+    return div(7, 2) + 7 / 2;
+}
+";
+        assert_eq!(run(source), Some(8));
+    }
+}
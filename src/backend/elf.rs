@@ -0,0 +1,365 @@
+//! Writer of ELF64 relocatable object files for x86-64, with a single `.text` section.
+
+/// Relocation to a 32-bit PC-relative address, through the PLT if needed.
+pub const R_X86_64_PLT32: u32 = 4;
+
+/// A global function defined in `.text`.
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub offset: usize,
+    pub size: usize,
+}
+
+/// A relocation in `.text`. Symbols which are not defined in the object are added as undefined
+/// symbols, to be resolved by the linker.
+#[derive(Debug, Clone)]
+pub struct Relocation {
+    pub offset: usize,
+    pub symbol: String,
+    pub kind: u32,
+    pub addend: i64,
+}
+
+const HEADER_SIZE: usize = 64;
+const SECTION_HEADER_SIZE: usize = 64;
+const SYMBOL_SIZE: usize = 24;
+const RELOCATION_SIZE: usize = 24;
+
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHT_RELA: u32 = 4;
+const SHF_ALLOC: u64 = 0x2;
+const SHF_EXECINSTR: u64 = 0x4;
+const SHF_INFO_LINK: u64 = 0x40;
+
+// section indices
+const TEXT: u16 = 1;
+const SYMTAB: u32 = 2;
+const STRTAB: u32 = 3;
+const SHSTRTAB: u16 = 6;
+const SECTION_COUNT: u16 = 7;
+
+pub fn write_relocatable(text: &[u8], symbols: &[Symbol], relocations: &[Relocation]) -> Vec<u8> {
+    let mut strtab = vec![0];
+    let mut symtab = vec![0; SYMBOL_SIZE];
+    let mut symbol_indices: Vec<&str> = Vec::new();
+
+    let mut add_symbol = |name: &str, section: u16, offset: usize, size: usize| {
+        let name_offset = strtab.len() as u32;
+        strtab.extend_from_slice(name.as_bytes());
+        strtab.push(0);
+
+        // global binding, function type for defined symbols
+        let info = if section == 0 { 0x10 } else { 0x12 };
+        symtab.extend_from_slice(&name_offset.to_le_bytes());
+        symtab.push(info);
+        symtab.push(0);
+        symtab.extend_from_slice(&section.to_le_bytes());
+        symtab.extend_from_slice(&(offset as u64).to_le_bytes());
+        symtab.extend_from_slice(&(size as u64).to_le_bytes());
+    };
+
+    for symbol in symbols {
+        add_symbol(&symbol.name, TEXT, symbol.offset, symbol.size);
+        symbol_indices.push(&symbol.name);
+    }
+    for relocation in relocations {
+        if !symbol_indices.contains(&relocation.symbol.as_str()) {
+            add_symbol(&relocation.symbol, 0, 0, 0);
+            symbol_indices.push(&relocation.symbol);
+        }
+    }
+
+    let mut rela = Vec::with_capacity(relocations.len() * RELOCATION_SIZE);
+    for relocation in relocations {
+        let index = symbol_indices
+            .iter()
+            .position(|&name| name == relocation.symbol)
+            .unwrap()
+            + 1;
+        rela.extend_from_slice(&(relocation.offset as u64).to_le_bytes());
+        rela.extend_from_slice(
+            &(((index as u64) << 32) | u64::from(relocation.kind)).to_le_bytes(),
+        );
+        rela.extend_from_slice(&relocation.addend.to_le_bytes());
+    }
+
+    let mut shstrtab = vec![0];
+    let mut section_name = |name: &str| {
+        let offset = shstrtab.len() as u32;
+        shstrtab.extend_from_slice(name.as_bytes());
+        shstrtab.push(0);
+        offset
+    };
+    let names = [
+        section_name(".text"),
+        section_name(".symtab"),
+        section_name(".strtab"),
+        section_name(".rela.text"),
+        section_name(".note.GNU-stack"),
+        section_name(".shstrtab"),
+    ];
+
+    // the section contents follow the header, each aligned as required
+    let mut output = vec![0; HEADER_SIZE];
+    let place = |output: &mut Vec<u8>, content: &[u8], align: usize| {
+        while !output.len().is_multiple_of(align) {
+            output.push(0);
+        }
+        let offset = output.len();
+        output.extend_from_slice(content);
+        offset
+    };
+    let text_offset = place(&mut output, text, 16);
+    let symtab_offset = place(&mut output, &symtab, 8);
+    let strtab_offset = place(&mut output, &strtab, 1);
+    let rela_offset = place(&mut output, &rela, 8);
+    let note_offset = output.len();
+    let shstrtab_offset = place(&mut output, &shstrtab, 1);
+    let section_headers_offset = place(&mut output, &[], 8);
+
+    write_header(&mut output, section_headers_offset);
+
+    output.extend_from_slice(&[0; SECTION_HEADER_SIZE]);
+    let sections = [
+        SectionHeader {
+            name: names[0],
+            kind: SHT_PROGBITS,
+            flags: SHF_ALLOC | SHF_EXECINSTR,
+            offset: text_offset,
+            size: text.len(),
+            link: 0,
+            info: 0,
+            align: 16,
+            entry_size: 0,
+        },
+        SectionHeader {
+            name: names[1],
+            kind: SHT_SYMTAB,
+            flags: 0,
+            offset: symtab_offset,
+            size: symtab.len(),
+            link: STRTAB,
+            // index of the first global symbol, all of them are
+            info: 1,
+            align: 8,
+            entry_size: SYMBOL_SIZE,
+        },
+        SectionHeader {
+            name: names[2],
+            kind: SHT_STRTAB,
+            flags: 0,
+            offset: strtab_offset,
+            size: strtab.len(),
+            link: 0,
+            info: 0,
+            align: 1,
+            entry_size: 0,
+        },
+        SectionHeader {
+            name: names[3],
+            kind: SHT_RELA,
+            flags: SHF_INFO_LINK,
+            offset: rela_offset,
+            size: rela.len(),
+            link: SYMTAB,
+            info: u32::from(TEXT),
+            align: 8,
+            entry_size: RELOCATION_SIZE,
+        },
+        // marks the stack as non-executable
+        SectionHeader {
+            name: names[4],
+            kind: SHT_PROGBITS,
+            flags: 0,
+            offset: note_offset,
+            size: 0,
+            link: 0,
+            info: 0,
+            align: 1,
+            entry_size: 0,
+        },
+        SectionHeader {
+            name: names[5],
+            kind: SHT_STRTAB,
+            flags: 0,
+            offset: shstrtab_offset,
+            size: shstrtab.len(),
+            link: 0,
+            info: 0,
+            align: 1,
+            entry_size: 0,
+        },
+    ];
+    for section in &sections {
+        section.write(&mut output);
+    }
+
+    output
+}
+
+fn write_header(output: &mut [u8], section_headers_offset: usize) {
+    let mut header = Vec::with_capacity(HEADER_SIZE);
+    // magic, 64-bit, little-endian, current version, System V ABI
+    header.extend_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1, 0]);
+    header.extend_from_slice(&[0; 8]);
+    header.extend_from_slice(&1u16.to_le_bytes()); // relocatable
+    header.extend_from_slice(&62u16.to_le_bytes()); // x86-64
+    header.extend_from_slice(&1u32.to_le_bytes()); // version
+    header.extend_from_slice(&0u64.to_le_bytes()); // entry point
+    header.extend_from_slice(&0u64.to_le_bytes()); // program headers
+    header.extend_from_slice(&(section_headers_offset as u64).to_le_bytes());
+    header.extend_from_slice(&0u32.to_le_bytes()); // flags
+    header.extend_from_slice(&(HEADER_SIZE as u16).to_le_bytes());
+    header.extend_from_slice(&0u16.to_le_bytes()); // program header size
+    header.extend_from_slice(&0u16.to_le_bytes()); // program header count
+    header.extend_from_slice(&(SECTION_HEADER_SIZE as u16).to_le_bytes());
+    header.extend_from_slice(&SECTION_COUNT.to_le_bytes());
+    header.extend_from_slice(&SHSTRTAB.to_le_bytes());
+    output[..HEADER_SIZE].copy_from_slice(&header);
+}
+
+struct SectionHeader {
+    name: u32,
+    kind: u32,
+    flags: u64,
+    offset: usize,
+    size: usize,
+    link: u32,
+    info: u32,
+    align: usize,
+    entry_size: usize,
+}
+
+impl SectionHeader {
+    fn write(&self, output: &mut Vec<u8>) {
+        output.extend_from_slice(&self.name.to_le_bytes());
+        output.extend_from_slice(&self.kind.to_le_bytes());
+        output.extend_from_slice(&self.flags.to_le_bytes());
+        output.extend_from_slice(&0u64.to_le_bytes()); // address
+        output.extend_from_slice(&(self.offset as u64).to_le_bytes());
+        output.extend_from_slice(&(self.size as u64).to_le_bytes());
+        output.extend_from_slice(&self.link.to_le_bytes());
+        output.extend_from_slice(&self.info.to_le_bytes());
+        output.extend_from_slice(&(self.align as u64).to_le_bytes());
+        output.extend_from_slice(&(self.entry_size as u64).to_le_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        let mut value = [0; 4];
+        value.copy_from_slice(&bytes[offset..offset + 4]);
+        u32::from_le_bytes(value)
+    }
+
+    fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+        let mut value = [0; 8];
+        value.copy_from_slice(&bytes[offset..offset + 8]);
+        u64::from_le_bytes(value)
+    }
+
+    fn string_at(bytes: &[u8], offset: usize) -> &str {
+        let end = offset + bytes[offset..].iter().position(|&b| b == 0).unwrap();
+        std::str::from_utf8(&bytes[offset..end]).unwrap()
+    }
+
+    /// An object defining `_hai_main`, which calls the undefined `_hai_f`.
+    fn object() -> Vec<u8> {
+        write_relocatable(
+            &[0xe8, 0, 0, 0, 0, 0xc3],
+            &[Symbol {
+                name: "_hai_main".to_string(),
+                offset: 0,
+                size: 6,
+            }],
+            &[Relocation {
+                offset: 1,
+                symbol: "_hai_f".to_string(),
+                kind: R_X86_64_PLT32,
+                addend: -4,
+            }],
+        )
+    }
+
+    #[test]
+    fn test_header() {
+        let object = object();
+        assert_eq!(object[..8], [0x7f, b'E', b'L', b'F', 2, 1, 1, 0]);
+        assert_eq!(u16_at(&object, 16), 1); // relocatable
+        assert_eq!(u16_at(&object, 18), 62); // x86-64
+        assert_eq!(u64_at(&object, 32), 0); // no program headers
+        assert_eq!(u64_at(&object, 40), 256); // section headers
+        assert_eq!(u16_at(&object, 52), 64);
+        assert_eq!(u16_at(&object, 58), 64);
+        assert_eq!(u16_at(&object, 60), 7);
+        assert_eq!(u16_at(&object, 62), 6); // .shstrtab
+        assert_eq!(object.len(), 256 + 7 * 64);
+    }
+
+    #[test]
+    fn test_sections() {
+        let object = object();
+        let shstrtab_offset = u64_at(&object, 256 + 6 * 64 + 24) as usize;
+        let sections: Vec<_> = (0..7)
+            .map(|index| {
+                let header = 256 + index * 64;
+                (
+                    string_at(&object, shstrtab_offset + u32_at(&object, header) as usize),
+                    u32_at(&object, header + 4),
+                    u64_at(&object, header + 24),
+                    u64_at(&object, header + 32),
+                    u32_at(&object, header + 40),
+                    u32_at(&object, header + 44),
+                )
+            })
+            .collect();
+        // name, type, offset, size, link and info of each section
+        assert_eq!(
+            sections,
+            [
+                ("", 0, 0, 0, 0, 0),
+                (".text", SHT_PROGBITS, 64, 6, 0, 0),
+                (".symtab", SHT_SYMTAB, 72, 3 * 24, 3, 1),
+                (".strtab", SHT_STRTAB, 144, 18, 0, 0),
+                (".rela.text", SHT_RELA, 168, 24, 2, 1),
+                (".note.GNU-stack", SHT_PROGBITS, 192, 0, 0, 0),
+                (".shstrtab", SHT_STRTAB, 192, 60, 0, 0),
+            ]
+        );
+        assert_eq!(object[64..70], [0xe8, 0, 0, 0, 0, 0xc3]);
+    }
+
+    #[test]
+    fn test_symbols_and_relocations() {
+        let object = object();
+        let symbol = |index: usize| {
+            let entry = 72 + index * 24;
+            (
+                string_at(&object, 144 + u32_at(&object, entry) as usize),
+                object[entry + 4],
+                u16_at(&object, entry + 6),
+                u64_at(&object, entry + 8),
+                u64_at(&object, entry + 16),
+            )
+        };
+        // name, binding and type, section, value and size
+        assert_eq!(symbol(0), ("", 0, 0, 0, 0));
+        assert_eq!(symbol(1), ("_hai_main", 0x12, 1, 0, 6));
+        assert_eq!(symbol(2), ("_hai_f", 0x10, 0, 0, 0));
+
+        // offset, symbol and type, addend
+        assert_eq!(u64_at(&object, 168), 1);
+        assert_eq!(u64_at(&object, 176), (2 << 32) | u64::from(R_X86_64_PLT32));
+        assert_eq!(u64_at(&object, 184) as i64, -4);
+    }
+}
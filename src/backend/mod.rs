@@ -0,0 +1,7 @@
+//! Code generation from the IR to native targets.
+
+pub mod c;
+pub mod elf;
+pub mod regalloc;
+pub mod wat;
+pub mod x86_64;
@@ -0,0 +1,182 @@
+//! Linear-scan register allocation (Poletto and Sarkar) over the values of an IR function.
+//!
+//! Each value gets a single live interval spanning from its first to its last live position,
+//! positions being the statements and terminators numbered in block order. Values that don't fit
+//! in the registers of the target are spilled to stack slots.
+
+use crate::ir::analysis::liveness::Liveness;
+use crate::ir::*;
+
+/// Whether the content of a register is preserved across calls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegisterKind {
+    CallerSaved,
+    CalleeSaved,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Location {
+    /// An index in the registers given to the allocator.
+    Register(usize),
+    /// An index of stack slot, slots are numbered from 0 to `Allocation::stack_slots`.
+    Stack(usize),
+}
+
+#[derive(Debug, Clone)]
+pub struct Allocation {
+    locations: Vec<Option<Location>>,
+    stack_slots: usize,
+}
+
+impl Allocation {
+    /// Returns the location of `value`, or None if the value is never defined.
+    pub fn location(&self, value: Value) -> Option<Location> {
+        self.locations[value.index()]
+    }
+
+    pub fn stack_slots(&self) -> usize {
+        self.stack_slots
+    }
+
+    /// Returns true if a value was allocated to the register `index`.
+    pub fn uses_register(&self, index: usize) -> bool {
+        self.locations.contains(&Some(Location::Register(index)))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Interval {
+    value: Value,
+    start: usize,
+    end: usize,
+    crosses_call: bool,
+}
+
+/// Assigns a location to every value of `function`. Values live across a call are only given
+/// callee-saved registers.
+pub fn allocate_registers(function: &Function, registers: &[RegisterKind]) -> Allocation {
+    let intervals = build_intervals(function);
+
+    let mut locations = vec![None; function.value_count()];
+    let mut stack_slots = 0;
+    let mut free = vec![true; registers.len()];
+    // intervals currently in a register, with their register
+    let mut active: Vec<(Interval, usize)> = Vec::new();
+
+    for interval in intervals {
+        active.retain(|&(other, register)| {
+            if other.end < interval.start {
+                free[register] = true;
+                false
+            } else {
+                true
+            }
+        });
+
+        let allowed = |register: usize| {
+            !interval.crosses_call || registers[register] == RegisterKind::CalleeSaved
+        };
+
+        // caller-saved registers are preferred as they don't need to be saved in the prologue
+        let register = (0..registers.len())
+            .filter(|&register| free[register] && allowed(register))
+            .min_by_key(|&register| registers[register] == RegisterKind::CalleeSaved);
+
+        if let Some(register) = register {
+            free[register] = false;
+            locations[interval.value.index()] = Some(Location::Register(register));
+            active.push((interval, register));
+            continue;
+        }
+
+        // spill the interval ending last, among the ones whose register could be used
+        let victim = active
+            .iter()
+            .enumerate()
+            .filter(|&(_, &(_, register))| allowed(register))
+            .max_by_key(|&(_, &(other, _))| other.end)
+            .map(|(index, _)| index);
+
+        match victim {
+            Some(index) if active[index].0.end > interval.end => {
+                let (spilled, register) = active.swap_remove(index);
+                locations[spilled.value.index()] = Some(Location::Stack(stack_slots));
+                stack_slots += 1;
+                locations[interval.value.index()] = Some(Location::Register(register));
+                active.push((interval, register));
+            }
+            _ => {
+                locations[interval.value.index()] = Some(Location::Stack(stack_slots));
+                stack_slots += 1;
+            }
+        }
+    }
+
+    Allocation {
+        locations,
+        stack_slots,
+    }
+}
+
+/// Returns the live intervals of the values of `function`, sorted by start position.
+fn build_intervals(function: &Function) -> Vec<Interval> {
+    let liveness = Liveness::new(function);
+
+    let mut ranges: Vec<Option<(Value, usize, usize)>> = vec![None; function.value_count()];
+    let mut extend = |value: Value, position: usize| {
+        let range = &mut ranges[value.index()];
+        *range = Some(match *range {
+            Some((_, start, end)) => (value, start.min(position), end.max(position)),
+            None => (value, position, position),
+        });
+    };
+
+    let mut calls = Vec::new();
+    let mut position = 0;
+    for (index, bb) in function.basic_blocks().iter().enumerate() {
+        let block_start = position;
+        for &value in liveness.live_in(BB(index)) {
+            extend(value, block_start);
+        }
+
+        for statement in bb.statements() {
+            let operation = match *statement {
+                Statement::WithDestination(_, ref operation)
+                | Statement::Operation(ref operation) => operation,
+            };
+            for operand in operation.operands() {
+                extend(operand, position);
+            }
+            if let Statement::WithDestination(value, _) = *statement {
+                extend(value, position);
+            }
+            if let Operation::Call { .. } = *operation {
+                calls.push(position);
+            }
+            position += 1;
+        }
+
+        for operand in bb.terminator().operands() {
+            extend(operand, position);
+        }
+        for &value in liveness.live_out(BB(index)) {
+            extend(value, position);
+        }
+        position += 1;
+    }
+
+    let mut intervals: Vec<Interval> = ranges
+        .into_iter()
+        .filter_map(|range| {
+            range.map(|(value, start, end)| Interval {
+                value,
+                start,
+                end,
+                // the arguments and the result of a call don't need to survive it
+                crosses_call: calls.iter().any(|&call| start < call && call < end),
+            })
+        })
+        .collect();
+    intervals.sort_by_key(|interval| interval.start);
+    intervals
+}
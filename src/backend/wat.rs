@@ -0,0 +1,510 @@
+//! WebAssembly text backend. Every function is exported under its hai name, and all the IR
+//! types are represented as `i32`, the unit value being 0.
+//!
+//! Structured control flow is recovered from the dominator tree, following "Beyond Relooper"
+//! (Ramsey): a block dominating merge points is wrapped in one `block` per merge point, loop
+//! headers open a `loop`, and the other successors are emitted inline. This requires a
+//! reducible control flow graph, which is always the case for functions lowered from hai.
+
+use crate::ir::analysis::cfg;
+use crate::ir::analysis::dominators::DominatorTree;
+use crate::ir::*;
+
+use std::fmt::Write;
+
+pub fn emit_module(module: &Module) -> Result<String, String> {
+    let mut output = String::new();
+    writeln!(output, "(module").unwrap();
+    for function in &module.functions {
+        FunctionEmitter::new(module, function, &mut output)?.emit();
+    }
+    writeln!(output, ")").unwrap();
+    Ok(output)
+}
+
+/// The constructs enclosing the code being emitted, which are the possible targets of `br`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ContainingSyntax {
+    IfThenElse,
+    LoopHeadedBy(BB),
+    BlockFollowedBy(BB),
+}
+
+struct FunctionEmitter<'a> {
+    module: &'a Module,
+    function: &'a Function,
+    domtree: DominatorTree,
+    rpo_index: Vec<usize>,
+    /// Blocks with several forward predecessors.
+    merge_nodes: Vec<bool>,
+    /// Blocks targeted by a back edge.
+    loop_headers: Vec<bool>,
+    context: Vec<ContainingSyntax>,
+    output: &'a mut String,
+    indent: usize,
+}
+
+impl<'a> FunctionEmitter<'a> {
+    fn new(
+        module: &'a Module,
+        function: &'a Function,
+        output: &'a mut String,
+    ) -> Result<Self, String> {
+        let count = function.basic_blocks().len();
+        let domtree = DominatorTree::new(function);
+
+        let mut rpo_index = vec![usize::MAX; count];
+        for (index, bb) in cfg::reverse_postorder(function).into_iter().enumerate() {
+            rpo_index[bb.0] = index;
+        }
+
+        let mut merge_nodes = vec![false; count];
+        let mut loop_headers = vec![false; count];
+        for (index, preds) in cfg::predecessors(function).into_iter().enumerate() {
+            let mut forward_preds = 0;
+            for pred in preds {
+                if rpo_index[pred.0] == usize::MAX {
+                    continue;
+                }
+                if rpo_index[pred.0] < rpo_index[index] {
+                    forward_preds += 1;
+                } else if domtree.dominates(BB(index), pred) {
+                    loop_headers[index] = true;
+                } else {
+                    return Err(format!(
+                        "function `{}` has an irreducible control flow graph",
+                        function.name()
+                    ));
+                }
+            }
+            merge_nodes[index] = forward_preds >= 2;
+        }
+
+        Ok(FunctionEmitter {
+            module,
+            function,
+            domtree,
+            rpo_index,
+            merge_nodes,
+            loop_headers,
+            context: Vec::new(),
+            output,
+            indent: 1,
+        })
+    }
+
+    fn emit(mut self) {
+        let name = self.function.name();
+        let mut header = format!("(func ${} (export \"{}\")", name, name);
+        for index in 0..self.function.params().len() {
+            write!(header, " (param $p{} i32)", index).unwrap();
+        }
+        header.push_str(" (result i32)");
+        self.line(&header);
+        self.indent += 1;
+
+        let mut defined = vec![false; self.function.value_count()];
+        for bb in self.function.basic_blocks() {
+            for statement in bb.statements() {
+                if let Statement::WithDestination(value, _) = *statement {
+                    defined[value.index()] = true;
+                }
+            }
+        }
+        for (index, _) in defined.iter().enumerate().filter(|&(_, &d)| d) {
+            self.line(&format!("(local $v{} i32)", index));
+        }
+        for index in 0..self.function.variables().len() {
+            self.line(&format!("(local $var{} i32)", index));
+        }
+
+        self.do_tree(BB(0));
+        // every path returns before, but the validator needs the end of the body to be
+        // unreachable
+        self.line("unreachable");
+
+        self.indent -= 1;
+        self.line(")");
+    }
+
+    fn do_tree(&mut self, bb: BB) {
+        let mut merge_children: Vec<BB> = self
+            .domtree
+            .children(bb)
+            .iter()
+            .cloned()
+            .filter(|child| self.merge_nodes[child.0])
+            .collect();
+        // the merge child coming last in reverse postorder is the outermost block
+        merge_children.sort_by_key(|child| std::cmp::Reverse(self.rpo_index[child.0]));
+
+        if self.loop_headers[bb.0] {
+            self.open("loop", ContainingSyntax::LoopHeadedBy(bb));
+            self.node_within(bb, &merge_children);
+            self.close();
+        } else {
+            self.node_within(bb, &merge_children);
+        }
+    }
+
+    fn node_within(&mut self, bb: BB, merge_children: &[BB]) {
+        if let Some((&first, rest)) = merge_children.split_first() {
+            self.open("block", ContainingSyntax::BlockFollowedBy(first));
+            self.node_within(bb, rest);
+            self.close();
+            self.do_tree(first);
+            return;
+        }
+
+        let block = &self.function.basic_blocks()[bb.0];
+        for statement in block.statements() {
+            self.emit_statement(statement);
+        }
+
+        match *block.terminator() {
+            Terminator::Return(value) => {
+                self.line(&format!("local.get $v{}", value.index()));
+                self.line("return");
+            }
+            Terminator::Jump(target) => self.do_branch(bb, target),
+            Terminator::Branch(cond, then_bb, else_bb) => {
+                self.line(&format!("local.get $v{}", cond.index()));
+                self.open("if", ContainingSyntax::IfThenElse);
+                self.do_branch(bb, then_bb);
+                self.indent -= 1;
+                self.line("else");
+                self.indent += 1;
+                self.do_branch(bb, else_bb);
+                self.close();
+            }
+            Terminator::Unreachable => self.line("unreachable"),
+        }
+    }
+
+    fn do_branch(&mut self, source: BB, target: BB) {
+        let backward = self.rpo_index[target.0] <= self.rpo_index[source.0];
+        if !backward && !self.merge_nodes[target.0] {
+            // `target` is only reached from here, its code is emitted in place
+            self.do_tree(target);
+            return;
+        }
+
+        let expected = if backward {
+            ContainingSyntax::LoopHeadedBy(target)
+        } else {
+            ContainingSyntax::BlockFollowedBy(target)
+        };
+        let position = self
+            .context
+            .iter()
+            .rposition(|&syntax| syntax == expected)
+            .expect("branch target not in scope");
+        let depth = self.context.len() - 1 - position;
+        self.line(&format!("br {}", depth));
+    }
+
+    fn emit_statement(&mut self, statement: &Statement) {
+        let (destination, operation) = match *statement {
+            Statement::WithDestination(value, ref operation) => (Some(value), operation),
+            Statement::Operation(ref operation) => (None, operation),
+        };
+
+        let binary = |instruction: &str, lhs: Value, rhs: Value| {
+            vec![
+                format!("local.get $v{}", lhs.index()),
+                format!("local.get $v{}", rhs.index()),
+                instruction.to_string(),
+            ]
+        };
+        let instructions = match *operation {
+            Operation::Literal(ref literal) => {
+                let constant = match *literal {
+                    Literal::Unit => 0,
+                    Literal::Int(i) => i,
+                    Literal::Boolean(b) => b as i32,
+                };
+                vec![format!("i32.const {}", constant)]
+            }
+            Operation::BinaryAdd(lhs, rhs) => binary("i32.add", lhs, rhs),
+            Operation::BinarySub(lhs, rhs) => binary("i32.sub", lhs, rhs),
+            Operation::BinaryMul(lhs, rhs) => binary("i32.mul", lhs, rhs),
+            Operation::BinaryDivide(lhs, rhs) => binary("i32.div_s", lhs, rhs),
+            Operation::BinaryShiftLeft(lhs, rhs) => binary("i32.shl", lhs, rhs),
+            Operation::Negate(value) => vec![
+                "i32.const 0".to_string(),
+                format!("local.get $v{}", value.index()),
+                "i32.sub".to_string(),
+            ],
+            Operation::Compare(comparison, lhs, rhs) => {
+                let instruction = match comparison {
+                    Comparison::Equal => "i32.eq",
+                    Comparison::NotEqual => "i32.ne",
+                    Comparison::Less => "i32.lt_s",
+                    Comparison::LessEqual => "i32.le_s",
+                    Comparison::Greater => "i32.gt_s",
+                    Comparison::GreaterEqual => "i32.ge_s",
+                };
+                binary(instruction, lhs, rhs)
+            }
+            Operation::LoadParam(index) => vec![format!("local.get $p{}", index)],
+            Operation::LoadVariable(var) => vec![format!("local.get $var{}", var.index())],
+            Operation::StoreVariable(var, value) => vec![
+                format!("local.get $v{}", value.index()),
+                format!("local.set $var{}", var.index()),
+            ],
+            Operation::Call { callee, ref args } => {
+                let mut instructions: Vec<String> = args
+                    .iter()
+                    .map(|arg| format!("local.get $v{}", arg.index()))
+                    .collect();
+                instructions.push(format!("call ${}", self.module.functions[callee].name()));
+                if destination.is_none() {
+                    instructions.push("drop".to_string());
+                }
+                instructions
+            }
+        };
+
+        for instruction in instructions {
+            self.line(&instruction);
+        }
+        if let Some(destination) = destination {
+            self.line(&format!("local.set $v{}", destination.index()));
+        }
+    }
+
+    fn open(&mut self, instruction: &str, syntax: ContainingSyntax) {
+        self.line(instruction);
+        self.indent += 1;
+        self.context.push(syntax);
+    }
+
+    fn close(&mut self) {
+        self.context.pop();
+        self.indent -= 1;
+        self.line("end");
+    }
+
+    fn line(&mut self, line: &str) {
+        writeln!(self.output, "{}{}", "  ".repeat(self.indent), line).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ir::text::parse_module;
+
+    /// Emits `input`, checks that the result is a valid WebAssembly module, and returns its
+    /// control flow instructions.
+    fn structure(input: &str) -> String {
+        let wat = emit_module(&parse_module(input).unwrap()).unwrap();
+        validate(&wat);
+        let keywords = ["loop", "block", "if", "else", "end", "br", "return"];
+        wat.lines()
+            .filter(|line| {
+                let first = line.split_whitespace().next().unwrap_or("");
+                keywords.contains(&first)
+            })
+            .map(|line| format!("{}\n", line))
+            .collect()
+    }
+
+    fn validate(wat: &str) {
+        let binary = wat::parse_str(wat).unwrap_or_else(|e| panic!("{}\n{}", e, wat));
+        if let Err(e) = wasmparser::validate(&binary) {
+            panic!("{}\n{}", e, wat);
+        }
+    }
+
+    #[test]
+    fn test_loop() {
+        let input = "fn sum(int) -> int {
+    vars: int
+bb0:
+    %0 = const 0
+    store $0, %0
+    jmp bb1
+bb1:
+    %1 = param 0
+    %2 = gt %1, %0
+    br %2, bb2, bb3
+bb2:
+    %3 = load $0
+    %4 = add %3, %1
+    store $0, %4
+    jmp bb1
+bb3:
+    %5 = load $0
+    ret %5
+}
+";
+        assert_eq!(
+            structure(input),
+            "    loop
+      if
+        br 1
+      else
+        return
+      end
+    end
+"
+        );
+    }
+
+    #[test]
+    fn test_loop_with_two_exits() {
+        let input = "fn f(int) -> int {
+bb0:
+    %0 = param 0
+    %1 = const 0
+    jmp bb1
+bb1:
+    %2 = gt %0, %1
+    br %2, bb2, bb4
+bb2:
+    %3 = eq %0, %1
+    br %3, bb4, bb3
+bb3:
+    jmp bb1
+bb4:
+    ret %0
+}
+";
+        // the exit is dominated by the loop header, so it follows a block within the loop
+        assert_eq!(
+            structure(input),
+            "    loop
+      block
+        if
+          if
+            br 2
+          else
+            br 3
+          end
+        else
+          br 1
+        end
+      end
+      return
+    end
+"
+        );
+    }
+
+    #[test]
+    fn test_nested_ifs() {
+        let input = "fn sign(int) -> int {
+bb0:
+    %0 = param 0
+    %1 = const 0
+    %2 = lt %0, %1
+    br %2, bb1, bb2
+bb1:
+    %3 = const -1
+    ret %3
+bb2:
+    %4 = eq %0, %1
+    br %4, bb3, bb4
+bb3:
+    ret %1
+bb4:
+    %5 = const 1
+    ret %5
+}
+";
+        assert_eq!(
+            structure(input),
+            "    if
+      return
+    else
+      if
+        return
+      else
+        return
+      end
+    end
+"
+        );
+    }
+
+    #[test]
+    fn test_merge_node() {
+        let input = "fn f(int) -> int {
+    vars: int
+bb0:
+    %0 = param 0
+    %1 = const 0
+    %2 = lt %0, %1
+    br %2, bb1, bb2
+bb1:
+    %3 = const 1
+    %4 = eq %0, %3
+    br %4, bb3, bb4
+bb2:
+    store $0, %1
+    jmp bb5
+bb3:
+    store $0, %3
+    jmp bb5
+bb4:
+    store $0, %0
+    jmp bb5
+bb5:
+    %5 = load $0
+    ret %5
+}
+";
+        // the three predecessors of bb5 branch out of the block preceding it
+        assert_eq!(
+            structure(input),
+            "    block
+      if
+        if
+          br 2
+        else
+          br 2
+        end
+      else
+        br 1
+      end
+    end
+    return
+"
+        );
+    }
+
+    #[test]
+    fn test_irreducible_control_flow() {
+        let input = "fn f(bool) -> int {
+bb0:
+    %0 = param 0
+    br %0, bb1, bb2
+bb1:
+    jmp bb2
+bb2:
+    jmp bb1
+}
+";
+        assert_eq!(
+            emit_module(&parse_module(input).unwrap()).unwrap_err(),
+            "function `f` has an irreducible control flow graph"
+        );
+    }
+
+    #[test]
+    fn test_examples_are_valid() {
+        let sources = [
+            include_str!("../../examples_hai/loops.hai"),
+            include_str!("../../tests/ui/calls.hai"),
+            include_str!("../../tests/ui/loops.hai"),
+        ];
+        for source in &sources {
+            for opt_level in 0..=2 {
+                let module = crate::compile(source, opt_level).unwrap();
+                validate(&emit_module(&module).unwrap());
+            }
+        }
+    }
+}
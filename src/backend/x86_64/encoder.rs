@@ -0,0 +1,403 @@
+//! Encoding of the instructions to machine code.
+
+use super::instruction::{BinaryOp, Instruction, Operand, Register};
+use crate::backend::elf::{Relocation, R_X86_64_PLT32};
+use crate::ir::Comparison;
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+/// Appends the machine code of a function to `code`. Calls are encoded with a zero displacement
+/// and a relocation against the callee symbol.
+pub fn encode_function(
+    instructions: &[Instruction],
+    code: &mut Vec<u8>,
+    relocations: &mut Vec<Relocation>,
+) {
+    let mut labels = HashMap::new();
+    // positions of the 32-bit displacements of jumps, with their target
+    let mut jumps = Vec::new();
+
+    for instruction in instructions {
+        match *instruction {
+            Instruction::Label(bb) => {
+                labels.insert(bb, code.len());
+            }
+            Instruction::Mov32(src, dst) => encode_mov(code, src, dst, false),
+            Instruction::Mov64(src, dst) => encode_mov(code, src, dst, true),
+            Instruction::Binary32(op, src, dst) => {
+                let opcode: &[u8] = match op {
+                    BinaryOp::Add => &[0x03],
+                    BinaryOp::Sub => &[0x2b],
+                    BinaryOp::Imul => &[0x0f, 0xaf],
+                    BinaryOp::Cmp => &[0x3b],
+                };
+                encode_modrm(code, opcode, dst.number(), src, false);
+            }
+            Instruction::CmpImmediate32(value, dst) => {
+                encode_immediate_group(code, 7, dst, value, false)
+            }
+            Instruction::SubRsp(size) => encode_rsp_adjustment(code, 5, size),
+            Instruction::AddRsp(size) => encode_rsp_adjustment(code, 0, size),
+            Instruction::Push(register) => {
+                encode_rex(code, false, 0, register.number());
+                code.push(0x50 + (register.number() & 7));
+            }
+            Instruction::Pop(register) => {
+                encode_rex(code, false, 0, register.number());
+                code.push(0x58 + (register.number() & 7));
+            }
+            Instruction::Cltd => code.push(0x99),
+            Instruction::Idiv32(src) => encode_modrm(code, &[0xf7], 7, src, false),
+            Instruction::Neg32(register) => {
+                encode_modrm(code, &[0xf7], 3, Operand::Register(register), false)
+            }
+            Instruction::ShlCl32(register) => {
+                encode_modrm(code, &[0xd3], 4, Operand::Register(register), false)
+            }
+            Instruction::SetAl(comparison) => {
+                code.extend_from_slice(&[0x0f, 0x90 + condition_code(comparison), 0xc0])
+            }
+            Instruction::ZeroExtendAl => code.extend_from_slice(&[0x0f, 0xb6, 0xc0]),
+            Instruction::Call(ref symbol) => {
+                code.push(0xe8);
+                relocations.push(Relocation {
+                    offset: code.len(),
+                    symbol: symbol.clone(),
+                    kind: R_X86_64_PLT32,
+                    addend: -4,
+                });
+                code.extend_from_slice(&[0; 4]);
+            }
+            Instruction::Jump(bb) => {
+                code.push(0xe9);
+                jumps.push((code.len(), bb));
+                code.extend_from_slice(&[0; 4]);
+            }
+            Instruction::JumpIfNotEqual(bb) => {
+                code.extend_from_slice(&[0x0f, 0x85]);
+                jumps.push((code.len(), bb));
+                code.extend_from_slice(&[0; 4]);
+            }
+            Instruction::Leave => code.push(0xc9),
+            Instruction::Ret => code.push(0xc3),
+            Instruction::Ud2 => code.extend_from_slice(&[0x0f, 0x0b]),
+        }
+    }
+
+    for (position, bb) in jumps {
+        // the displacement is relative to the end of the jump instruction
+        let displacement = labels[&bb] as i64 - (position as i64 + 4);
+        code[position..position + 4].copy_from_slice(&(displacement as i32).to_le_bytes());
+    }
+}
+
+/// Emits an instruction of the group 1 (`add`, `sub`, `cmp`...) selected by `extension`, with
+/// an immediate operand.
+fn encode_immediate_group(code: &mut Vec<u8>, extension: u8, dst: Operand, value: i32, wide: bool) {
+    if let Ok(value) = i8::try_from(value) {
+        encode_modrm(code, &[0x83], extension, dst, wide);
+        code.push(value as u8);
+    } else {
+        encode_modrm(code, &[0x81], extension, dst, wide);
+        code.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+fn encode_rsp_adjustment(code: &mut Vec<u8>, extension: u8, size: i32) {
+    encode_immediate_group(
+        code,
+        extension,
+        Operand::Register(Register::Rsp),
+        size,
+        true,
+    );
+}
+
+fn encode_mov(code: &mut Vec<u8>, src: Operand, dst: Operand, wide: bool) {
+    match (src, dst) {
+        (Operand::Immediate(value), Operand::Register(register)) => {
+            encode_rex(code, wide, 0, register.number());
+            code.push(0xb8 + (register.number() & 7));
+            code.extend_from_slice(&value.to_le_bytes());
+        }
+        (Operand::Immediate(value), dst) => {
+            encode_modrm(code, &[0xc7], 0, dst, wide);
+            code.extend_from_slice(&value.to_le_bytes());
+        }
+        (Operand::Register(register), dst) => {
+            encode_modrm(code, &[0x89], register.number(), dst, wide)
+        }
+        (src, Operand::Register(register)) => {
+            encode_modrm(code, &[0x8b], register.number(), src, wide)
+        }
+        _ => panic!("unsupported move from {:?} to {:?}", src, dst),
+    }
+}
+
+/// Emits the REX prefix if one is needed for a 64-bit operand size or an extended register.
+fn encode_rex(code: &mut Vec<u8>, wide: bool, reg: u8, rm: u8) {
+    let rex = 0x40 | (u8::from(wide) << 3) | ((reg >> 3) << 2) | (rm >> 3);
+    if rex != 0x40 {
+        code.push(rex);
+    }
+}
+
+/// Emits `opcode` with a ModRM byte whose reg field is `reg` and whose r/m field designates
+/// `rm`, memory operands being relative to `%rbp`.
+fn encode_modrm(code: &mut Vec<u8>, opcode: &[u8], reg: u8, rm: Operand, wide: bool) {
+    match rm {
+        Operand::Register(register) => {
+            encode_rex(code, wide, reg, register.number());
+            code.extend_from_slice(opcode);
+            code.push(0xc0 | ((reg & 7) << 3) | (register.number() & 7));
+        }
+        Operand::Memory(displacement) => {
+            let base = Register::Rbp.number();
+            encode_rex(code, wide, reg, base);
+            code.extend_from_slice(opcode);
+            if let Ok(displacement) = i8::try_from(displacement) {
+                code.push(0x40 | ((reg & 7) << 3) | base);
+                code.push(displacement as u8);
+            } else {
+                code.push(0x80 | ((reg & 7) << 3) | base);
+                code.extend_from_slice(&displacement.to_le_bytes());
+            }
+        }
+        Operand::Immediate(_) => panic!("an immediate can't be encoded in ModRM"),
+    }
+}
+
+fn condition_code(comparison: Comparison) -> u8 {
+    match comparison {
+        Comparison::Equal => 0x4,
+        Comparison::NotEqual => 0x5,
+        Comparison::Less => 0xc,
+        Comparison::LessEqual => 0xe,
+        Comparison::Greater => 0xf,
+        Comparison::GreaterEqual => 0xd,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ir::BB;
+
+    fn encode(instructions: &[Instruction]) -> Vec<u8> {
+        let mut code = Vec::new();
+        encode_function(instructions, &mut code, &mut Vec::new());
+        code
+    }
+
+    fn check(cases: &[(Instruction, &[u8])]) {
+        for (instruction, expected) in cases {
+            assert_eq!(
+                encode(std::slice::from_ref(instruction)),
+                *expected,
+                "{:?}",
+                instruction
+            );
+        }
+    }
+
+    // The expected bytes are the output of GNU as for the instruction in the comment, `{load}`
+    // selecting the same direction of the ModRM operands as the encoder.
+
+    #[test]
+    fn test_moves() {
+        use self::Operand::*;
+        use self::Register::*;
+        check(&[
+            // movl $1, %eax
+            (
+                Instruction::Mov32(Immediate(1), Register(Rax)),
+                &[0xb8, 1, 0, 0, 0],
+            ),
+            // movl $-1, %r12d
+            (
+                Instruction::Mov32(Immediate(-1), Register(R12)),
+                &[0x41, 0xbc, 0xff, 0xff, 0xff, 0xff],
+            ),
+            // movq %rsp, %rbp
+            (
+                Instruction::Mov64(Register(Rsp), Register(Rbp)),
+                &[0x48, 0x89, 0xe5],
+            ),
+            // movq %rsp, %r12
+            (
+                Instruction::Mov64(Register(Rsp), Register(R12)),
+                &[0x49, 0x89, 0xe4],
+            ),
+            // movl %r13d, -8(%rbp)
+            (
+                Instruction::Mov32(Register(R13), Memory(-8)),
+                &[0x44, 0x89, 0x6d, 0xf8],
+            ),
+            // movl -200(%rbp), %r12d
+            (
+                Instruction::Mov32(Memory(-200), Register(R12)),
+                &[0x44, 0x8b, 0xa5, 0x38, 0xff, 0xff, 0xff],
+            ),
+            // movl $5, -4(%rbp)
+            (
+                Instruction::Mov32(Immediate(5), Memory(-4)),
+                &[0xc7, 0x45, 0xfc, 5, 0, 0, 0],
+            ),
+        ]);
+    }
+
+    #[test]
+    fn test_displacements() {
+        use self::Operand::*;
+        use self::Register::*;
+        check(&[
+            // movl -128(%rbp), %eax
+            (
+                Instruction::Mov32(Memory(-128), Register(Rax)),
+                &[0x8b, 0x45, 0x80],
+            ),
+            // movl -129(%rbp), %eax
+            (
+                Instruction::Mov32(Memory(-129), Register(Rax)),
+                &[0x8b, 0x85, 0x7f, 0xff, 0xff, 0xff],
+            ),
+        ]);
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        use self::Operand::*;
+        use self::Register::*;
+        check(&[
+            // {load} addl %r13d, %r12d
+            (
+                Instruction::Binary32(BinaryOp::Add, Register(R13), R12),
+                &[0x45, 0x03, 0xe5],
+            ),
+            // {load} subl %r12d, %eax
+            (
+                Instruction::Binary32(BinaryOp::Sub, Register(R12), Rax),
+                &[0x41, 0x2b, 0xc4],
+            ),
+            // imull -8(%rbp), %r12d
+            (
+                Instruction::Binary32(BinaryOp::Imul, Memory(-8), R12),
+                &[0x44, 0x0f, 0xaf, 0x65, 0xf8],
+            ),
+            // {load} cmpl %eax, %ecx
+            (
+                Instruction::Binary32(BinaryOp::Cmp, Register(Rax), Rcx),
+                &[0x3b, 0xc8],
+            ),
+            // cltd
+            (Instruction::Cltd, &[0x99]),
+            // idivl %r13d
+            (Instruction::Idiv32(Register(R13)), &[0x41, 0xf7, 0xfd]),
+            // idivl -12(%rbp)
+            (Instruction::Idiv32(Memory(-12)), &[0xf7, 0x7d, 0xf4]),
+            // negl %r12d
+            (Instruction::Neg32(R12), &[0x41, 0xf7, 0xdc]),
+            // shll %cl, %r13d
+            (Instruction::ShlCl32(R13), &[0x41, 0xd3, 0xe5]),
+            // setl %al
+            (Instruction::SetAl(Comparison::Less), &[0x0f, 0x9c, 0xc0]),
+            // movzbl %al, %eax
+            (Instruction::ZeroExtendAl, &[0x0f, 0xb6, 0xc0]),
+        ]);
+    }
+
+    #[test]
+    fn test_immediates() {
+        use self::Operand::*;
+        use self::Register::*;
+        check(&[
+            // cmpl $1, %r12d
+            (
+                Instruction::CmpImmediate32(1, Register(R12)),
+                &[0x41, 0x83, 0xfc, 0x01],
+            ),
+            // cmpl $127, %ecx
+            (
+                Instruction::CmpImmediate32(127, Register(Rcx)),
+                &[0x83, 0xf9, 0x7f],
+            ),
+            // cmpl $128, %ecx
+            (
+                Instruction::CmpImmediate32(128, Register(Rcx)),
+                &[0x81, 0xf9, 0x80, 0, 0, 0],
+            ),
+            // cmpl $1000, -4(%rbp)
+            (
+                Instruction::CmpImmediate32(1000, Memory(-4)),
+                &[0x81, 0x7d, 0xfc, 0xe8, 0x03, 0, 0],
+            ),
+            // subq $16, %rsp
+            (Instruction::SubRsp(16), &[0x48, 0x83, 0xec, 0x10]),
+            // subq $1024, %rsp
+            (
+                Instruction::SubRsp(1024),
+                &[0x48, 0x81, 0xec, 0, 0x04, 0, 0],
+            ),
+            // addq $-128, %rsp
+            (Instruction::AddRsp(-128), &[0x48, 0x83, 0xc4, 0x80]),
+            // addq $128, %rsp
+            (Instruction::AddRsp(128), &[0x48, 0x81, 0xc4, 0x80, 0, 0, 0]),
+        ]);
+    }
+
+    #[test]
+    fn test_stack_and_control() {
+        use self::Register::*;
+        check(&[
+            // pushq %rbp
+            (Instruction::Push(Rbp), &[0x55]),
+            // pushq %r12
+            (Instruction::Push(R12), &[0x41, 0x54]),
+            // popq %r13
+            (Instruction::Pop(R13), &[0x41, 0x5d]),
+            // popq %rbx
+            (Instruction::Pop(Rbx), &[0x5b]),
+            // leave
+            (Instruction::Leave, &[0xc9]),
+            // ret
+            (Instruction::Ret, &[0xc3]),
+            // ud2
+            (Instruction::Ud2, &[0x0f, 0x0b]),
+        ]);
+    }
+
+    #[test]
+    fn test_jumps_and_calls() {
+        let mut code = Vec::new();
+        let mut relocations = Vec::new();
+        encode_function(
+            &[
+                Instruction::Label(BB(0)),
+                Instruction::JumpIfNotEqual(BB(1)),
+                Instruction::Call("_hai_f".to_string()),
+                Instruction::Label(BB(1)),
+                Instruction::Jump(BB(0)),
+            ],
+            &mut code,
+            &mut relocations,
+        );
+        assert_eq!(
+            code,
+            [
+                // jne +5, over the call
+                0x0f, 0x85, 5, 0, 0, 0, //
+                // call with a relocated displacement
+                0xe8, 0, 0, 0, 0, //
+                // jmp -16, to the start
+                0xe9, 0xf0, 0xff, 0xff, 0xff,
+            ]
+        );
+        assert_eq!(relocations.len(), 1);
+        assert_eq!(relocations[0].offset, 7);
+        assert_eq!(relocations[0].symbol, "_hai_f");
+        assert_eq!(relocations[0].kind, R_X86_64_PLT32);
+        assert_eq!(relocations[0].addend, -4);
+    }
+}
@@ -0,0 +1,177 @@
+//! The subset of x86-64 instructions used by the backend, printed in AT&T syntax.
+
+use crate::ir::{Comparison, BB};
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    Rax,
+    Rcx,
+    Rdx,
+    Rbx,
+    Rsp,
+    Rbp,
+    Rsi,
+    Rdi,
+    R8,
+    R9,
+    R10,
+    R11,
+    R12,
+    R13,
+    R14,
+    R15,
+}
+
+impl Register {
+    /// Returns the number of the register in the instruction encoding.
+    pub fn number(self) -> u8 {
+        self as u8
+    }
+
+    pub fn name32(self) -> &'static str {
+        [
+            "%eax", "%ecx", "%edx", "%ebx", "%esp", "%ebp", "%esi", "%edi", "%r8d", "%r9d",
+            "%r10d", "%r11d", "%r12d", "%r13d", "%r14d", "%r15d",
+        ][self as usize]
+    }
+
+    pub fn name64(self) -> &'static str {
+        [
+            "%rax", "%rcx", "%rdx", "%rbx", "%rsp", "%rbp", "%rsi", "%rdi", "%r8", "%r9", "%r10",
+            "%r11", "%r12", "%r13", "%r14", "%r15",
+        ][self as usize]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    Register(Register),
+    /// A memory location, as its displacement from `%rbp`.
+    Memory(i32),
+    Immediate(i32),
+}
+
+impl Operand {
+    fn fmt_sized(self, f: &mut fmt::Formatter, wide: bool) -> fmt::Result {
+        match self {
+            Operand::Register(register) if wide => write!(f, "{}", register.name64()),
+            Operand::Register(register) => write!(f, "{}", register.name32()),
+            Operand::Memory(displacement) => write!(f, "{}(%rbp)", displacement),
+            Operand::Immediate(value) => write!(f, "${}", value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Imul,
+    Cmp,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Instruction {
+    /// The start of a basic block of the function.
+    Label(BB),
+    Mov32(Operand, Operand),
+    Mov64(Operand, Operand),
+    /// `op src, dst` on 32-bit operands.
+    Binary32(BinaryOp, Operand, Register),
+    /// `cmpl $imm, dst`
+    CmpImmediate32(i32, Operand),
+    SubRsp(i32),
+    AddRsp(i32),
+    Push(Register),
+    Pop(Register),
+    Cltd,
+    Idiv32(Operand),
+    Neg32(Register),
+    /// Shifts the register left by `%cl`.
+    ShlCl32(Register),
+    /// Sets `%al` to the result of the comparison flags.
+    SetAl(Comparison),
+    /// Zero-extends `%al` to `%eax`.
+    ZeroExtendAl,
+    Call(String),
+    Jump(BB),
+    JumpIfNotEqual(BB),
+    Leave,
+    Ret,
+    Ud2,
+}
+
+impl Instruction {
+    /// Writes the instruction in AT&T syntax, `function` is the symbol the labels are relative to.
+    pub fn write_asm(&self, f: &mut fmt::Formatter, function: &str) -> fmt::Result {
+        match *self {
+            Instruction::Label(bb) => return writeln!(f, "{}:", label(function, bb)),
+            Instruction::Mov32(src, dst) => {
+                write!(f, "    movl ")?;
+                src.fmt_sized(f, false)?;
+                write!(f, ", ")?;
+                dst.fmt_sized(f, false)?;
+            }
+            Instruction::Mov64(src, dst) => {
+                write!(f, "    movq ")?;
+                src.fmt_sized(f, true)?;
+                write!(f, ", ")?;
+                dst.fmt_sized(f, true)?;
+            }
+            Instruction::Binary32(op, src, dst) => {
+                let mnemonic = match op {
+                    BinaryOp::Add => "addl",
+                    BinaryOp::Sub => "subl",
+                    BinaryOp::Imul => "imull",
+                    BinaryOp::Cmp => "cmpl",
+                };
+                write!(f, "    {} ", mnemonic)?;
+                src.fmt_sized(f, false)?;
+                write!(f, ", {}", dst.name32())?;
+            }
+            Instruction::CmpImmediate32(value, dst) => {
+                write!(f, "    cmpl ${}, ", value)?;
+                dst.fmt_sized(f, false)?;
+            }
+            Instruction::SubRsp(size) => write!(f, "    subq ${}, %rsp", size)?,
+            Instruction::AddRsp(size) => write!(f, "    addq ${}, %rsp", size)?,
+            Instruction::Push(register) => write!(f, "    pushq {}", register.name64())?,
+            Instruction::Pop(register) => write!(f, "    popq {}", register.name64())?,
+            Instruction::Cltd => write!(f, "    cltd")?,
+            Instruction::Idiv32(src) => {
+                write!(f, "    idivl ")?;
+                src.fmt_sized(f, false)?;
+            }
+            Instruction::Neg32(register) => write!(f, "    negl {}", register.name32())?,
+            Instruction::ShlCl32(register) => write!(f, "    shll %cl, {}", register.name32())?,
+            Instruction::SetAl(comparison) => {
+                write!(f, "    set{} %al", condition_suffix(comparison))?
+            }
+            Instruction::ZeroExtendAl => write!(f, "    movzbl %al, %eax")?,
+            Instruction::Call(ref symbol) => write!(f, "    call {}", symbol)?,
+            Instruction::Jump(bb) => write!(f, "    jmp {}", label(function, bb))?,
+            Instruction::JumpIfNotEqual(bb) => write!(f, "    jne {}", label(function, bb))?,
+            Instruction::Leave => write!(f, "    leave")?,
+            Instruction::Ret => write!(f, "    ret")?,
+            Instruction::Ud2 => write!(f, "    ud2")?,
+        }
+        writeln!(f)
+    }
+}
+
+fn label(function: &str, bb: BB) -> String {
+    format!(".L{}_{}", function, bb)
+}
+
+fn condition_suffix(comparison: Comparison) -> &'static str {
+    match comparison {
+        Comparison::Equal => "e",
+        Comparison::NotEqual => "ne",
+        Comparison::Less => "l",
+        Comparison::LessEqual => "le",
+        Comparison::Greater => "g",
+        Comparison::GreaterEqual => "ge",
+    }
+}
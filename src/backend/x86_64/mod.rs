@@ -0,0 +1,460 @@
+//! x86-64 backend following the System V ABI. The instructions selected for a module are either
+//! printed as GNU assembler (AT&T syntax), or encoded directly in an ELF relocatable object.
+//!
+//! Every hai function `f` is emitted as the global symbol `_hai_f`, so that the hai `main` does
+//! not clash with the C entry point. When the module has a `main` without parameters, a C `main`
+//! calling it is emitted too, so the output can be linked with the system C compiler.
+//!
+//! Values are placed in registers by the linear-scan allocator, variables and parameters live in
+//! stack slots. Integers and booleans are 32-bit wide, the unit value is 0. `%eax`, `%ecx` and
+//! `%edx` are scratch registers, and the parameter registers are not allocated, so that
+//! arguments can be moved to them in any order.
+
+pub mod encoder;
+pub mod instruction;
+
+use self::instruction::{BinaryOp, Instruction, Operand, Register};
+use super::elf;
+use super::regalloc::{self, Allocation, Location, RegisterKind};
+use crate::ir::*;
+
+use std::fmt;
+
+const PARAM_REGISTERS: [Register; 6] = [
+    Register::Rdi,
+    Register::Rsi,
+    Register::Rdx,
+    Register::Rcx,
+    Register::R8,
+    Register::R9,
+];
+const SLOT_SIZE: usize = 8;
+
+/// The allocatable registers.
+const REGISTERS: [(Register, RegisterKind); 7] = [
+    (Register::R10, RegisterKind::CallerSaved),
+    (Register::R11, RegisterKind::CallerSaved),
+    (Register::Rbx, RegisterKind::CalleeSaved),
+    (Register::R12, RegisterKind::CalleeSaved),
+    (Register::R13, RegisterKind::CalleeSaved),
+    (Register::R14, RegisterKind::CalleeSaved),
+    (Register::R15, RegisterKind::CalleeSaved),
+];
+
+/// A function after instruction selection.
+#[derive(Debug, Clone)]
+pub struct MachineFunction {
+    pub symbol: String,
+    pub instructions: Vec<Instruction>,
+}
+
+impl fmt::Display for MachineFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "    .globl {}", self.symbol)?;
+        writeln!(f, "{}:", self.symbol)?;
+        for instruction in &self.instructions {
+            instruction.write_asm(f, &self.symbol)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the functions of `module` after instruction selection, with the C entry point last.
+pub fn select_module(module: &Module) -> Vec<MachineFunction> {
+    select_functions(module, &REGISTERS)
+}
+
+/// Selects the instructions of `module`, giving only `registers` to the register allocator.
+fn select_functions(
+    module: &Module,
+    registers: &[(Register, RegisterKind)],
+) -> Vec<MachineFunction> {
+    let mut functions: Vec<MachineFunction> = module
+        .functions
+        .iter()
+        .map(|function| FunctionSelector::new(module, function, registers).select())
+        .collect();
+
+    let has_entry_point = module
+        .functions
+        .iter()
+        .any(|function| function.name() == "main" && function.params().is_empty());
+    if has_entry_point {
+        functions.push(MachineFunction {
+            symbol: "main".to_string(),
+            instructions: vec![
+                Instruction::Push(Register::Rbp),
+                Instruction::Mov64(
+                    Operand::Register(Register::Rsp),
+                    Operand::Register(Register::Rbp),
+                ),
+                Instruction::Call(symbol_name("main")),
+                Instruction::Pop(Register::Rbp),
+                Instruction::Ret,
+            ],
+        });
+    }
+    functions
+}
+
+/// Returns the assembly of `module`, for the GNU assembler.
+pub fn emit_module(module: &Module) -> String {
+    let mut output = String::from("    .text\n");
+    for function in select_module(module) {
+        output.push('\n');
+        output.push_str(&function.to_string());
+    }
+    output.push_str("\n    .section .note.GNU-stack,\"\",@progbits\n");
+    output
+}
+
+/// Returns an ELF relocatable object containing the machine code of `module`.
+pub fn emit_object(module: &Module) -> Vec<u8> {
+    let mut text = Vec::new();
+    let mut symbols = Vec::new();
+    let mut relocations = Vec::new();
+    for function in select_module(module) {
+        let offset = text.len();
+        encoder::encode_function(&function.instructions, &mut text, &mut relocations);
+        symbols.push(elf::Symbol {
+            name: function.symbol,
+            offset,
+            size: text.len() - offset,
+        });
+    }
+    elf::write_relocatable(&text, &symbols, &relocations)
+}
+
+/// Returns the assembly symbol of the hai function `name`.
+pub fn symbol_name(name: &str) -> String {
+    format!("_hai_{}", name)
+}
+
+struct FunctionSelector<'a> {
+    module: &'a Module,
+    function: &'a Function,
+    /// The registers given to the allocator, `Location::Register` indexes this list.
+    registers: &'a [(Register, RegisterKind)],
+    allocation: Allocation,
+    /// The callee-saved registers used by the function.
+    saved_registers: Vec<Register>,
+    instructions: Vec<Instruction>,
+}
+
+impl<'a> FunctionSelector<'a> {
+    fn new(
+        module: &'a Module,
+        function: &'a Function,
+        registers: &'a [(Register, RegisterKind)],
+    ) -> Self {
+        let kinds: Vec<RegisterKind> = registers.iter().map(|&(_, kind)| kind).collect();
+        let allocation = regalloc::allocate_registers(function, &kinds);
+        let saved_registers = (0..registers.len())
+            .filter(|&index| {
+                registers[index].1 == RegisterKind::CalleeSaved && allocation.uses_register(index)
+            })
+            .map(|index| registers[index].0)
+            .collect();
+
+        FunctionSelector {
+            module,
+            function,
+            registers,
+            allocation,
+            saved_registers,
+            instructions: Vec::new(),
+        }
+    }
+
+    fn select(mut self) -> MachineFunction {
+        self.emit(Instruction::Push(Register::Rbp));
+        self.emit(Instruction::Mov64(
+            Operand::Register(Register::Rsp),
+            Operand::Register(Register::Rbp),
+        ));
+
+        let slot_count = self.allocation.stack_slots()
+            + self.function.variables().len()
+            + self.register_params()
+            + self.saved_registers.len();
+        let frame_size = (slot_count * SLOT_SIZE).div_ceil(16) * 16;
+        if frame_size != 0 {
+            self.emit(Instruction::SubRsp(frame_size as i32));
+        }
+
+        for index in 0..self.saved_registers.len() {
+            let register = Operand::Register(self.saved_registers[index]);
+            self.emit(Instruction::Mov64(
+                register,
+                self.saved_register_slot(index),
+            ));
+        }
+
+        // parameters passed in registers are saved in the frame, as calls clobber the registers
+        for (index, &register) in PARAM_REGISTERS
+            .iter()
+            .enumerate()
+            .take(self.register_params())
+        {
+            let slot = self.param_slot(index);
+            self.emit(Instruction::Mov32(Operand::Register(register), slot));
+        }
+
+        for (index, bb) in self.function.basic_blocks().iter().enumerate() {
+            self.emit(Instruction::Label(BB(index)));
+            for statement in bb.statements() {
+                self.select_statement(statement);
+            }
+            self.select_terminator(bb.terminator());
+        }
+
+        MachineFunction {
+            symbol: symbol_name(self.function.name()),
+            instructions: self.instructions,
+        }
+    }
+
+    fn select_statement(&mut self, statement: &Statement) {
+        let (destination, operation) = match *statement {
+            Statement::WithDestination(value, ref operation) => (Some(value), operation),
+            Statement::Operation(ref operation) => (None, operation),
+        };
+
+        match *operation {
+            Operation::Literal(ref literal) => {
+                let constant = match *literal {
+                    Literal::Unit => 0,
+                    Literal::Int(i) => i,
+                    Literal::Boolean(b) => b as i32,
+                };
+                if let Some(destination) = destination {
+                    let destination = self.operand(destination);
+                    self.emit(Instruction::Mov32(
+                        Operand::Immediate(constant),
+                        destination,
+                    ));
+                }
+                return;
+            }
+            Operation::BinaryAdd(lhs, rhs) => self.select_binary(BinaryOp::Add, lhs, rhs),
+            Operation::BinarySub(lhs, rhs) => self.select_binary(BinaryOp::Sub, lhs, rhs),
+            Operation::BinaryMul(lhs, rhs) => self.select_binary(BinaryOp::Imul, lhs, rhs),
+            Operation::BinaryDivide(lhs, rhs) => {
+                self.load(lhs, Register::Rax);
+                self.emit(Instruction::Cltd);
+                let rhs = self.operand(rhs);
+                self.emit(Instruction::Idiv32(rhs));
+            }
+            Operation::BinaryShiftLeft(lhs, rhs) => {
+                self.load(lhs, Register::Rax);
+                self.load(rhs, Register::Rcx);
+                self.emit(Instruction::ShlCl32(Register::Rax));
+            }
+            Operation::Negate(value) => {
+                self.load(value, Register::Rax);
+                self.emit(Instruction::Neg32(Register::Rax));
+            }
+            Operation::Compare(comparison, lhs, rhs) => {
+                self.select_binary(BinaryOp::Cmp, lhs, rhs);
+                self.emit(Instruction::SetAl(comparison));
+                self.emit(Instruction::ZeroExtendAl);
+            }
+            Operation::LoadParam(index) => {
+                let slot = self.param_slot(index);
+                self.load_memory(slot, destination);
+                return;
+            }
+            Operation::LoadVariable(var) => {
+                let slot = self.variable_slot(var);
+                self.load_memory(slot, destination);
+                return;
+            }
+            Operation::StoreVariable(var, value) => {
+                self.load(value, Register::Rax);
+                let slot = self.variable_slot(var);
+                self.emit(Instruction::Mov32(Operand::Register(Register::Rax), slot));
+            }
+            Operation::Call { callee, ref args } => self.select_call(callee, args),
+        }
+
+        if let Some(destination) = destination {
+            let destination = self.operand(destination);
+            self.emit(Instruction::Mov32(
+                Operand::Register(Register::Rax),
+                destination,
+            ));
+        }
+    }
+
+    /// Moves the memory operand `slot` to `destination`, going through `%eax` if both are in
+    /// memory.
+    fn load_memory(&mut self, slot: Operand, destination: Option<Value>) {
+        let destination = match destination {
+            Some(destination) => destination,
+            None => return,
+        };
+        match self.operand(destination) {
+            Operand::Register(register) => {
+                self.emit(Instruction::Mov32(slot, Operand::Register(register)))
+            }
+            destination => {
+                self.emit(Instruction::Mov32(slot, Operand::Register(Register::Rax)));
+                self.emit(Instruction::Mov32(
+                    Operand::Register(Register::Rax),
+                    destination,
+                ));
+            }
+        }
+    }
+
+    /// Computes `lhs op rhs` in `%eax`.
+    fn select_binary(&mut self, op: BinaryOp, lhs: Value, rhs: Value) {
+        self.load(lhs, Register::Rax);
+        let rhs = self.operand(rhs);
+        self.emit(Instruction::Binary32(op, rhs, Register::Rax));
+    }
+
+    fn select_call(&mut self, callee: usize, args: &[Value]) {
+        let stack_args = args.len().saturating_sub(PARAM_REGISTERS.len());
+        // the stack must stay 16-byte aligned at the call
+        let padding = if stack_args % 2 == 1 { SLOT_SIZE } else { 0 };
+        if padding != 0 {
+            self.emit(Instruction::SubRsp(padding as i32));
+        }
+        for &arg in args.iter().skip(PARAM_REGISTERS.len()).rev() {
+            self.load(arg, Register::Rax);
+            self.emit(Instruction::Push(Register::Rax));
+        }
+        for (&arg, &register) in args.iter().zip(PARAM_REGISTERS.iter()) {
+            self.load(arg, register);
+        }
+
+        let name = self.module.functions[callee].name();
+        self.emit(Instruction::Call(symbol_name(name)));
+
+        let cleanup = stack_args * SLOT_SIZE + padding;
+        if cleanup != 0 {
+            self.emit(Instruction::AddRsp(cleanup as i32));
+        }
+    }
+
+    fn select_terminator(&mut self, terminator: &Terminator) {
+        match *terminator {
+            Terminator::Return(value) => {
+                self.load(value, Register::Rax);
+                for index in 0..self.saved_registers.len() {
+                    let register = Operand::Register(self.saved_registers[index]);
+                    self.emit(Instruction::Mov64(
+                        self.saved_register_slot(index),
+                        register,
+                    ));
+                }
+                self.emit(Instruction::Leave);
+                self.emit(Instruction::Ret);
+            }
+            Terminator::Jump(bb) => self.emit(Instruction::Jump(bb)),
+            Terminator::Branch(cond, then_bb, else_bb) => {
+                let cond = self.operand(cond);
+                self.emit(Instruction::CmpImmediate32(0, cond));
+                self.emit(Instruction::JumpIfNotEqual(then_bb));
+                self.emit(Instruction::Jump(else_bb));
+            }
+            Terminator::Unreachable => self.emit(Instruction::Ud2),
+        }
+    }
+
+    fn load(&mut self, value: Value, register: Register) {
+        let operand = self.operand(value);
+        self.emit(Instruction::Mov32(operand, Operand::Register(register)));
+    }
+
+    fn register_params(&self) -> usize {
+        self.function.params().len().min(PARAM_REGISTERS.len())
+    }
+
+    fn operand(&self, value: Value) -> Operand {
+        match self.allocation.location(value) {
+            Some(Location::Register(register)) => Operand::Register(self.registers[register].0),
+            Some(Location::Stack(slot)) => frame_slot(slot),
+            None => panic!("{} is used but never defined", value),
+        }
+    }
+
+    fn variable_slot(&self, var: Variable) -> Operand {
+        frame_slot(self.allocation.stack_slots() + var.index())
+    }
+
+    fn saved_register_slot(&self, index: usize) -> Operand {
+        frame_slot(
+            self.allocation.stack_slots()
+                + self.function.variables().len()
+                + self.register_params()
+                + index,
+        )
+    }
+
+    fn param_slot(&self, index: usize) -> Operand {
+        if index < PARAM_REGISTERS.len() {
+            frame_slot(self.allocation.stack_slots() + self.function.variables().len() + index)
+        } else {
+            // pushed by the caller, above the return address and the saved frame pointer
+            Operand::Memory((16 + (index - PARAM_REGISTERS.len()) * SLOT_SIZE) as i32)
+        }
+    }
+
+    fn emit(&mut self, instruction: Instruction) {
+        self.instructions.push(instruction);
+    }
+}
+
+fn frame_slot(index: usize) -> Operand {
+    Operand::Memory(-(((index + 1) * SLOT_SIZE) as i32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the number of accesses to the stack frame and the size of the machine code of
+    /// `module`.
+    fn code_size(module: &Module, registers: &[(Register, RegisterKind)]) -> (usize, usize) {
+        let memory = |operand: Operand| matches!(operand, Operand::Memory(_)) as usize;
+        let mut accesses = 0;
+        let mut code = Vec::new();
+        for function in select_functions(module, registers) {
+            for instruction in &function.instructions {
+                accesses += match *instruction {
+                    Instruction::Mov32(src, dst) | Instruction::Mov64(src, dst) => {
+                        memory(src) + memory(dst)
+                    }
+                    Instruction::Binary32(_, operand, _)
+                    | Instruction::CmpImmediate32(_, operand)
+                    | Instruction::Idiv32(operand) => memory(operand),
+                    _ => 0,
+                };
+            }
+            encoder::encode_function(&function.instructions, &mut code, &mut Vec::new());
+        }
+        (accesses, code.len())
+    }
+
+    #[test]
+    fn test_register_allocation_shrinks_code() {
+        let source = include_str!("../../../examples_hai/loops.hai");
+        for opt_level in 0..=2 {
+            let module = crate::compile(source, opt_level).unwrap();
+            let (allocated_accesses, allocated_size) = code_size(&module, &REGISTERS);
+            // without registers, every value is spilled to a stack slot
+            let (spilled_accesses, spilled_size) = code_size(&module, &[]);
+            assert!(
+                allocated_accesses < spilled_accesses && allocated_size < spilled_size,
+                "-O{}: {} stack accesses and {} bytes with registers, {} and {} without",
+                opt_level,
+                allocated_accesses,
+                allocated_size,
+                spilled_accesses,
+                spilled_size
+            );
+        }
+    }
+}
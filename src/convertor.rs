@@ -1,38 +1,267 @@
 use crate::ast;
-use crate::ast::visitor::Visitor;
+use crate::ast::visitor::{TryVisitor, UnknownId};
 use crate::ir;
 
 use std::collections::HashMap;
+use std::fmt;
 
-pub fn ast2ir(program: &ast::Program) -> ir::Module {
-    let mut program_convertor = ProgramConvertor::default();
-    let module = program
-        .accept_program_visitor(&mut program_convertor)
-        .unwrap();
+/// A name resolution or type error found while lowering a program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    /// The function in which the error was found, if any.
+    pub function: Option<String>,
+    pub message: String,
+    /// The code the error is about.
+    pub span: ast::Span,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.function {
+            Some(ref function) => write!(f, "in function `{}`: {}", function, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Lowers `program` to the IR, or returns every error found in it.
+pub fn ast2ir(program: &ast::Program) -> Result<ir::Module, Vec<Error>> {
+    program.try_accept_program_visitor(&mut Convertor::new())
+}
+
+#[derive(Debug, Clone)]
+struct FunctionSignature {
+    index: usize,
+    params: Vec<ir::Type>,
+    return_ty: ir::Type,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TypedValue {
+    ty: ir::Type,
+    value: ir::Value,
+}
+
+/// Lowers the functions one after the other. The errors of a statement are reported and the
+/// next statements are still lowered, so that every error of a function is found at once.
+#[derive(Debug, Clone)]
+struct Convertor {
+    signatures: HashMap<String, FunctionSignature>,
+    /// The functions lowered so far, in declaration order.
+    functions: Vec<ir::Function>,
+
+    // the state of the function being lowered
+    name: String,
+    return_ty: ir::Type,
+    sym_table: SymbolTable,
+    builder: ir::builder::FunctionBuilder,
+    reachable: bool,
+    /// The statement or expression being lowered, errors are reported on it.
+    span: ast::Span,
+    end_span: ast::Span,
+}
+
+impl Convertor {
+    fn new() -> Self {
+        Convertor {
+            signatures: HashMap::new(),
+            functions: Vec::new(),
+            name: String::new(),
+            return_ty: ir::Type::Unit,
+            sym_table: SymbolTable::new(),
+            builder: ir::builder::FunctionBuilder::new(String::new(), Vec::new(), ir::Type::Unit),
+            reachable: true,
+            span: ast::Span::default(),
+            end_span: ast::Span::default(),
+        }
+    }
+
+    /// Starts lowering a function, its parameters are stored in variables.
+    fn begin_function(
+        &mut self,
+        name: &str,
+        params: &[(String, ast::Type)],
+        return_ty: &ast::Type,
+    ) {
+        let param_tys: Vec<ir::Type> = params.iter().map(|(_, ty)| ty_ast2ir(*ty)).collect();
+        self.name = name.to_string();
+        self.return_ty = ty_ast2ir(*return_ty);
+        self.sym_table = SymbolTable::new();
+        self.sym_table.begin_scope();
+        self.builder =
+            ir::builder::FunctionBuilder::new(name.to_string(), param_tys.clone(), self.return_ty);
+        self.reachable = true;
+
+        for (index, (param, _)) in params.iter().enumerate() {
+            let var = self.builder.create_variable_param(index);
+            let value = self
+                .builder
+                .append_value_operation(ir::Operation::LoadParam(index));
+            self.builder
+                .append_no_value_operation(ir::Operation::StoreVariable(var, value));
+            self.sym_table
+                .register_variable(param.to_string(), param_tys[index], var);
+        }
+    }
+
+    /// Terminates the function being lowered and returns it, or the missing return.
+    fn finish_function(&mut self) -> Result<ir::Function, Vec<Error>> {
+        let result = if !self.reachable {
+            self.builder.terminate_bb(ir::Terminator::Unreachable);
+            Ok(())
+        } else if self.return_ty == ir::Type::Unit {
+            let unit = self.unit_value();
+            self.builder.terminate_bb(ir::Terminator::Return(unit));
+            Ok(())
+        } else {
+            self.builder.terminate_bb(ir::Terminator::Unreachable);
+            let message = "missing return at the end of the function".to_string();
+            Err(self.error_at(self.end_span, message))
+        };
+
+        let builder = std::mem::replace(
+            &mut self.builder,
+            ir::builder::FunctionBuilder::new(String::new(), Vec::new(), ir::Type::Unit),
+        );
+        result.map(|()| builder.into_function())
+    }
+
+    fn error(&self, message: String) -> Vec<Error> {
+        self.error_at(self.span, message)
+    }
 
-    module
+    fn error_at(&self, span: ast::Span, message: String) -> Vec<Error> {
+        vec![Error {
+            function: Some(self.name.clone()),
+            message,
+            span,
+        }]
+    }
+
+    fn lower_statement(
+        &mut self,
+        program: &ast::Program,
+        statement: ast::StatementId,
+    ) -> Result<(), Vec<Error>> {
+        let statement_span = match program.statement_span(statement) {
+            Some(span) => span,
+            None => return Err(self.unknown_id(program, UnknownId::Statement(statement))),
+        };
+        let span = std::mem::replace(&mut self.span, statement_span);
+        let result = program.try_accept_statement_visitor(self, statement);
+        self.span = span;
+        result
+    }
+
+    fn lower_expression(
+        &mut self,
+        program: &ast::Program,
+        expression: ast::ExpressionId,
+    ) -> Result<TypedValue, Vec<Error>> {
+        let expression_span = match program.expression_span(expression) {
+            Some(span) => span,
+            None => return Err(self.unknown_id(program, UnknownId::Expression(expression))),
+        };
+        let span = std::mem::replace(&mut self.span, expression_span);
+        let result = program.try_accept_expression_visitor(self, expression);
+        self.span = span;
+        result
+    }
+
+    /// Lowers a condition. On error, a placeholder value is returned along with the errors, so
+    /// that the statements depending on the condition are still checked.
+    fn lower_condition(
+        &mut self,
+        program: &ast::Program,
+        condition: ast::ExpressionId,
+    ) -> (ir::Value, Vec<Error>) {
+        let errors = match self.lower_expression(program, condition) {
+            Ok(tv) if tv.ty == ir::Type::Boolean => return (tv.value, Vec::new()),
+            Ok(tv) => self.error_at(
+                program.expression_span(condition).unwrap_or(self.span),
+                format!("conditions must be of type bool, found {}", tv.ty),
+            ),
+            Err(errors) => errors,
+        };
+        let placeholder = self
+            .builder
+            .append_value_operation(ir::Operation::Literal(ir::Literal::Boolean(false)));
+        (placeholder, errors)
+    }
+
+    fn unit_value(&mut self) -> ir::Value {
+        self.builder
+            .append_value_operation(ir::Operation::Literal(ir::Literal::Unit))
+    }
 }
 
-#[derive(Debug, Clone, Default)]
-struct ProgramConvertor {}
+/// Returns the errors collected, if any.
+fn collected(errors: Vec<Error>) -> Result<(), Vec<Error>> {
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
 
-impl Visitor for ProgramConvertor {
+impl TryVisitor for Convertor {
     type ProgramItem = ir::Module;
-    type DeclarationItem = ir::Function;
+    type DeclarationItem = ();
     type StatementItem = ();
-    type ExpressionItem = ();
+    type ExpressionItem = TypedValue;
+    type Error = Vec<Error>;
+
+    /// Reports the id on the node referring to it.
+    fn unknown_id(&mut self, _program: &ast::Program, id: UnknownId) -> Vec<Error> {
+        self.error(id.to_string())
+    }
 
     fn visit_program(
         &mut self,
         program: &ast::Program,
         declarations: &[ast::Declaration],
-    ) -> Option<ir::Module> {
-        let functions = declarations
-            .iter()
-            .map(|decl| program.accept_declaration_visitor(self, decl).unwrap())
-            .collect();
+    ) -> Result<ir::Module, Vec<Error>> {
+        let mut errors = Vec::new();
+        for (index, decl) in declarations.iter().enumerate() {
+            match *decl {
+                ast::Declaration::FunctionDeclaration {
+                    ref name,
+                    ref parameters,
+                    ref return_ty,
+                    name_span,
+                    ..
+                } => {
+                    let signature = FunctionSignature {
+                        index,
+                        params: parameters.iter().map(|(_, ty)| ty_ast2ir(*ty)).collect(),
+                        return_ty: ty_ast2ir(*return_ty),
+                    };
+                    if self.signatures.contains_key(name) {
+                        errors.push(Error {
+                            function: None,
+                            message: format!("function `{}` is defined multiple times", name),
+                            span: name_span,
+                        });
+                    } else {
+                        self.signatures.insert(name.clone(), signature);
+                    }
+                }
+            }
+        }
 
-        Some(ir::Module { functions })
+        // every function is lowered, to report the errors of all of them
+        for decl in declarations {
+            let ast::Declaration::FunctionDeclaration { span, .. } = *decl;
+            self.span = span;
+            if let Err(function_errors) = program.try_accept_declaration_visitor(self, decl) {
+                errors.extend(function_errors);
+            }
+        }
+
+        collected(errors)?;
+        Ok(ir::Module {
+            functions: std::mem::take(&mut self.functions),
+        })
     }
 
     fn visit_function_declaration(
@@ -40,39 +269,364 @@ impl Visitor for ProgramConvertor {
         program: &ast::Program,
         name: &str,
         parameters: &[(String, ast::Type)],
+        return_ty: &ast::Type,
         statement: ast::StatementId,
-    ) -> Option<ir::Function> {
-        let mut function_convertor = FunctionConvertor::new(name, parameters);
-        unimplemented!()
+    ) -> Result<(), Vec<Error>> {
+        self.begin_function(name, parameters, return_ty);
+        let body_span = match program.statement_span(statement) {
+            Some(span) => span,
+            None => return Err(self.unknown_id(program, UnknownId::Statement(statement))),
+        };
+        self.end_span = ast::Span::new(body_span.end - 1, body_span.end);
+
+        let mut errors = self
+            .lower_statement(program, statement)
+            .err()
+            .unwrap_or_default();
+        match self.finish_function() {
+            Ok(function) if errors.is_empty() => self.functions.push(function),
+            Ok(_) => {}
+            Err(function_errors) => errors.extend(function_errors),
+        }
+        collected(errors)
     }
-}
 
-#[derive(Debug, Clone)]
-struct FunctionConvertor {
-    sym_table: SymbolTable,
-    builder: ir::builder::FunctionBuilder,
-}
+    fn visit_block_statement(
+        &mut self,
+        program: &ast::Program,
+        statements: &[ast::StatementId],
+    ) -> Result<(), Vec<Error>> {
+        self.sym_table.begin_scope();
+        let mut errors = Vec::new();
+        for &s in statements {
+            if let Err(statement_errors) = self.lower_statement(program, s) {
+                errors.extend(statement_errors);
+            }
+        }
+        self.sym_table.end_scope();
+        collected(errors)
+    }
+
+    fn visit_let_statement(
+        &mut self,
+        program: &ast::Program,
+        identifier: &str,
+        expression: ast::ExpressionId,
+    ) -> Result<(), Vec<Error>> {
+        let tv = self.lower_expression(program, expression)?;
+        let var = self.builder.create_variable(tv.ty);
+        self.builder
+            .append_no_value_operation(ir::Operation::StoreVariable(var, tv.value));
+        self.sym_table
+            .register_variable(identifier.to_string(), tv.ty, var);
+        Ok(())
+    }
+
+    fn visit_return_statement(
+        &mut self,
+        program: &ast::Program,
+        expression: Option<ast::ExpressionId>,
+    ) -> Result<(), Vec<Error>> {
+        let tv = match expression {
+            Some(expression) => self.lower_expression(program, expression),
+            None => Ok(TypedValue {
+                ty: ir::Type::Unit,
+                value: self.unit_value(),
+            }),
+        };
+
+        // an invalid return still ends the function, not to report a missing return after it
+        let result = match tv {
+            Ok(tv) if tv.ty == self.return_ty => {
+                self.builder.terminate_bb(ir::Terminator::Return(tv.value));
+                Ok(())
+            }
+            Ok(tv) => {
+                self.builder.terminate_bb(ir::Terminator::Unreachable);
+                Err(self.error(format!(
+                    "the function returns {} but a value of type {} is returned",
+                    self.return_ty, tv.ty
+                )))
+            }
+            Err(errors) => {
+                self.builder.terminate_bb(ir::Terminator::Unreachable);
+                Err(errors)
+            }
+        };
+
+        // following statements are unreachable, they are lowered in a fresh block
+        let next_bb = self.builder.create_new_bb();
+        self.builder.switch_to_bb(next_bb);
+        self.reachable = false;
+        result
+    }
+
+    fn visit_while_statement(
+        &mut self,
+        program: &ast::Program,
+        condition: ast::ExpressionId,
+        body: ast::StatementId,
+    ) -> Result<(), Vec<Error>> {
+        let header_bb = self.builder.create_new_bb();
+        let body_bb = self.builder.create_new_bb();
+        let exit_bb = self.builder.create_new_bb();
 
-impl FunctionConvertor {
-    pub fn new(name: &str, params: &[(String, ast::Type)]) -> Self {
-        let mut param_tys = Vec::new();
-        for &(_, ref ty) in params {
-            param_tys.push(ty_ast2ir(ty.clone()));
+        self.builder.terminate_bb(ir::Terminator::Jump(header_bb));
+        self.builder.switch_to_bb(header_bb);
+        let (cond, mut errors) = self.lower_condition(program, condition);
+        self.builder
+            .terminate_bb(ir::Terminator::Branch(cond, body_bb, exit_bb));
+
+        // the loop may not be entered, so reaching the exit only depends on reaching the loop
+        let reachable = self.reachable;
+        self.builder.switch_to_bb(body_bb);
+        if let Err(body_errors) = self.lower_statement(program, body) {
+            errors.extend(body_errors);
         }
+        self.builder.terminate_bb(ir::Terminator::Jump(header_bb));
+
+        self.builder.switch_to_bb(exit_bb);
+        self.reachable = reachable;
+        collected(errors)
+    }
 
-        let mut sym_table = SymbolTable::new();
-        sym_table.begin_scope(); // TODO
-        let mut builder = ir::builder::FunctionBuilder::new(name.to_string(), param_tys.clone());
+    fn visit_if_statement(
+        &mut self,
+        program: &ast::Program,
+        condition: ast::ExpressionId,
+        then_statement: ast::StatementId,
+        else_statement: Option<ast::StatementId>,
+    ) -> Result<(), Vec<Error>> {
+        let (cond, mut errors) = self.lower_condition(program, condition);
+        let then_bb = self.builder.create_new_bb();
+        let else_bb = self.builder.create_new_bb();
+        let merge_bb = match else_statement {
+            Some(_) => self.builder.create_new_bb(),
+            None => else_bb,
+        };
+        self.builder
+            .terminate_bb(ir::Terminator::Branch(cond, then_bb, else_bb));
 
-        for (index, &(ref param, _)) in params.iter().enumerate() {
-            let var = builder.create_variable_param(index);
-            sym_table.register_variable(param.to_string(), param_tys[index].clone(), var);
+        let reachable = self.reachable;
+        self.builder.switch_to_bb(then_bb);
+        if let Err(then_errors) = self.lower_statement(program, then_statement) {
+            errors.extend(then_errors);
         }
+        self.builder.terminate_bb(ir::Terminator::Jump(merge_bb));
+        let then_reachable = self.reachable;
 
-        FunctionConvertor {
-            sym_table: SymbolTable::new(),
-            builder: ir::builder::FunctionBuilder::new(name.to_string(), param_tys),
+        self.reachable = reachable;
+        if let Some(else_statement) = else_statement {
+            self.builder.switch_to_bb(else_bb);
+            if let Err(else_errors) = self.lower_statement(program, else_statement) {
+                errors.extend(else_errors);
+            }
+            self.builder.terminate_bb(ir::Terminator::Jump(merge_bb));
+        }
+
+        self.builder.switch_to_bb(merge_bb);
+        self.reachable |= then_reachable;
+        collected(errors)
+    }
+
+    fn visit_assign_statement(
+        &mut self,
+        program: &ast::Program,
+        identifier: &str,
+        expression: ast::ExpressionId,
+    ) -> Result<(), Vec<Error>> {
+        let tv = self.lower_expression(program, expression)?;
+        let var = match self.sym_table.lookup_variable(identifier) {
+            Some(var) => var,
+            None => return Err(self.error(format!("unknown variable `{}`", identifier))),
+        };
+
+        if var.ty != tv.ty {
+            return Err(self.error(format!(
+                "variable `{}` has type {} but a value of type {} is assigned",
+                identifier, var.ty, tv.ty
+            )));
+        }
+
+        self.builder
+            .append_no_value_operation(ir::Operation::StoreVariable(var.var, tv.value));
+        Ok(())
+    }
+
+    fn visit_expression_statement(
+        &mut self,
+        program: &ast::Program,
+        expression: ast::ExpressionId,
+    ) -> Result<(), Vec<Error>> {
+        self.lower_expression(program, expression)?;
+        Ok(())
+    }
+
+    fn visit_binop_expression(
+        &mut self,
+        program: &ast::Program,
+        op: ast::BinOp,
+        lhs: ast::ExpressionId,
+        rhs: ast::ExpressionId,
+    ) -> Result<TypedValue, Vec<Error>> {
+        // both operands are lowered, to report the errors of both
+        let (lhs, rhs) = match (
+            self.lower_expression(program, lhs),
+            self.lower_expression(program, rhs),
+        ) {
+            (Ok(lhs), Ok(rhs)) => (lhs, rhs),
+            (lhs, rhs) => return Err(lhs.err().into_iter().chain(rhs.err()).flatten().collect()),
+        };
+
+        let (operation, ty) = match op {
+            ast::BinOp::Add => (
+                ir::Operation::BinaryAdd(lhs.value, rhs.value),
+                ir::Type::Int,
+            ),
+            ast::BinOp::Sub => (
+                ir::Operation::BinarySub(lhs.value, rhs.value),
+                ir::Type::Int,
+            ),
+            ast::BinOp::Mul => (
+                ir::Operation::BinaryMul(lhs.value, rhs.value),
+                ir::Type::Int,
+            ),
+            ast::BinOp::Divide => (
+                ir::Operation::BinaryDivide(lhs.value, rhs.value),
+                ir::Type::Int,
+            ),
+            ast::BinOp::Equal => (
+                ir::Operation::Compare(ir::Comparison::Equal, lhs.value, rhs.value),
+                ir::Type::Boolean,
+            ),
+            ast::BinOp::NotEqual => (
+                ir::Operation::Compare(ir::Comparison::NotEqual, lhs.value, rhs.value),
+                ir::Type::Boolean,
+            ),
+            ast::BinOp::Less => (
+                ir::Operation::Compare(ir::Comparison::Less, lhs.value, rhs.value),
+                ir::Type::Boolean,
+            ),
+            ast::BinOp::LessEqual => (
+                ir::Operation::Compare(ir::Comparison::LessEqual, lhs.value, rhs.value),
+                ir::Type::Boolean,
+            ),
+            ast::BinOp::Greater => (
+                ir::Operation::Compare(ir::Comparison::Greater, lhs.value, rhs.value),
+                ir::Type::Boolean,
+            ),
+            ast::BinOp::GreaterEqual => (
+                ir::Operation::Compare(ir::Comparison::GreaterEqual, lhs.value, rhs.value),
+                ir::Type::Boolean,
+            ),
+        };
+
+        let valid_operands = match op {
+            // equality is defined on every type, as long as both sides agree
+            ast::BinOp::Equal | ast::BinOp::NotEqual => lhs.ty == rhs.ty,
+            _ => lhs.ty == ir::Type::Int && rhs.ty == ir::Type::Int,
+        };
+        if !valid_operands {
+            return Err(self.error(format!(
+                "operator `{}` can't be applied to {} and {}",
+                op, lhs.ty, rhs.ty
+            )));
         }
+
+        Ok(TypedValue {
+            ty,
+            value: self.builder.append_value_operation(operation),
+        })
+    }
+
+    fn visit_func_call(
+        &mut self,
+        program: &ast::Program,
+        func: ast::ExpressionId,
+        args: &[ast::ExpressionId],
+    ) -> Result<TypedValue, Vec<Error>> {
+        let name = match program.get_expression(func) {
+            Some(ast::Expression::Identifier(name)) => name,
+            _ => return Err(self.error("only named functions can be called".to_string())),
+        };
+        let signature = match self.signatures.get(name) {
+            Some(signature) => signature.clone(),
+            None => return Err(self.error(format!("unknown function `{}`", name))),
+        };
+
+        if args.len() != signature.params.len() {
+            return Err(self.error(format!(
+                "function `{}` takes {} arguments but {} were supplied",
+                name,
+                signature.params.len(),
+                args.len()
+            )));
+        }
+
+        let mut arg_values = Vec::with_capacity(args.len());
+        let mut errors = Vec::new();
+        for (&arg, &param_ty) in args.iter().zip(&signature.params) {
+            match self.lower_expression(program, arg) {
+                Ok(tv) if tv.ty == param_ty => arg_values.push(tv.value),
+                Ok(tv) => errors.extend(self.error_at(
+                    program.expression_span(arg).unwrap_or(self.span),
+                    format!(
+                        "function `{}` expects an argument of type {}, found {}",
+                        name, param_ty, tv.ty
+                    ),
+                )),
+                Err(arg_errors) => errors.extend(arg_errors),
+            }
+        }
+        collected(errors)?;
+
+        let value = self.builder.append_value_operation(ir::Operation::Call {
+            callee: signature.index,
+            args: arg_values,
+        });
+        Ok(TypedValue {
+            ty: signature.return_ty,
+            value,
+        })
+    }
+
+    fn visit_integer(&mut self, _program: &ast::Program, i: i32) -> Result<TypedValue, Vec<Error>> {
+        let value = self
+            .builder
+            .append_value_operation(ir::Operation::Literal(ir::Literal::Int(i)));
+        Ok(TypedValue {
+            ty: ir::Type::Int,
+            value,
+        })
+    }
+
+    fn visit_boolean(
+        &mut self,
+        _program: &ast::Program,
+        b: bool,
+    ) -> Result<TypedValue, Vec<Error>> {
+        let value = self
+            .builder
+            .append_value_operation(ir::Operation::Literal(ir::Literal::Boolean(b)));
+        Ok(TypedValue {
+            ty: ir::Type::Boolean,
+            value,
+        })
+    }
+
+    fn visit_identifier(
+        &mut self,
+        _program: &ast::Program,
+        id: &str,
+    ) -> Result<TypedValue, Vec<Error>> {
+        let tv = match self.sym_table.lookup_variable(id) {
+            Some(tv) => tv,
+            None => return Err(self.error(format!("unknown variable `{}`", id))),
+        };
+        let value = self
+            .builder
+            .append_value_operation(ir::Operation::LoadVariable(tv.var));
+        Ok(TypedValue { ty: tv.ty, value })
     }
 }
 
@@ -124,3 +678,61 @@ impl SymbolTable {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ast::{Declaration, Span, Statement};
+
+    #[test]
+    fn test_ids_of_another_program() {
+        let other = crate::parse("fn g() -> int { return 2; }").unwrap();
+        let Declaration::FunctionDeclaration { statement, .. } = other.declarations[0];
+        let other_body = statement;
+        let other_value = match other.get_statement(other_body) {
+            Some(Statement::BlockStatement(statements)) => match other.get_statement(statements[0])
+            {
+                Some(&Statement::ReturnStatement(Some(expression))) => expression,
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        };
+
+        // the unknown body is reported on the declaration
+        let mut program = crate::parse("fn f() -> int { return 1; }").unwrap();
+        let Declaration::FunctionDeclaration {
+            ref mut statement,
+            span,
+            ..
+        } = program.declarations[0];
+        *statement = other_body;
+        assert_eq!(
+            ast2ir(&program).unwrap_err(),
+            [Error {
+                function: Some("f".to_string()),
+                message: format!("unknown statement {}", other_body.index()),
+                span,
+            }]
+        );
+
+        // and an unknown expression on its statement
+        let return_span = Span::new(16, 25);
+        let body =
+            vec![program
+                .create_statement(Statement::ReturnStatement(Some(other_value)), return_span)];
+        let body = program.create_statement(Statement::BlockStatement(body), Span::new(14, 27));
+        let Declaration::FunctionDeclaration {
+            ref mut statement, ..
+        } = program.declarations[0];
+        *statement = body;
+        assert_eq!(
+            ast2ir(&program).unwrap_err(),
+            [Error {
+                function: Some("f".to_string()),
+                message: format!("unknown expression {}", other_value.index()),
+                span: return_span,
+            }]
+        );
+    }
+}
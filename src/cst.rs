@@ -0,0 +1,320 @@
+//! The concrete syntax tree, a lossless view of the source.
+//!
+//! Every character of the source is in exactly one token of the tree, including the whitespace
+//! and the comments, so writing the tokens in order gives the source back. Trivia tokens are kept
+//! in the innermost node around them. The [`ast::Program`](crate::ast::Program) is derived from
+//! the tree by [`parser::convert_program`](crate::parser::convert_program).
+
+use crate::ast::Span;
+
+use std::fmt;
+
+pub use crate::lexer::Token;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SyntaxKind {
+    // trivia
+    Whitespace,
+    LineComment,
+    BlockComment,
+
+    // tokens
+    Identifier,
+    Integer,
+    FnKeyword,
+    LetKeyword,
+    ReturnKeyword,
+    WhileKeyword,
+    IfKeyword,
+    ElseKeyword,
+    TrueKeyword,
+    FalseKeyword,
+    LeftParen,
+    RightParen,
+    LeftBrace,
+    RightBrace,
+    Comma,
+    Colon,
+    Semicolon,
+    Arrow,
+    Equal,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    EqualEqual,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    /// A character that can't start a token or an unterminated comment, and as a node, code that
+    /// can't be parsed.
+    Error,
+
+    // nodes
+    Program,
+    FunctionDeclaration,
+    ParameterList,
+    Parameter,
+    Type,
+    BlockStatement,
+    LetStatement,
+    ReturnStatement,
+    WhileStatement,
+    IfStatement,
+    AssignStatement,
+    ExpressionStatement,
+    BinaryExpression,
+    ParenExpression,
+    FunctionCall,
+    ExpressionList,
+    /// An integer or a boolean.
+    Literal,
+    /// An identifier used as an expression.
+    Name,
+}
+
+impl SyntaxKind {
+    /// Returns the kind of a word, a keyword or an identifier.
+    pub fn from_keyword(word: &str) -> SyntaxKind {
+        match word {
+            "fn" => SyntaxKind::FnKeyword,
+            "let" => SyntaxKind::LetKeyword,
+            "return" => SyntaxKind::ReturnKeyword,
+            "while" => SyntaxKind::WhileKeyword,
+            "if" => SyntaxKind::IfKeyword,
+            "else" => SyntaxKind::ElseKeyword,
+            "true" => SyntaxKind::TrueKeyword,
+            "false" => SyntaxKind::FalseKeyword,
+            _ => SyntaxKind::Identifier,
+        }
+    }
+
+    /// Returns the kind as it is named in error messages, like "`;`" or "an identifier".
+    pub fn description(self) -> &'static str {
+        match self {
+            SyntaxKind::Whitespace => "whitespace",
+            SyntaxKind::LineComment | SyntaxKind::BlockComment => "a comment",
+            SyntaxKind::Identifier => "an identifier",
+            SyntaxKind::Integer => "an integer",
+            SyntaxKind::FnKeyword => "`fn`",
+            SyntaxKind::LetKeyword => "`let`",
+            SyntaxKind::ReturnKeyword => "`return`",
+            SyntaxKind::WhileKeyword => "`while`",
+            SyntaxKind::IfKeyword => "`if`",
+            SyntaxKind::ElseKeyword => "`else`",
+            SyntaxKind::TrueKeyword => "`true`",
+            SyntaxKind::FalseKeyword => "`false`",
+            SyntaxKind::LeftParen => "`(`",
+            SyntaxKind::RightParen => "`)`",
+            SyntaxKind::LeftBrace => "`{`",
+            SyntaxKind::RightBrace => "`}`",
+            SyntaxKind::Comma => "`,`",
+            SyntaxKind::Colon => "`:`",
+            SyntaxKind::Semicolon => "`;`",
+            SyntaxKind::Arrow => "`->`",
+            SyntaxKind::Equal => "`=`",
+            SyntaxKind::Plus => "`+`",
+            SyntaxKind::Minus => "`-`",
+            SyntaxKind::Star => "`*`",
+            SyntaxKind::Slash => "`/`",
+            SyntaxKind::EqualEqual => "`==`",
+            SyntaxKind::NotEqual => "`!=`",
+            SyntaxKind::Less => "`<`",
+            SyntaxKind::LessEqual => "`<=`",
+            SyntaxKind::Greater => "`>`",
+            SyntaxKind::GreaterEqual => "`>=`",
+            SyntaxKind::Error => "invalid code",
+            SyntaxKind::Program => "a program",
+            SyntaxKind::FunctionDeclaration => "a function",
+            SyntaxKind::ParameterList => "parameters",
+            SyntaxKind::Parameter => "a parameter",
+            SyntaxKind::Type => "a type",
+            SyntaxKind::BlockStatement => "a block",
+            SyntaxKind::LetStatement
+            | SyntaxKind::ReturnStatement
+            | SyntaxKind::WhileStatement
+            | SyntaxKind::IfStatement
+            | SyntaxKind::AssignStatement
+            | SyntaxKind::ExpressionStatement => "a statement",
+            SyntaxKind::BinaryExpression
+            | SyntaxKind::ParenExpression
+            | SyntaxKind::FunctionCall
+            | SyntaxKind::Literal
+            | SyntaxKind::Name => "an expression",
+            SyntaxKind::ExpressionList => "arguments",
+        }
+    }
+
+    pub fn is_trivia(self) -> bool {
+        matches!(
+            self,
+            SyntaxKind::Whitespace | SyntaxKind::LineComment | SyntaxKind::BlockComment
+        )
+    }
+
+    pub fn is_comment(self) -> bool {
+        self == SyntaxKind::LineComment || self == SyntaxKind::BlockComment
+    }
+
+    pub fn is_statement(self) -> bool {
+        matches!(
+            self,
+            SyntaxKind::BlockStatement
+                | SyntaxKind::LetStatement
+                | SyntaxKind::ReturnStatement
+                | SyntaxKind::WhileStatement
+                | SyntaxKind::IfStatement
+                | SyntaxKind::AssignStatement
+                | SyntaxKind::ExpressionStatement
+        )
+    }
+
+    pub fn is_expression(self) -> bool {
+        matches!(
+            self,
+            SyntaxKind::BinaryExpression
+                | SyntaxKind::ParenExpression
+                | SyntaxKind::FunctionCall
+                | SyntaxKind::Literal
+                | SyntaxKind::Name
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Element {
+    Node(Node),
+    Token(Token),
+}
+
+impl Element {
+    fn collect_tokens<'t>(&'t self, tokens: &mut Vec<&'t Token>) {
+        match *self {
+            Element::Node(ref node) => {
+                for child in &node.children {
+                    child.collect_tokens(tokens);
+                }
+            }
+            Element::Token(ref token) => tokens.push(token),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Node {
+    pub kind: SyntaxKind,
+    children: Vec<Element>,
+    /// The code of the node, computed once as the first and last tokens can be deep in long
+    /// expressions.
+    span: Option<Span>,
+}
+
+impl Node {
+    pub fn new(kind: SyntaxKind, children: Vec<Element>) -> Self {
+        let mut span: Option<Span> = None;
+        for child in &children {
+            let child_span = match *child {
+                Element::Node(ref node) => node.span,
+                Element::Token(ref token) if !token.kind.is_trivia() => {
+                    Some(Span::new(token.offset, token.end()))
+                }
+                Element::Token(_) => None,
+            };
+            if let Some(child_span) = child_span {
+                span = Some(span.map_or(child_span, |span| span.to(child_span)));
+            }
+        }
+        Node {
+            kind,
+            children,
+            span,
+        }
+    }
+
+    pub fn children(&self) -> &[Element] {
+        &self.children
+    }
+
+    /// Returns every token of the node in source order, trivia included.
+    pub fn tokens(&self) -> Vec<&Token> {
+        let mut tokens = Vec::new();
+        for child in &self.children {
+            child.collect_tokens(&mut tokens);
+        }
+        tokens
+    }
+
+    /// Returns the code of the node without the trivia around it. A node without any code, like
+    /// an empty parameter list, has an empty span.
+    pub fn span(&self) -> Span {
+        self.span.unwrap_or_default()
+    }
+
+    pub fn child_nodes(&self) -> impl Iterator<Item = &Node> {
+        self.children.iter().filter_map(|child| match *child {
+            Element::Node(ref node) => Some(node),
+            Element::Token(_) => None,
+        })
+    }
+
+    /// Returns the direct tokens of the node, without the trivia.
+    pub fn child_tokens(&self) -> impl Iterator<Item = &Token> {
+        self.children.iter().filter_map(|child| match *child {
+            Element::Token(ref token) if !token.kind.is_trivia() => Some(token),
+            _ => None,
+        })
+    }
+
+    pub fn child_token(&self, kind: SyntaxKind) -> Option<&Token> {
+        self.child_tokens().find(|token| token.kind == kind)
+    }
+
+    pub fn child_node(&self, kind: SyntaxKind) -> Option<&Node> {
+        self.child_nodes().find(|node| node.kind == kind)
+    }
+
+    /// Returns the tree as indented lines, one per node or token.
+    pub fn dump(&self) -> String {
+        let mut output = String::new();
+        self.dump_into(&mut output, 0);
+        output
+    }
+
+    fn dump_into(&self, output: &mut String, depth: usize) {
+        let span = self.span();
+        output.push_str(&format!(
+            "{:indent$}{:?}@{}..{}\n",
+            "",
+            self.kind,
+            span.start,
+            span.end,
+            indent = depth * 2
+        ));
+        for child in &self.children {
+            match *child {
+                Element::Node(ref node) => node.dump_into(output, depth + 1),
+                Element::Token(ref token) => output.push_str(&format!(
+                    "{:indent$}{:?}@{}..{} {:?}\n",
+                    "",
+                    token.kind,
+                    token.offset,
+                    token.end(),
+                    token.text,
+                    indent = (depth + 1) * 2
+                )),
+            }
+        }
+    }
+}
+
+/// Writes the source of the node back.
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for token in self.tokens() {
+            f.write_str(&token.text)?;
+        }
+        Ok(())
+    }
+}
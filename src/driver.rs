@@ -0,0 +1,478 @@
+//! The command-line interface of the compiler: the `hai` binary only passes its arguments to
+//! [`main`].
+
+use crate::{ast, backend, ir, vm};
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+pub const USAGE: &str = "\
+usage: hai <command> [options] <file>
+
+Compiles hai source files, or textual IR files when the extension is `.ir`.
+
+commands:
+    check               check the file for errors
+    build               compile the file to an executable, or to the format given by --emit
+    run                 compile the file and run its `main` function in the virtual machine
+    fmt                 format the file in place, or into the output file
+
+options:
+    --emit=<kind>       with `build`, write the program as one of:
+                        tokens, cst, ast, ir, ir-opt, asm, obj, c, wat, bytecode, cfg-dot
+    -O<level>           optimization level, from 0 to 2 (default 0)
+    -o <file>           output file, the standard output by default for --emit
+    --dominators        add the dominator tree to the graphs of --emit=cfg-dot
+    --integrated-as     encode the machine code directly instead of using the system assembler
+    --check             with `fmt`, fail if the file isn't formatted instead of formatting it
+    -h, --help          print this message
+
+exit status:
+    0 on success, 1 on compilation errors, 2 on invalid usage, 3 on input/output or toolchain
+    errors, 4 on runtime errors; `fmt --check` exits with 1 if the file isn't formatted, and `run`
+    prints the value returned by `main` on the standard output";
+
+const EMIT_KINDS: [&str; 11] = [
+    "tokens", "cst", "ast", "ir", "ir-opt", "asm", "obj", "c", "wat", "bytecode", "cfg-dot",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Check,
+    Build,
+    Run,
+    Fmt,
+}
+
+#[derive(Debug)]
+pub struct Options {
+    pub command: Command,
+    pub input_path: String,
+    pub output_path: Option<String>,
+    pub opt_level: u32,
+    pub emit: Option<String>,
+    /// Add the dominator tree to the CFG graphs.
+    pub dominators: bool,
+    /// Encode the machine code directly instead of using the system assembler.
+    pub integrated_as: bool,
+    /// Only check that the file is formatted.
+    pub check: bool,
+}
+
+/// The reasons the compiler can stop, each having its own exit status.
+#[derive(Debug)]
+pub enum Failure {
+    Usage(String),
+    Compilation(Vec<String>),
+    Io(String),
+    Runtime(String),
+}
+
+impl From<crate::Error> for Failure {
+    fn from(error: crate::Error) -> Self {
+        match error {
+            crate::Error::Semantic(errors) => {
+                Failure::Compilation(errors.iter().map(ToString::to_string).collect())
+            }
+            error => Failure::Compilation(vec![error.to_string()]),
+        }
+    }
+}
+
+impl Failure {
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            Failure::Compilation(_) => 1,
+            Failure::Usage(_) => 2,
+            Failure::Io(_) => 3,
+            Failure::Runtime(_) => 4,
+        }
+    }
+
+    pub fn report(&self) {
+        match *self {
+            Failure::Usage(ref message) => {
+                eprintln!("error: {}", message);
+                eprintln!("run `hai --help` for the usage");
+            }
+            Failure::Compilation(ref errors) => {
+                for error in errors {
+                    eprintln!("error: {}", error);
+                }
+            }
+            Failure::Io(ref message) => eprintln!("error: {}", message),
+            Failure::Runtime(ref message) => eprintln!("runtime error: {}", message),
+        }
+    }
+}
+
+/// Runs the command given by the arguments, without the program name, and returns the exit
+/// status of the compiler.
+pub fn main(args: Vec<String>) -> i32 {
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", USAGE);
+        return 0;
+    }
+
+    match parse_args(args).and_then(|options| execute(&options)) {
+        Ok(()) => 0,
+        Err(failure) => {
+            failure.report();
+            failure.exit_code()
+        }
+    }
+}
+
+pub fn parse_args(args: Vec<String>) -> Result<Options, Failure> {
+    let mut args = args.into_iter();
+    let command = match args.next().as_deref() {
+        Some("check") => Command::Check,
+        Some("build") => Command::Build,
+        Some("run") => Command::Run,
+        Some("fmt") => Command::Fmt,
+        Some(command) => return Err(Failure::Usage(format!("unknown command `{}`", command))),
+        None => return Err(Failure::Usage("no command given".to_string())),
+    };
+
+    let mut input_path = None;
+    let mut options = Options {
+        command,
+        input_path: String::new(),
+        output_path: None,
+        opt_level: 0,
+        emit: None,
+        dominators: false,
+        integrated_as: false,
+        check: false,
+    };
+    while let Some(arg) = args.next() {
+        if arg == "-o" {
+            match args.next() {
+                Some(path) => options.output_path = Some(path),
+                None => return Err(Failure::Usage("missing file after `-o`".to_string())),
+            }
+        } else if let Some(level) = arg.strip_prefix("-O") {
+            options.opt_level = match level.parse() {
+                Ok(level) if level <= 2 => level,
+                _ => {
+                    return Err(Failure::Usage(format!(
+                        "invalid optimization level `{}`",
+                        level
+                    )))
+                }
+            };
+        } else if let Some(kind) = arg.strip_prefix("--emit=") {
+            if !EMIT_KINDS.contains(&kind) {
+                return Err(Failure::Usage(format!("unknown emit kind `{}`", kind)));
+            }
+            options.emit = Some(kind.to_string());
+        } else if arg == "--dominators" {
+            options.dominators = true;
+        } else if arg == "--integrated-as" {
+            options.integrated_as = true;
+        } else if arg == "--check" {
+            options.check = true;
+        } else if arg.starts_with('-') {
+            return Err(Failure::Usage(format!("unknown option `{}`", arg)));
+        } else if input_path.is_some() {
+            return Err(Failure::Usage(
+                "only one input file can be given".to_string(),
+            ));
+        } else {
+            input_path = Some(arg);
+        }
+    }
+
+    options.input_path = input_path.ok_or_else(|| Failure::Usage("no input file".to_string()))?;
+    if options.emit.is_some() && command != Command::Build {
+        return Err(Failure::Usage(
+            "--emit can only be used with `build`".to_string(),
+        ));
+    }
+    if options.check && command != Command::Fmt {
+        return Err(Failure::Usage(
+            "--check can only be used with `fmt`".to_string(),
+        ));
+    }
+    Ok(options)
+}
+
+/// Runs the command of `options`.
+pub fn execute(options: &Options) -> Result<(), Failure> {
+    match options.command {
+        Command::Check => check(options),
+        Command::Build => build(options),
+        Command::Run => run(options),
+        Command::Fmt => fmt(options),
+    }
+}
+
+fn check(options: &Options) -> Result<(), Failure> {
+    lower(options)?;
+    Ok(())
+}
+
+fn fmt(options: &Options) -> Result<(), Failure> {
+    let source = read_source(options)?;
+    let formatted = crate::format(&source).map_err(|error| located(error, options, &source))?;
+
+    if options.check {
+        if formatted != source {
+            return Err(Failure::Compilation(vec![format!(
+                "`{}` is not formatted",
+                options.input_path
+            )]));
+        }
+        return Ok(());
+    }
+
+    let output_path = options.output_path.as_ref().unwrap_or(&options.input_path);
+    if formatted != source || options.output_path.is_some() {
+        std::fs::write(output_path, formatted)
+            .map_err(|e| Failure::Io(format!("can't write `{}`: {}", output_path, e)))?;
+    }
+    Ok(())
+}
+
+fn build(options: &Options) -> Result<(), Failure> {
+    let emit = match options.emit.as_deref() {
+        Some(emit) => emit,
+        None => {
+            let module = compile(options)?;
+            build_executable(&module, options)?;
+            return Ok(());
+        }
+    };
+
+    let output = match emit {
+        "tokens" => tokens(&read_source(options)?),
+        "cst" => {
+            let source = read_source(options)?;
+            crate::parse_cst(&source)
+                .map_err(|error| located(error, options, &source))?
+                .dump()
+        }
+        "ast" => {
+            let program = parse(options, &read_source(options)?)?;
+            let mut visitor = ast::pretty_print_visitor::PrettyPrintVisitor::new();
+            program.accept_program_visitor(&mut visitor);
+            visitor.into_string()
+        }
+        "ir" => lower(options)?.to_string(),
+        "ir-opt" => compile(options)?.to_string(),
+        "asm" => backend::x86_64::emit_module(&compile(options)?),
+        "obj" => {
+            let object = backend::x86_64::emit_object(&compile(options)?);
+            let output_path = options.output_path.clone().unwrap_or_else(|| {
+                let path = Path::new(&options.input_path);
+                path.with_extension("o").to_string_lossy().into_owned()
+            });
+            std::fs::write(&output_path, object)
+                .map_err(|e| Failure::Io(format!("can't write `{}`: {}", output_path, e)))?;
+            return Ok(());
+        }
+        "c" => backend::c::emit_module(&compile(options)?),
+        "wat" => backend::wat::emit_module(&compile(options)?)
+            .map_err(|e| Failure::Compilation(vec![e]))?,
+        "bytecode" => vm::compiler::compile_module(&compile(options)?)
+            .map_err(|e| Failure::Compilation(vec![e]))?
+            .to_string(),
+        "cfg-dot" => ir::dot::module_to_dot(&compile(options)?, options.dominators),
+        _ => unreachable!("emit kinds are checked with the arguments"),
+    };
+
+    write_output(options, &output)?;
+    Ok(())
+}
+
+fn run(options: &Options) -> Result<(), Failure> {
+    let module = compile(options)?;
+    let bytecode =
+        vm::compiler::compile_module(&module).map_err(|e| Failure::Compilation(vec![e]))?;
+    let result = vm::machine::Machine::new(&bytecode)
+        .run("main", &[])
+        .map_err(|e| Failure::Runtime(e.to_string()))?;
+    write_output(options, &format!("{}\n", result))
+}
+
+fn read_source(options: &Options) -> Result<String, Failure> {
+    std::fs::read_to_string(&options.input_path)
+        .map_err(|e| Failure::Io(format!("can't read `{}`: {}", options.input_path, e)))
+}
+
+/// Lists the tokens without the trivia, one per line with their position.
+fn tokens(source: &str) -> String {
+    let mut output = String::new();
+    for token in crate::tokenize(source) {
+        if token.kind.is_trivia() {
+            continue;
+        }
+        let (line, column) = line_col(source, token.offset);
+        output.push_str(&format!(
+            "{}:{} {:?} {:?}\n",
+            line, column, token.kind, token.text
+        ));
+    }
+    output
+}
+
+/// Returns the unoptimized IR of the input file.
+fn lower(options: &Options) -> Result<ir::Module, Failure> {
+    let source = read_source(options)?;
+
+    // textual IR skips the front-end, which is convenient to test the optimizations
+    if options.input_path.ends_with(".ir") {
+        return Ok(crate::parse_ir(&source)?);
+    }
+
+    let program = parse(options, &source)?;
+    crate::lower(&program).map_err(|error| located(error, options, &source))
+}
+
+fn parse(options: &Options, source: &str) -> Result<ast::Program, Failure> {
+    crate::parse(source).map_err(|error| located(error, options, source))
+}
+
+/// Reports the errors of the input file with their location, and the hints to fix them.
+fn located(error: crate::Error, options: &Options, source: &str) -> Failure {
+    let errors: Vec<(String, ast::Span, Option<String>)> = match error {
+        crate::Error::Syntax(errors) => errors
+            .into_iter()
+            .map(|error| {
+                let message = format!("syntax error: {}", error);
+                (message, error.span, error.hint)
+            })
+            .collect(),
+        crate::Error::Semantic(errors) => errors
+            .iter()
+            .map(|error| (error.to_string(), error.span, None))
+            .collect(),
+        error => return error.into(),
+    };
+    Failure::Compilation(
+        errors
+            .into_iter()
+            .map(|(message, span, hint)| {
+                let (line, column) = line_col(source, span.start);
+                let mut error = format!(
+                    "{}\n  --> {}:{}:{}",
+                    message, options.input_path, line, column
+                );
+                if let Some(hint) = hint {
+                    error.push_str(&format!("\n   = hint: {}", hint));
+                }
+                error
+            })
+            .collect(),
+    )
+}
+
+/// Returns the 1-based line and column of a byte offset.
+fn line_col(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset];
+    let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+/// Returns the IR of the input file, optimized according to the options.
+fn compile(options: &Options) -> Result<ir::Module, Failure> {
+    let mut module = lower(options)?;
+    ir::opt::optimize_module(&mut module, options.opt_level);
+    Ok(module)
+}
+
+fn write_output(options: &Options, output: &str) -> Result<(), Failure> {
+    match options.output_path {
+        Some(ref path) => std::fs::write(path, output)
+            .map_err(|e| Failure::Io(format!("can't write `{}`: {}", path, e))),
+        None => std::io::stdout()
+            .write_all(output.as_bytes())
+            .map_err(|e| Failure::Io(format!("can't write the output: {}", e))),
+    }
+}
+
+/// Assembles and links `module` with the system C compiler. The executable is written to the
+/// output path, or next to the input without its extension.
+fn build_executable(module: &ir::Module, options: &Options) -> Result<(), Failure> {
+    if !module
+        .functions
+        .iter()
+        .any(|function| function.name() == "main" && function.params().is_empty())
+    {
+        return Err(Failure::Compilation(vec![
+            "a `main` function without parameters is required to build an executable".to_string(),
+        ]));
+    }
+
+    let input = Path::new(&options.input_path);
+    let output_path = match options.output_path {
+        Some(ref path) => PathBuf::from(path),
+        // `prog.hai` is built to `prog`, and `prog` to `prog.out`
+        None if input.extension().is_some() => input.with_extension(""),
+        None => input.with_extension("out"),
+    };
+    let overwrites_input = match (output_path.canonicalize(), input.canonicalize()) {
+        (Ok(output), Ok(input)) => output == input,
+        _ => false,
+    };
+    if overwrites_input {
+        return Err(Failure::Usage(format!(
+            "the executable would overwrite the input file `{}`",
+            options.input_path
+        )));
+    }
+
+    let temp_path = std::env::temp_dir().join(format!("hai-{}", std::process::id()));
+    let (input_path, content) = if options.integrated_as {
+        let object = backend::x86_64::emit_object(module);
+        (temp_path.with_extension("o"), object)
+    } else {
+        let assembly = backend::x86_64::emit_module(module);
+        (temp_path.with_extension("s"), assembly.into_bytes())
+    };
+    std::fs::write(&input_path, content)
+        .map_err(|e| Failure::Io(format!("can't write `{}`: {}", input_path.display(), e)))?;
+
+    let status = std::process::Command::new("cc")
+        .arg("-o")
+        .arg(&output_path)
+        .arg(&input_path)
+        .status();
+    let _ = std::fs::remove_file(&input_path);
+    match status {
+        Ok(status) if status.success() => Ok(()),
+        Ok(_) => Err(Failure::Io("linking with `cc` failed".to_string())),
+        Err(e) => Err(Failure::Io(format!("can't run `cc`: {}", e))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn test_executables_never_overwrite_the_input() {
+        let dir = std::env::temp_dir().join(format!("hai-driver-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("prog");
+        let input = input.to_str().unwrap();
+        let source = "fn main() -> int {\n    return 0;\n}\n";
+        std::fs::write(input, source).unwrap();
+
+        assert_eq!(main(args(&["build", "-o", input, input])), 2);
+        assert_eq!(std::fs::read_to_string(input).unwrap(), source);
+
+        assert_eq!(main(args(&["build", input])), 0);
+        assert_eq!(std::fs::read_to_string(input).unwrap(), source);
+        assert!(dir.join("prog.out").exists());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
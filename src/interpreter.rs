@@ -0,0 +1,365 @@
+//! A tree-walking interpreter of the AST.
+//!
+//! It shares nothing with the compiler after the parser: it doesn't use the IR or the type checks
+//! of the convertor. The differential fuzzer compares the compiled programs to it, so a bug in
+//! the lowering or the optimizations shows up as a difference, and the virtual machine is
+//! benchmarked against it.
+
+use crate::ast::visitor::Visitor;
+use crate::ast::{BinOp, Declaration, ExpressionId, Program, StatementId, Type};
+
+use std::collections::HashMap;
+
+/// The number of statements and expressions evaluated before giving up on a program.
+const FUEL: u64 = 10_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Value {
+    Unit,
+    Int(i32),
+    Boolean(bool),
+}
+
+/// What a statement does to the rest of its function.
+#[derive(Debug, Clone, Copy)]
+enum Flow {
+    Next,
+    Return(Value),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The program runs for too long, it may not terminate.
+    OutOfFuel,
+    /// The program divides by zero, or the smallest integer by -1, which compiled code traps on.
+    Trap(String),
+    /// The program isn't well-typed.
+    Runtime(String),
+}
+
+/// Runs the `main` function of `program` and returns its result.
+pub fn run_main(program: &Program) -> Result<i32, Error> {
+    let mut interpreter = Interpreter {
+        functions: HashMap::new(),
+        scopes: Vec::new(),
+        fuel: FUEL,
+        error: None,
+    };
+    for declaration in &program.declarations {
+        let Declaration::FunctionDeclaration { ref name, .. } = *declaration;
+        interpreter
+            .functions
+            .insert(name.clone(), declaration.clone());
+    }
+
+    match interpreter.call(program, "main", Vec::new()) {
+        Some(Value::Int(value)) => Ok(value),
+        Some(value) => Err(Error::Runtime(format!(
+            "`main` returns {:?} instead of an integer",
+            value
+        ))),
+        None => Err(interpreter.error.unwrap()),
+    }
+}
+
+struct Interpreter {
+    functions: HashMap<String, Declaration>,
+    /// The variables of the function being run, by block.
+    scopes: Vec<HashMap<String, Value>>,
+    fuel: u64,
+    /// The reason the evaluation stopped, when a visit returns `None`.
+    error: Option<Error>,
+}
+
+impl Interpreter {
+    fn fail<T>(&mut self, message: String) -> Option<T> {
+        self.error = Some(Error::Runtime(message));
+        None
+    }
+
+    fn burn(&mut self) -> Option<()> {
+        if self.fuel == 0 {
+            self.error = Some(Error::OutOfFuel);
+            return None;
+        }
+        self.fuel -= 1;
+        Some(())
+    }
+
+    fn call(&mut self, program: &Program, name: &str, args: Vec<Value>) -> Option<Value> {
+        let (parameters, return_ty, statement) = match self.functions.get(name) {
+            Some(&Declaration::FunctionDeclaration {
+                ref parameters,
+                return_ty,
+                statement,
+                ..
+            }) => (parameters.clone(), return_ty, statement),
+            None => return self.fail(format!("unknown function `{}`", name)),
+        };
+        if args.len() != parameters.len() {
+            return self.fail(format!("wrong number of arguments to `{}`", name));
+        }
+
+        let scope = parameters
+            .iter()
+            .map(|(name, _)| name.clone())
+            .zip(args)
+            .collect();
+        let caller_scopes = std::mem::replace(&mut self.scopes, vec![scope]);
+        let flow = self.statement(program, statement);
+        self.scopes = caller_scopes;
+
+        match flow? {
+            Flow::Return(value) => Some(value),
+            Flow::Next if return_ty == Type::Unit => Some(Value::Unit),
+            Flow::Next => self.fail(format!("`{}` ends without returning", name)),
+        }
+    }
+
+    fn statement(&mut self, program: &Program, statement: StatementId) -> Option<Flow> {
+        self.burn()?;
+        program.accept_statement_visitor(self, statement)
+    }
+
+    fn expression(&mut self, program: &Program, expression: ExpressionId) -> Option<Value> {
+        self.burn()?;
+        program.accept_expression_visitor(self, expression)
+    }
+
+    fn int(&mut self, program: &Program, expression: ExpressionId) -> Option<i32> {
+        match self.expression(program, expression)? {
+            Value::Int(value) => Some(value),
+            value => self.fail(format!("expected an integer, found {:?}", value)),
+        }
+    }
+
+    fn condition(&mut self, program: &Program, condition: ExpressionId) -> Option<bool> {
+        match self.expression(program, condition)? {
+            Value::Boolean(value) => Some(value),
+            value => self.fail(format!("expected a boolean, found {:?}", value)),
+        }
+    }
+}
+
+impl Visitor for Interpreter {
+    type ProgramItem = ();
+    type DeclarationItem = ();
+    type StatementItem = Flow;
+    type ExpressionItem = Value;
+
+    fn visit_block_statement(
+        &mut self,
+        program: &Program,
+        statements: &[StatementId],
+    ) -> Option<Flow> {
+        self.scopes.push(HashMap::new());
+        let mut flow = Some(Flow::Next);
+        for &statement in statements {
+            flow = self.statement(program, statement);
+            if let Some(Flow::Next) = flow {
+                continue;
+            }
+            break;
+        }
+        self.scopes.pop();
+        flow
+    }
+
+    fn visit_let_statement(
+        &mut self,
+        program: &Program,
+        identifier: &str,
+        expression: ExpressionId,
+    ) -> Option<Flow> {
+        let value = self.expression(program, expression)?;
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(identifier.to_string(), value);
+        Some(Flow::Next)
+    }
+
+    fn visit_return_statement(
+        &mut self,
+        program: &Program,
+        expression: Option<ExpressionId>,
+    ) -> Option<Flow> {
+        let value = match expression {
+            Some(expression) => self.expression(program, expression)?,
+            None => Value::Unit,
+        };
+        Some(Flow::Return(value))
+    }
+
+    fn visit_while_statement(
+        &mut self,
+        program: &Program,
+        condition: ExpressionId,
+        body: StatementId,
+    ) -> Option<Flow> {
+        while self.condition(program, condition)? {
+            if let Flow::Return(value) = self.statement(program, body)? {
+                return Some(Flow::Return(value));
+            }
+        }
+        Some(Flow::Next)
+    }
+
+    fn visit_if_statement(
+        &mut self,
+        program: &Program,
+        condition: ExpressionId,
+        then_statement: StatementId,
+        else_statement: Option<StatementId>,
+    ) -> Option<Flow> {
+        if self.condition(program, condition)? {
+            self.statement(program, then_statement)
+        } else if let Some(else_statement) = else_statement {
+            self.statement(program, else_statement)
+        } else {
+            Some(Flow::Next)
+        }
+    }
+
+    fn visit_assign_statement(
+        &mut self,
+        program: &Program,
+        identifier: &str,
+        expression: ExpressionId,
+    ) -> Option<Flow> {
+        let value = self.expression(program, expression)?;
+        match self
+            .scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(identifier))
+        {
+            Some(variable) => *variable = value,
+            None => return self.fail(format!("unknown variable `{}`", identifier)),
+        }
+        Some(Flow::Next)
+    }
+
+    fn visit_expression_statement(
+        &mut self,
+        program: &Program,
+        expression: ExpressionId,
+    ) -> Option<Flow> {
+        self.expression(program, expression)?;
+        Some(Flow::Next)
+    }
+
+    fn visit_binop_expression(
+        &mut self,
+        program: &Program,
+        op: BinOp,
+        lhs: ExpressionId,
+        rhs: ExpressionId,
+    ) -> Option<Value> {
+        let (lhs, rhs) = match op {
+            BinOp::Equal | BinOp::NotEqual => {
+                let lhs = self.expression(program, lhs)?;
+                let rhs = self.expression(program, rhs)?;
+                let equal = lhs == rhs;
+                return Some(Value::Boolean(equal == matches!(op, BinOp::Equal)));
+            }
+            _ => (self.int(program, lhs)?, self.int(program, rhs)?),
+        };
+
+        let value = match op {
+            BinOp::Add => Value::Int(lhs.wrapping_add(rhs)),
+            BinOp::Sub => Value::Int(lhs.wrapping_sub(rhs)),
+            BinOp::Mul => Value::Int(lhs.wrapping_mul(rhs)),
+            BinOp::Divide => match lhs.checked_div(rhs) {
+                Some(value) => Value::Int(value),
+                None => {
+                    self.error = Some(Error::Trap(format!("division of {} by {}", lhs, rhs)));
+                    return None;
+                }
+            },
+            BinOp::Less => Value::Boolean(lhs < rhs),
+            BinOp::LessEqual => Value::Boolean(lhs <= rhs),
+            BinOp::Greater => Value::Boolean(lhs > rhs),
+            BinOp::GreaterEqual => Value::Boolean(lhs >= rhs),
+            BinOp::Equal | BinOp::NotEqual => unreachable!("equality is evaluated above"),
+        };
+        Some(value)
+    }
+
+    fn visit_func_call(
+        &mut self,
+        program: &Program,
+        func: ExpressionId,
+        args: &[ExpressionId],
+    ) -> Option<Value> {
+        let name = match program.get_expression(func) {
+            Some(crate::ast::Expression::Identifier(name)) => name.clone(),
+            _ => return self.fail("only named functions can be called".to_string()),
+        };
+        let mut values = Vec::with_capacity(args.len());
+        for &arg in args {
+            values.push(self.expression(program, arg)?);
+        }
+        self.call(program, &name, values)
+    }
+
+    fn visit_integer(&mut self, _program: &Program, i: i32) -> Option<Value> {
+        Some(Value::Int(i))
+    }
+
+    fn visit_boolean(&mut self, _program: &Program, b: bool) -> Option<Value> {
+        Some(Value::Boolean(b))
+    }
+
+    fn visit_identifier(&mut self, _program: &Program, id: &str) -> Option<Value> {
+        match self.scopes.iter().rev().find_map(|scope| scope.get(id)) {
+            Some(&value) => Some(value),
+            None => self.fail(format!("unknown variable `{}`", id)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(source: &str) -> Result<i32, Error> {
+        run_main(&crate::parse(source).unwrap())
+    }
+
+    #[test]
+    fn scopes_and_calls() {
+        let source = "\
+fn add(a: int, b: int) -> int {
+    return a + b;
+}
+
+fn main() -> int {
+    let x = 1;
+    {
+        let x = true;
+        if x {
+            x = false;
+        }
+    }
+    let i = 0;
+    while i < 5 {
+        x = add(x, i * 2147483647);
+        i = i + 1;
+    }
+    return x / 2;
+}
+";
+        assert_eq!(run(source), Ok(-4));
+    }
+
+    #[test]
+    fn traps_are_errors() {
+        let source = "fn main() -> int { let zero = 0; return 1 / zero; }";
+        assert!(matches!(run(source), Err(Error::Trap(_))));
+        let source = "fn main() -> int { let min = 0 - 2147483647 - 1; return min / (0 - 1); }";
+        assert!(matches!(run(source), Err(Error::Trap(_))));
+        let source = "fn main() -> int { while true {} return 0; }";
+        assert_eq!(run(source), Err(Error::OutOfFuel));
+    }
+}
@@ -0,0 +1,204 @@
+use super::*;
+
+/// Direct call graph of a module, with its strongly connected components.
+#[derive(Debug, Clone)]
+pub struct CallGraph {
+    callees: Vec<Vec<usize>>,
+    sccs: Vec<Vec<usize>>,
+    scc_indices: Vec<usize>,
+}
+
+impl CallGraph {
+    pub fn new(module: &Module) -> Self {
+        let callees: Vec<Vec<usize>> = module
+            .functions
+            .iter()
+            .map(|function| {
+                let mut callees = Vec::new();
+                for statement in function.basic_blocks.iter().flat_map(|bb| &bb.statements) {
+                    match *statement {
+                        Statement::WithDestination(_, Operation::Call { callee, .. })
+                        | Statement::Operation(Operation::Call { callee, .. })
+                            if !callees.contains(&callee) =>
+                        {
+                            callees.push(callee)
+                        }
+                        _ => {}
+                    }
+                }
+                callees
+            })
+            .collect();
+
+        let mut tarjan = Tarjan {
+            callees: &callees,
+            index_counter: 0,
+            indices: vec![None; callees.len()],
+            lowlinks: vec![0; callees.len()],
+            stack: Vec::new(),
+            on_stack: vec![false; callees.len()],
+            sccs: Vec::new(),
+        };
+        for function in 0..callees.len() {
+            if tarjan.indices[function].is_none() {
+                tarjan.visit(function);
+            }
+        }
+        let sccs = tarjan.sccs;
+
+        let mut scc_indices = vec![0; callees.len()];
+        for (index, scc) in sccs.iter().enumerate() {
+            for &function in scc {
+                scc_indices[function] = index;
+            }
+        }
+
+        CallGraph {
+            callees,
+            sccs,
+            scc_indices,
+        }
+    }
+
+    /// Returns the strongly connected components of the graph, callees coming before their
+    /// callers.
+    pub fn sccs(&self) -> &[Vec<usize>] {
+        &self.sccs
+    }
+
+    /// Returns true if `function` can end up calling itself.
+    pub fn is_recursive(&self, function: usize) -> bool {
+        self.sccs[self.scc_indices[function]].len() > 1
+            || self.callees[function].contains(&function)
+    }
+
+    /// Returns, for each function, whether it can be called starting from `root`.
+    pub fn reachable_from(&self, root: usize) -> Vec<bool> {
+        let mut reachable = vec![false; self.callees.len()];
+        reachable[root] = true;
+        let mut worklist = vec![root];
+        while let Some(function) = worklist.pop() {
+            for &callee in &self.callees[function] {
+                if !reachable[callee] {
+                    reachable[callee] = true;
+                    worklist.push(callee);
+                }
+            }
+        }
+        reachable
+    }
+}
+
+struct Tarjan<'a> {
+    callees: &'a [Vec<usize>],
+    index_counter: usize,
+    indices: Vec<Option<usize>>,
+    lowlinks: Vec<usize>,
+    stack: Vec<usize>,
+    on_stack: Vec<bool>,
+    sccs: Vec<Vec<usize>>,
+}
+
+impl<'a> Tarjan<'a> {
+    fn visit(&mut self, function: usize) {
+        self.indices[function] = Some(self.index_counter);
+        self.lowlinks[function] = self.index_counter;
+        self.index_counter += 1;
+        self.stack.push(function);
+        self.on_stack[function] = true;
+
+        for &callee in &self.callees[function] {
+            match self.indices[callee] {
+                None => {
+                    self.visit(callee);
+                    self.lowlinks[function] = self.lowlinks[function].min(self.lowlinks[callee]);
+                }
+                Some(index) if self.on_stack[callee] => {
+                    self.lowlinks[function] = self.lowlinks[function].min(index);
+                }
+                Some(_) => {}
+            }
+        }
+
+        if Some(self.lowlinks[function]) == self.indices[function] {
+            let mut scc = Vec::new();
+            loop {
+                let member = self.stack.pop().unwrap();
+                self.on_stack[member] = false;
+                scc.push(member);
+                if member == function {
+                    break;
+                }
+            }
+            self.sccs.push(scc);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ir::text::parse_module;
+
+    #[test]
+    fn test_recursion_and_reachability() {
+        let module = parse_module(
+            "fn leaf() -> int {
+bb0:
+    %0 = const 1
+    ret %0
+}
+
+fn even(int) -> int {
+bb0:
+    %0 = param 0
+    %1 = call @2(%0)
+    ret %1
+}
+
+fn odd(int) -> int {
+bb0:
+    %0 = param 0
+    %1 = call @1(%0)
+    %2 = call @0()
+    ret %1
+}
+
+fn itself(int) -> int {
+bb0:
+    %0 = param 0
+    %1 = call @3(%0)
+    ret %1
+}
+
+fn main() -> int {
+bb0:
+    %0 = const 2
+    %1 = call @1(%0)
+    ret %1
+}
+
+fn unused() -> int {
+bb0:
+    %0 = const 2
+    %1 = call @3(%0)
+    ret %1
+}
+",
+        )
+        .unwrap();
+        let call_graph = CallGraph::new(&module);
+
+        assert_eq!(
+            call_graph.sccs(),
+            &[vec![0], vec![2, 1], vec![3], vec![4], vec![5]]
+        );
+        let recursive: Vec<bool> = (0..6).map(|f| call_graph.is_recursive(f)).collect();
+        assert_eq!(recursive, vec![false, true, true, true, false, false]);
+        assert_eq!(
+            call_graph.reachable_from(4),
+            vec![true, true, true, false, true, false]
+        );
+    }
+}
@@ -0,0 +1,39 @@
+use super::*;
+
+pub fn predecessors(function: &Function) -> Vec<Vec<BB>> {
+    let mut preds = vec![Vec::new(); function.basic_blocks.len()];
+    for (index, bb) in function.basic_blocks.iter().enumerate() {
+        for succ in bb.terminator.successors() {
+            if !preds[succ.0].contains(&BB(index)) {
+                preds[succ.0].push(BB(index));
+            }
+        }
+    }
+    preds
+}
+
+/// Returns the blocks reachable from the entry block, in reverse postorder.
+pub fn reverse_postorder(function: &Function) -> Vec<BB> {
+    let mut visited = vec![false; function.basic_blocks.len()];
+    let mut postorder = Vec::with_capacity(function.basic_blocks.len());
+
+    // each stack entry is a block and the index of its next successor to visit
+    let mut stack = vec![(BB(0), 0)];
+    visited[0] = true;
+    while let Some(&mut (bb, ref mut next)) = stack.last_mut() {
+        let succs = function.basic_blocks[bb.0].terminator.successors();
+        if let Some(&succ) = succs.get(*next) {
+            *next += 1;
+            if !visited[succ.0] {
+                visited[succ.0] = true;
+                stack.push((succ, 0));
+            }
+        } else {
+            postorder.push(bb);
+            stack.pop();
+        }
+    }
+
+    postorder.reverse();
+    postorder
+}
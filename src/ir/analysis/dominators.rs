@@ -0,0 +1,145 @@
+use super::*;
+
+/// Dominator tree of a function, computed with the algorithm from "A Simple, Fast Dominance
+/// Algorithm" (Cooper, Harvey, Kennedy).
+#[derive(Debug, Clone)]
+pub struct DominatorTree {
+    idoms: Vec<Option<BB>>,
+    children: Vec<Vec<BB>>,
+}
+
+impl DominatorTree {
+    pub fn new(function: &Function) -> Self {
+        let count = function.basic_blocks.len();
+        let rpo = cfg::reverse_postorder(function);
+        let preds = cfg::predecessors(function);
+
+        let mut rpo_index = vec![usize::MAX; count];
+        for (index, bb) in rpo.iter().enumerate() {
+            rpo_index[bb.0] = index;
+        }
+
+        let mut idoms: Vec<Option<BB>> = vec![None; count];
+        idoms[0] = Some(BB(0));
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &bb in rpo.iter().skip(1) {
+                let mut new_idom = None;
+                for &pred in &preds[bb.0] {
+                    if idoms[pred.0].is_none() {
+                        continue;
+                    }
+                    new_idom = Some(match new_idom {
+                        None => pred,
+                        Some(other) => intersect(&idoms, &rpo_index, pred, other),
+                    });
+                }
+
+                if new_idom.is_some() && idoms[bb.0] != new_idom {
+                    idoms[bb.0] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+
+        // the entry block has no immediate dominator
+        idoms[0] = None;
+
+        let mut children = vec![Vec::new(); count];
+        for (index, idom) in idoms.iter().enumerate() {
+            if let Some(idom) = *idom {
+                children[idom.0].push(BB(index));
+            }
+        }
+
+        DominatorTree { idoms, children }
+    }
+
+    /// Returns the immediate dominator of `bb`, the entry block and unreachable blocks have none.
+    pub fn immediate_dominator(&self, bb: BB) -> Option<BB> {
+        self.idoms[bb.0]
+    }
+
+    /// Returns true if every path from the entry block to `b` goes through `a`.
+    pub fn dominates(&self, a: BB, mut b: BB) -> bool {
+        loop {
+            if a == b {
+                return true;
+            }
+            match self.idoms[b.0] {
+                Some(idom) => b = idom,
+                None => return false,
+            }
+        }
+    }
+
+    pub fn children(&self, bb: BB) -> &[BB] {
+        &self.children[bb.0]
+    }
+}
+
+fn intersect(idoms: &[Option<BB>], rpo_index: &[usize], mut a: BB, mut b: BB) -> BB {
+    while a != b {
+        while rpo_index[a.0] > rpo_index[b.0] {
+            a = idoms[a.0].unwrap();
+        }
+        while rpo_index[b.0] > rpo_index[a.0] {
+            b = idoms[b.0].unwrap();
+        }
+    }
+    a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ir::text::parse_module;
+
+    #[test]
+    fn test_diamond_and_loop() {
+        let module = parse_module(
+            "fn f(bool) -> bool {
+bb0:
+    %0 = param 0
+    br %0, bb1, bb2
+bb1:
+    jmp bb3
+bb2:
+    jmp bb3
+bb3:
+    br %0, bb4, bb5
+bb4:
+    jmp bb3
+bb5:
+    ret %0
+bb6:
+    jmp bb5
+}
+",
+        )
+        .unwrap();
+        let domtree = DominatorTree::new(&module.functions[0]);
+
+        let idoms: Vec<Option<BB>> = (0..7).map(|i| domtree.immediate_dominator(BB(i))).collect();
+        assert_eq!(
+            idoms,
+            vec![
+                None,
+                Some(BB(0)),
+                Some(BB(0)),
+                Some(BB(0)),
+                Some(BB(3)),
+                Some(BB(3)),
+                None
+            ]
+        );
+        assert_eq!(domtree.children(BB(0)), &[BB(1), BB(2), BB(3)]);
+        assert!(domtree.dominates(BB(3), BB(4)));
+        assert!(domtree.dominates(BB(0), BB(5)));
+        assert!(!domtree.dominates(BB(1), BB(3)));
+        assert!(!domtree.dominates(BB(4), BB(5)));
+    }
+}
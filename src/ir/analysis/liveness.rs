@@ -0,0 +1,117 @@
+use super::*;
+
+use std::collections::HashSet;
+
+/// The values live at the entry and at the exit of each basic block.
+#[derive(Debug, Clone)]
+pub struct Liveness {
+    live_in: Vec<HashSet<Value>>,
+    live_out: Vec<HashSet<Value>>,
+}
+
+impl Liveness {
+    pub fn new(function: &Function) -> Self {
+        let block_count = function.basic_blocks.len();
+
+        // values used in a block before being defined in it, and values defined in the block
+        let mut uses = vec![HashSet::new(); block_count];
+        let mut defs = vec![HashSet::new(); block_count];
+        for (index, bb) in function.basic_blocks.iter().enumerate() {
+            for statement in &bb.statements {
+                let operation = match *statement {
+                    Statement::WithDestination(_, ref operation)
+                    | Statement::Operation(ref operation) => operation,
+                };
+                for operand in operation.operands() {
+                    if !defs[index].contains(&operand) {
+                        uses[index].insert(operand);
+                    }
+                }
+                if let Statement::WithDestination(value, _) = *statement {
+                    defs[index].insert(value);
+                }
+            }
+            for operand in bb.terminator.operands() {
+                if !defs[index].contains(&operand) {
+                    uses[index].insert(operand);
+                }
+            }
+        }
+
+        let mut live_in: Vec<HashSet<Value>> = uses.clone();
+        let mut live_out: Vec<HashSet<Value>> = vec![HashSet::new(); block_count];
+
+        // iterating in reverse postorder backwards converges quickly
+        let mut order = cfg::reverse_postorder(function);
+        order.reverse();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &bb in &order {
+                let mut out = HashSet::new();
+                for succ in function.basic_blocks[bb.0].terminator.successors() {
+                    out.extend(live_in[succ.0].iter().cloned());
+                }
+
+                let mut new_in = uses[bb.0].clone();
+                new_in.extend(out.difference(&defs[bb.0]).cloned());
+
+                if new_in.len() != live_in[bb.0].len() {
+                    live_in[bb.0] = new_in;
+                    changed = true;
+                }
+                live_out[bb.0] = out;
+            }
+        }
+
+        Liveness { live_in, live_out }
+    }
+
+    pub fn live_in(&self, bb: BB) -> &HashSet<Value> {
+        &self.live_in[bb.0]
+    }
+
+    pub fn live_out(&self, bb: BB) -> &HashSet<Value> {
+        &self.live_out[bb.0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ir::text::parse_module;
+
+    fn values(indices: &[u32]) -> HashSet<Value> {
+        indices.iter().map(|&index| Value(index)).collect()
+    }
+
+    #[test]
+    fn test_loop() {
+        let module = parse_module(
+            "fn f(int, bool) -> int {
+bb0:
+    %0 = param 0
+    %1 = param 1
+    %2 = const 1
+    jmp bb1
+bb1:
+    %3 = add %0, %2
+    br %1, bb1, bb2
+bb2:
+    ret %3
+}
+",
+        )
+        .unwrap();
+        let liveness = Liveness::new(&module.functions[0]);
+
+        assert_eq!(liveness.live_in(BB(0)), &values(&[]));
+        assert_eq!(liveness.live_out(BB(0)), &values(&[0, 1, 2]));
+        // the operands of the loop stay live around the back edge
+        assert_eq!(liveness.live_in(BB(1)), &values(&[0, 1, 2]));
+        assert_eq!(liveness.live_out(BB(1)), &values(&[0, 1, 2, 3]));
+        assert_eq!(liveness.live_in(BB(2)), &values(&[3]));
+        assert_eq!(liveness.live_out(BB(2)), &values(&[]));
+    }
+}
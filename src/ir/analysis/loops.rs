@@ -0,0 +1,103 @@
+use super::*;
+
+use super::dominators::DominatorTree;
+
+/// A natural loop: the blocks that can reach one of the back edges to `header` without going
+/// through `header`.
+#[derive(Debug, Clone)]
+pub struct Loop {
+    pub header: BB,
+    pub blocks: Vec<BB>,
+}
+
+impl Loop {
+    pub fn contains(&self, bb: BB) -> bool {
+        self.blocks.contains(&bb)
+    }
+}
+
+/// Finds the natural loops of `function`, loops sharing a header are merged. Inner loops come
+/// before the loops containing them.
+pub fn find_loops(function: &Function, domtree: &DominatorTree) -> Vec<Loop> {
+    let preds = cfg::predecessors(function);
+    let rpo = cfg::reverse_postorder(function);
+    let mut reachable = vec![false; function.basic_blocks.len()];
+    for &bb in &rpo {
+        reachable[bb.0] = true;
+    }
+
+    let mut loops: Vec<Loop> = Vec::new();
+    for bb in rpo {
+        for succ in function.basic_blocks[bb.0].terminator.successors() {
+            if !domtree.dominates(succ, bb) {
+                continue;
+            }
+
+            // `bb -> succ` is a back edge
+            let index = match loops.iter().position(|l| l.header == succ) {
+                Some(index) => index,
+                None => {
+                    loops.push(Loop {
+                        header: succ,
+                        blocks: vec![succ],
+                    });
+                    loops.len() - 1
+                }
+            };
+
+            let blocks = &mut loops[index].blocks;
+            let mut worklist = vec![bb];
+            while let Some(current) = worklist.pop() {
+                if blocks.contains(&current) {
+                    continue;
+                }
+                blocks.push(current);
+                worklist.extend(preds[current.0].iter().filter(|p| reachable[p.0]));
+            }
+        }
+    }
+
+    loops.sort_by_key(|l| l.blocks.len());
+    loops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ir::text::parse_module;
+
+    #[test]
+    fn test_nested_loops() {
+        let module = parse_module(
+            "fn f(bool) -> bool {
+bb0:
+    %0 = param 0
+    jmp bb1
+bb1:
+    br %0, bb2, bb5
+bb2:
+    br %0, bb3, bb4
+bb3:
+    jmp bb2
+bb4:
+    jmp bb1
+bb5:
+    ret %0
+}
+",
+        )
+        .unwrap();
+        let function = &module.functions[0];
+        let loops = find_loops(function, &DominatorTree::new(function));
+
+        assert_eq!(loops.len(), 2);
+        assert_eq!(loops[0].header, BB(2));
+        assert_eq!(loops[0].blocks, vec![BB(2), BB(3)]);
+        assert_eq!(loops[1].header, BB(1));
+        let mut blocks = loops[1].blocks.clone();
+        blocks.sort_by_key(|bb| bb.0);
+        assert_eq!(blocks, vec![BB(1), BB(2), BB(3), BB(4)]);
+        assert!(!loops[1].contains(BB(5)));
+    }
+}
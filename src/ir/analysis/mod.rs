@@ -0,0 +1,7 @@
+use super::*;
+
+pub mod call_graph;
+pub mod cfg;
+pub mod dominators;
+pub mod liveness;
+pub mod loops;
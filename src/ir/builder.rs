@@ -10,6 +10,7 @@ pub struct PreBasicBlock {
 pub struct FunctionBuilder {
     name: String,
     params: Vec<Type>,
+    return_ty: Type,
     variables: Vec<Type>,
     bbs: Vec<PreBasicBlock>,
     current_bb: BB,
@@ -17,10 +18,11 @@ pub struct FunctionBuilder {
 }
 
 impl FunctionBuilder {
-    pub fn new(name: String, params: Vec<Type>) -> Self {
+    pub fn new(name: String, params: Vec<Type>, return_ty: Type) -> Self {
         FunctionBuilder {
             name,
             params,
+            return_ty,
             variables: Vec::new(),
             bbs: vec![PreBasicBlock {
                 statements: Vec::new(),
@@ -44,8 +46,10 @@ impl FunctionBuilder {
         Function {
             name: self.name,
             params: self.params,
+            return_ty: self.return_ty,
             variables: self.variables,
             basic_blocks,
+            value_counter: self.value_counter,
         }
     }
 
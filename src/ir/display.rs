@@ -0,0 +1,155 @@
+use super::*;
+
+use std::fmt;
+
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, function) in self.functions.iter().enumerate() {
+            if index != 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", function)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "fn {}(", self.name)?;
+        write_list(f, &self.params)?;
+        writeln!(f, ") -> {} {{", self.return_ty)?;
+
+        if !self.variables.is_empty() {
+            write!(f, "    vars: ")?;
+            write_list(f, &self.variables)?;
+            writeln!(f)?;
+        }
+
+        for (index, bb) in self.basic_blocks.iter().enumerate() {
+            writeln!(f, "{}:", BB(index))?;
+            write!(f, "{}", bb)?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+impl fmt::Display for BasicBlock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for statement in &self.statements {
+            writeln!(f, "    {}", statement)?;
+        }
+        writeln!(f, "    {}", self.terminator)
+    }
+}
+
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Statement::WithDestination(value, ref operation) => {
+                write!(f, "{} = {}", value, operation)
+            }
+            Statement::Operation(ref operation) => write!(f, "{}", operation),
+        }
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Operation::Literal(ref literal) => write!(f, "const {}", literal),
+            Operation::BinaryAdd(lhs, rhs) => write!(f, "add {}, {}", lhs, rhs),
+            Operation::BinarySub(lhs, rhs) => write!(f, "sub {}, {}", lhs, rhs),
+            Operation::BinaryMul(lhs, rhs) => write!(f, "mul {}, {}", lhs, rhs),
+            Operation::BinaryDivide(lhs, rhs) => write!(f, "div {}, {}", lhs, rhs),
+            Operation::BinaryShiftLeft(lhs, rhs) => write!(f, "shl {}, {}", lhs, rhs),
+            Operation::Negate(value) => write!(f, "neg {}", value),
+            Operation::Compare(comparison, lhs, rhs) => {
+                write!(f, "{} {}, {}", comparison, lhs, rhs)
+            }
+            Operation::LoadParam(index) => write!(f, "param {}", index),
+            Operation::LoadVariable(var) => write!(f, "load {}", var),
+            Operation::StoreVariable(var, value) => write!(f, "store {}, {}", var, value),
+            Operation::Call { callee, ref args } => {
+                write!(f, "call @{}(", callee)?;
+                write_list(f, args)?;
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Terminator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Terminator::Return(value) => write!(f, "ret {}", value),
+            Terminator::Jump(bb) => write!(f, "jmp {}", bb),
+            Terminator::Branch(cond, then_bb, else_bb) => {
+                write!(f, "br {}, {}, {}", cond, then_bb, else_bb)
+            }
+            Terminator::Unreachable => write!(f, "unreachable"),
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Comparison::Equal => "eq",
+            Comparison::NotEqual => "ne",
+            Comparison::Less => "lt",
+            Comparison::LessEqual => "le",
+            Comparison::Greater => "gt",
+            Comparison::GreaterEqual => "ge",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl fmt::Display for BB {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "bb{}", self.0)
+    }
+}
+
+impl fmt::Display for Variable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "${}", self.0)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "%{}", self.0)
+    }
+}
+
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Literal::Unit => write!(f, "()"),
+            Literal::Int(i) => write!(f, "{}", i),
+            Literal::Boolean(b) => write!(f, "{}", b),
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Type::Unit => write!(f, "()"),
+            Type::Int => write!(f, "int"),
+            Type::Boolean => write!(f, "bool"),
+        }
+    }
+}
+
+fn write_list<T: fmt::Display>(f: &mut fmt::Formatter, items: &[T]) -> fmt::Result {
+    for (index, item) in items.iter().enumerate() {
+        if index != 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", item)?;
+    }
+    Ok(())
+}
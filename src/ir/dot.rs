@@ -0,0 +1,134 @@
+//! Export of the control flow graph of functions in the Graphviz DOT format.
+
+use super::*;
+
+use super::analysis::dominators::DominatorTree;
+
+use std::fmt::Write;
+
+/// Returns one DOT graph per function of `module`. If `dominators` is true, the edges of the
+/// dominator tree are added as dashed edges.
+pub fn module_to_dot(module: &Module, dominators: bool) -> String {
+    let mut output = String::new();
+    for function in &module.functions {
+        output.push_str(&function_to_dot(function, dominators));
+    }
+    output
+}
+
+pub fn function_to_dot(function: &Function, dominators: bool) -> String {
+    let mut output = String::new();
+    writeln!(output, "digraph \"{}\" {{", escape(&function.name)).unwrap();
+    writeln!(output, "    node [shape=box, fontname=monospace];").unwrap();
+
+    for (index, bb) in function.basic_blocks.iter().enumerate() {
+        let mut label = format!("{}:\\l", BB(index));
+        for statement in &bb.statements {
+            write!(label, "{}\\l", escape(&statement.to_string())).unwrap();
+        }
+        write!(label, "{}\\l", escape(&bb.terminator.to_string())).unwrap();
+        writeln!(output, "    bb{} [label=\"{}\"];", index, label).unwrap();
+    }
+
+    for (index, bb) in function.basic_blocks.iter().enumerate() {
+        match bb.terminator {
+            Terminator::Jump(target) => {
+                writeln!(output, "    bb{} -> {};", index, target).unwrap();
+            }
+            Terminator::Branch(_, then_bb, else_bb) => {
+                writeln!(output, "    bb{} -> {} [label=\"true\"];", index, then_bb).unwrap();
+                writeln!(output, "    bb{} -> {} [label=\"false\"];", index, else_bb).unwrap();
+            }
+            Terminator::Return(_) | Terminator::Unreachable => {}
+        }
+    }
+
+    if dominators {
+        let domtree = DominatorTree::new(function);
+        for index in 0..function.basic_blocks.len() {
+            if let Some(idom) = domtree.immediate_dominator(BB(index)) {
+                writeln!(
+                    output,
+                    "    {} -> bb{} [style=dashed, color=blue, constraint=false];",
+                    idom, index
+                )
+                .unwrap();
+            }
+        }
+    }
+
+    writeln!(output, "}}").unwrap();
+    output
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ir::text::parse_module;
+
+    const INPUT: &str = "fn f(bool) -> int {
+bb0:
+    %0 = param 0
+    br %0, bb1, bb2
+bb1:
+    %1 = const 1
+    jmp bb3
+bb2:
+    jmp bb3
+bb3:
+    %2 = const 2
+    ret %2
+}
+";
+
+    #[test]
+    fn test_edges() {
+        let module = parse_module(INPUT).unwrap();
+        assert_eq!(
+            module_to_dot(&module, false),
+            r#"digraph "f" {
+    node [shape=box, fontname=monospace];
+    bb0 [label="bb0:\l%0 = param 0\lbr %0, bb1, bb2\l"];
+    bb1 [label="bb1:\l%1 = const 1\ljmp bb3\l"];
+    bb2 [label="bb2:\ljmp bb3\l"];
+    bb3 [label="bb3:\l%2 = const 2\lret %2\l"];
+    bb0 -> bb1 [label="true"];
+    bb0 -> bb2 [label="false"];
+    bb1 -> bb3;
+    bb2 -> bb3;
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_dominators() {
+        let module = parse_module(INPUT).unwrap();
+        let dot = module_to_dot(&module, true);
+        let dominator_edges: Vec<_> = dot.lines().filter(|line| line.contains("dashed")).collect();
+        assert_eq!(
+            dominator_edges,
+            [
+                "    bb0 -> bb1 [style=dashed, color=blue, constraint=false];",
+                "    bb0 -> bb2 [style=dashed, color=blue, constraint=false];",
+                "    bb0 -> bb3 [style=dashed, color=blue, constraint=false];",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_escaping() {
+        let mut module =
+            parse_module("fn f() -> int {\nbb0:\n    %0 = const 1\n    ret %0\n}\n").unwrap();
+        module.functions[0].name = r#"say "hi" \o/"#.to_string();
+        assert_eq!(
+            module_to_dot(&module, false).lines().next(),
+            Some(r#"digraph "say \"hi\" \\o/" {"#)
+        );
+    }
+}
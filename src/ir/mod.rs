@@ -1,6 +1,14 @@
+pub mod analysis;
 pub mod builder;
+mod display;
+pub mod dot;
+pub mod opt;
+pub mod text;
+pub mod verify;
 
-#[derive(Debug, Clone)]
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Default)]
 pub struct Module {
     pub functions: Vec<Function>,
 }
@@ -9,8 +17,72 @@ pub struct Module {
 pub struct Function {
     name: String,
     params: Vec<Type>,
+    return_ty: Type,
     variables: Vec<Type>,
     basic_blocks: Vec<BasicBlock>,
+    value_counter: u32,
+}
+
+impl Function {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn params(&self) -> &[Type] {
+        &self.params
+    }
+
+    pub fn return_ty(&self) -> Type {
+        self.return_ty
+    }
+
+    pub fn variables(&self) -> &[Type] {
+        &self.variables
+    }
+
+    pub fn basic_blocks(&self) -> &[BasicBlock] {
+        &self.basic_blocks
+    }
+
+    /// Returns an upper bound of the indices of the values of the function.
+    pub fn value_count(&self) -> usize {
+        self.value_counter as usize
+    }
+
+    pub fn create_value(&mut self) -> Value {
+        let value = Value(self.value_counter);
+        self.value_counter += 1;
+        value
+    }
+
+    pub fn create_variable(&mut self, ty: Type) -> Variable {
+        let index = self.variables.len();
+        self.variables.push(ty);
+        Variable(index as u32)
+    }
+
+    /// Returns the values defined by an integer literal, with the literal.
+    pub fn int_constants(&self) -> HashMap<Value, i32> {
+        let mut constants = HashMap::new();
+        for bb in &self.basic_blocks {
+            for statement in &bb.statements {
+                if let Statement::WithDestination(value, Operation::Literal(Literal::Int(i))) =
+                    *statement
+                {
+                    constants.insert(value, i);
+                }
+            }
+        }
+        constants
+    }
+
+    /// Returns the number of statements and terminators of the function.
+    pub fn size(&self) -> usize {
+        self.basic_blocks
+            .iter()
+            .map(|bb| bb.statements.len() + 1)
+            .sum()
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -19,27 +91,181 @@ pub struct BasicBlock {
     terminator: Terminator,
 }
 
+impl BasicBlock {
+    pub fn statements(&self) -> &[Statement] {
+        &self.statements
+    }
+
+    pub fn terminator(&self) -> &Terminator {
+        &self.terminator
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Statement {
     WithDestination(Value, Operation),
     Operation(Operation),
-    Literal(Literal),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Operation {
+    Literal(Literal),
     BinaryAdd(Value, Value),
     BinarySub(Value, Value),
     BinaryMul(Value, Value),
     BinaryDivide(Value, Value),
+    BinaryShiftLeft(Value, Value),
+    Negate(Value),
+    Compare(Comparison, Value, Value),
     LoadParam(usize),
     LoadVariable(Variable),
     StoreVariable(Variable, Value),
+    Call { callee: usize, args: Vec<Value> },
+}
+
+impl Operation {
+    /// Returns true if removing this operation cannot change the behavior of the program,
+    /// provided its result is unused. `constants` are the integer literals of the function.
+    pub fn is_pure(&self, constants: &HashMap<Value, i32>) -> bool {
+        !self.has_side_effects() && !self.can_trap(constants)
+    }
+
+    /// Returns true if the operation does more than computing its result, or trapping.
+    pub fn has_side_effects(&self) -> bool {
+        matches!(*self, Operation::StoreVariable(..) | Operation::Call { .. })
+    }
+
+    /// Returns true if the operation may stop the program, given the integer literals of the
+    /// function.
+    pub fn can_trap(&self, constants: &HashMap<Value, i32>) -> bool {
+        match *self {
+            // division traps on a zero divisor, and on `i32::MIN / -1`
+            Operation::BinaryDivide(_, rhs) => match constants.get(&rhs) {
+                Some(&divisor) => divisor == 0 || divisor == -1,
+                None => true,
+            },
+            _ => false,
+        }
+    }
+
+    pub fn operands(&self) -> Vec<Value> {
+        match *self {
+            Operation::BinaryAdd(lhs, rhs)
+            | Operation::BinarySub(lhs, rhs)
+            | Operation::BinaryMul(lhs, rhs)
+            | Operation::BinaryDivide(lhs, rhs)
+            | Operation::BinaryShiftLeft(lhs, rhs)
+            | Operation::Compare(_, lhs, rhs) => vec![lhs, rhs],
+            Operation::Negate(value) | Operation::StoreVariable(_, value) => vec![value],
+            Operation::Call { ref args, .. } => args.clone(),
+            Operation::Literal(_) | Operation::LoadParam(_) | Operation::LoadVariable(_) => {
+                Vec::new()
+            }
+        }
+    }
+
+    pub fn map_operands<F: FnMut(Value) -> Value>(&mut self, mut f: F) {
+        match *self {
+            Operation::BinaryAdd(ref mut lhs, ref mut rhs)
+            | Operation::BinarySub(ref mut lhs, ref mut rhs)
+            | Operation::BinaryMul(ref mut lhs, ref mut rhs)
+            | Operation::BinaryDivide(ref mut lhs, ref mut rhs)
+            | Operation::BinaryShiftLeft(ref mut lhs, ref mut rhs)
+            | Operation::Compare(_, ref mut lhs, ref mut rhs) => {
+                *lhs = f(*lhs);
+                *rhs = f(*rhs);
+            }
+            Operation::Negate(ref mut value) | Operation::StoreVariable(_, ref mut value) => {
+                *value = f(*value)
+            }
+            Operation::Call { ref mut args, .. } => {
+                for arg in args {
+                    *arg = f(*arg);
+                }
+            }
+            Operation::Literal(_) | Operation::LoadParam(_) | Operation::LoadVariable(_) => {}
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+impl Comparison {
+    /// Returns the comparison giving the same result once its operands are swapped.
+    pub fn swapped(self) -> Self {
+        match self {
+            Comparison::Equal => Comparison::Equal,
+            Comparison::NotEqual => Comparison::NotEqual,
+            Comparison::Less => Comparison::Greater,
+            Comparison::LessEqual => Comparison::GreaterEqual,
+            Comparison::Greater => Comparison::Less,
+            Comparison::GreaterEqual => Comparison::LessEqual,
+        }
+    }
+
+    pub fn evaluate<T: Ord>(self, lhs: T, rhs: T) -> bool {
+        match self {
+            Comparison::Equal => lhs == rhs,
+            Comparison::NotEqual => lhs != rhs,
+            Comparison::Less => lhs < rhs,
+            Comparison::LessEqual => lhs <= rhs,
+            Comparison::Greater => lhs > rhs,
+            Comparison::GreaterEqual => lhs >= rhs,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum Terminator {
     Return(Value),
+    Jump(BB),
+    Branch(Value, BB, BB),
+    Unreachable,
+}
+
+impl Terminator {
+    pub fn operands(&self) -> Vec<Value> {
+        match *self {
+            Terminator::Return(value) | Terminator::Branch(value, _, _) => vec![value],
+            Terminator::Jump(_) | Terminator::Unreachable => Vec::new(),
+        }
+    }
+
+    pub fn map_operands<F: FnMut(Value) -> Value>(&mut self, mut f: F) {
+        match *self {
+            Terminator::Return(ref mut value) | Terminator::Branch(ref mut value, _, _) => {
+                *value = f(*value)
+            }
+            Terminator::Jump(_) | Terminator::Unreachable => {}
+        }
+    }
+
+    pub fn successors(&self) -> Vec<BB> {
+        match *self {
+            Terminator::Jump(bb) => vec![bb],
+            Terminator::Branch(_, then_bb, else_bb) => vec![then_bb, else_bb],
+            Terminator::Return(_) | Terminator::Unreachable => Vec::new(),
+        }
+    }
+
+    pub fn map_successors<F: FnMut(BB) -> BB>(&mut self, mut f: F) {
+        match *self {
+            Terminator::Jump(ref mut bb) => *bb = f(*bb),
+            Terminator::Branch(_, ref mut then_bb, ref mut else_bb) => {
+                *then_bb = f(*then_bb);
+                *else_bb = f(*else_bb);
+            }
+            Terminator::Return(_) | Terminator::Unreachable => {}
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -48,16 +274,29 @@ pub struct BB(pub usize);
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Variable(u32);
 
+impl Variable {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Value(u32);
 
-#[derive(Debug, Clone)]
+impl Value {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Literal {
+    Unit,
     Int(i32),
     Boolean(bool),
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Type {
     Unit,
     Int,
@@ -0,0 +1,753 @@
+use super::*;
+
+use crate::ir::analysis::cfg;
+
+use std::collections::HashMap;
+
+/// Instruction combining: rewrites operations into simpler equivalent ones, using the
+/// definitions of their operands (algebraic identities, strength reduction, constant folding,
+/// comparison canonicalization). Branches on a constant condition become jumps.
+pub fn combine_instructions(function: &mut Function) -> bool {
+    let mut combiner = Combiner {
+        definitions: HashMap::new(),
+        replacements: HashMap::new(),
+        changed: false,
+    };
+
+    // in reverse postorder, the definition of an operand is always visited before its uses
+    for bb in cfg::reverse_postorder(function) {
+        let statements = std::mem::take(&mut function.basic_blocks[bb.0].statements);
+        let mut kept = Vec::with_capacity(statements.len());
+        for statement in statements {
+            combiner.combine_statement(function, statement, &mut kept);
+        }
+        function.basic_blocks[bb.0].statements = kept;
+    }
+
+    for bb in &mut function.basic_blocks {
+        for statement in &mut bb.statements {
+            match *statement {
+                Statement::WithDestination(_, ref mut operation)
+                | Statement::Operation(ref mut operation) => {
+                    operation.map_operands(|v| combiner.lookup(v))
+                }
+            }
+        }
+        bb.terminator.map_operands(|v| combiner.lookup(v));
+        if let Some(terminator) = simplify_terminator(&combiner.definitions, &bb.terminator) {
+            bb.terminator = terminator;
+            combiner.changed = true;
+        }
+    }
+
+    combiner.changed
+}
+
+#[derive(Debug, Clone)]
+enum Rewrite {
+    /// The operation computes an already existing value.
+    Value(Value),
+    /// The operation can be replaced by a simpler one.
+    Operation(Operation),
+}
+
+type Rule = fn(&mut RuleContext, &Operation) -> Option<Rewrite>;
+
+const RULES: &[Rule] = &[
+    fold_constants,
+    add_zero,
+    sub_zero,
+    sub_self,
+    sub_from_zero,
+    double_negation,
+    mul_one,
+    mul_zero,
+    mul_minus_one,
+    mul_power_of_two,
+    div_one,
+    canonicalize_comparison,
+    compare_self,
+    compare_boolean,
+];
+
+#[derive(Debug)]
+struct Combiner {
+    definitions: HashMap<Value, Operation>,
+    replacements: HashMap<Value, Value>,
+    changed: bool,
+}
+
+impl Combiner {
+    fn lookup(&self, value: Value) -> Value {
+        self.replacements.get(&value).cloned().unwrap_or(value)
+    }
+
+    fn combine_statement(
+        &mut self,
+        function: &mut Function,
+        mut statement: Statement,
+        kept: &mut Vec<Statement>,
+    ) {
+        match statement {
+            Statement::WithDestination(value, ref mut operation) => {
+                operation.map_operands(|v| self.lookup(v));
+
+                let mut context = RuleContext {
+                    definitions: &mut self.definitions,
+                    function,
+                    inserted: kept,
+                };
+                while let Some(rewrite) = apply_rules(&mut context, operation) {
+                    self.changed = true;
+                    match rewrite {
+                        Rewrite::Value(existing) => {
+                            self.replacements.insert(value, existing);
+                            return;
+                        }
+                        Rewrite::Operation(simplified) => *operation = simplified,
+                    }
+                }
+                self.definitions.insert(value, operation.clone());
+            }
+            Statement::Operation(ref mut operation) => {
+                operation.map_operands(|v| self.lookup(v));
+            }
+        }
+        kept.push(statement);
+    }
+}
+
+fn apply_rules(context: &mut RuleContext, operation: &Operation) -> Option<Rewrite> {
+    RULES.iter().find_map(|rule| rule(context, operation))
+}
+
+/// What the rules can see of the function being combined.
+struct RuleContext<'a> {
+    definitions: &'a mut HashMap<Value, Operation>,
+    function: &'a mut Function,
+    inserted: &'a mut Vec<Statement>,
+}
+
+impl<'a> RuleContext<'a> {
+    fn definition(&self, value: Value) -> Option<&Operation> {
+        self.definitions.get(&value)
+    }
+
+    fn int_constant(&self, value: Value) -> Option<i32> {
+        match self.definition(value) {
+            Some(&Operation::Literal(Literal::Int(i))) => Some(i),
+            _ => None,
+        }
+    }
+
+    fn bool_constant(&self, value: Value) -> Option<bool> {
+        match self.definition(value) {
+            Some(&Operation::Literal(Literal::Boolean(b))) => Some(b),
+            _ => None,
+        }
+    }
+
+    /// Creates a new constant, defined just before the operation being combined.
+    fn constant(&mut self, literal: Literal) -> Value {
+        let value = self.function.create_value();
+        let operation = Operation::Literal(literal);
+        self.definitions.insert(value, operation.clone());
+        self.inserted
+            .push(Statement::WithDestination(value, operation));
+        value
+    }
+}
+
+fn int(i: i32) -> Rewrite {
+    Rewrite::Operation(Operation::Literal(Literal::Int(i)))
+}
+
+fn boolean(b: bool) -> Rewrite {
+    Rewrite::Operation(Operation::Literal(Literal::Boolean(b)))
+}
+
+/// `op c1, c2` -> `c`
+fn fold_constants(context: &mut RuleContext, operation: &Operation) -> Option<Rewrite> {
+    let ints = |lhs, rhs| Some((context.int_constant(lhs)?, context.int_constant(rhs)?));
+    match *operation {
+        Operation::BinaryAdd(lhs, rhs) => ints(lhs, rhs).map(|(l, r)| int(l.wrapping_add(r))),
+        Operation::BinarySub(lhs, rhs) => ints(lhs, rhs).map(|(l, r)| int(l.wrapping_sub(r))),
+        Operation::BinaryMul(lhs, rhs) => ints(lhs, rhs).map(|(l, r)| int(l.wrapping_mul(r))),
+        Operation::BinaryDivide(lhs, rhs) => {
+            // a trapping division is left for the program to execute
+            ints(lhs, rhs).and_then(|(l, r)| l.checked_div(r)).map(int)
+        }
+        Operation::BinaryShiftLeft(lhs, rhs) => {
+            ints(lhs, rhs).map(|(l, r)| int(l.wrapping_shl(r as u32)))
+        }
+        Operation::Negate(value) => context.int_constant(value).map(|i| int(i.wrapping_neg())),
+        Operation::Compare(comparison, lhs, rhs) => {
+            if let Some((l, r)) = ints(lhs, rhs) {
+                return Some(boolean(comparison.evaluate(l, r)));
+            }
+            let l = context.bool_constant(lhs)?;
+            let r = context.bool_constant(rhs)?;
+            Some(boolean(comparison.evaluate(l, r)))
+        }
+        _ => None,
+    }
+}
+
+/// `x + 0`, `0 + x` -> `x`
+fn add_zero(context: &mut RuleContext, operation: &Operation) -> Option<Rewrite> {
+    match *operation {
+        Operation::BinaryAdd(lhs, rhs) if context.int_constant(rhs) == Some(0) => {
+            Some(Rewrite::Value(lhs))
+        }
+        Operation::BinaryAdd(lhs, rhs) if context.int_constant(lhs) == Some(0) => {
+            Some(Rewrite::Value(rhs))
+        }
+        _ => None,
+    }
+}
+
+/// `x - 0` -> `x`
+fn sub_zero(context: &mut RuleContext, operation: &Operation) -> Option<Rewrite> {
+    match *operation {
+        Operation::BinarySub(lhs, rhs) if context.int_constant(rhs) == Some(0) => {
+            Some(Rewrite::Value(lhs))
+        }
+        _ => None,
+    }
+}
+
+/// `x - x` -> `0`
+fn sub_self(_context: &mut RuleContext, operation: &Operation) -> Option<Rewrite> {
+    match *operation {
+        Operation::BinarySub(lhs, rhs) if lhs == rhs => Some(int(0)),
+        _ => None,
+    }
+}
+
+/// `0 - x` -> `-x`
+fn sub_from_zero(context: &mut RuleContext, operation: &Operation) -> Option<Rewrite> {
+    match *operation {
+        Operation::BinarySub(lhs, rhs) if context.int_constant(lhs) == Some(0) => {
+            Some(Rewrite::Operation(Operation::Negate(rhs)))
+        }
+        _ => None,
+    }
+}
+
+/// `-(-x)` -> `x`
+fn double_negation(context: &mut RuleContext, operation: &Operation) -> Option<Rewrite> {
+    match *operation {
+        Operation::Negate(value) => match context.definition(value) {
+            Some(&Operation::Negate(inner)) => Some(Rewrite::Value(inner)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// `x * 1`, `1 * x` -> `x`
+fn mul_one(context: &mut RuleContext, operation: &Operation) -> Option<Rewrite> {
+    match *operation {
+        Operation::BinaryMul(lhs, rhs) if context.int_constant(rhs) == Some(1) => {
+            Some(Rewrite::Value(lhs))
+        }
+        Operation::BinaryMul(lhs, rhs) if context.int_constant(lhs) == Some(1) => {
+            Some(Rewrite::Value(rhs))
+        }
+        _ => None,
+    }
+}
+
+/// `x * 0`, `0 * x` -> `0`
+fn mul_zero(context: &mut RuleContext, operation: &Operation) -> Option<Rewrite> {
+    match *operation {
+        Operation::BinaryMul(lhs, rhs)
+            if context.int_constant(lhs) == Some(0) || context.int_constant(rhs) == Some(0) =>
+        {
+            Some(int(0))
+        }
+        _ => None,
+    }
+}
+
+/// `x * -1`, `-1 * x` -> `-x`
+fn mul_minus_one(context: &mut RuleContext, operation: &Operation) -> Option<Rewrite> {
+    match *operation {
+        Operation::BinaryMul(lhs, rhs) if context.int_constant(rhs) == Some(-1) => {
+            Some(Rewrite::Operation(Operation::Negate(lhs)))
+        }
+        Operation::BinaryMul(lhs, rhs) if context.int_constant(lhs) == Some(-1) => {
+            Some(Rewrite::Operation(Operation::Negate(rhs)))
+        }
+        _ => None,
+    }
+}
+
+/// `x * 2^k`, `2^k * x` -> `x << k`
+fn mul_power_of_two(context: &mut RuleContext, operation: &Operation) -> Option<Rewrite> {
+    let (value, factor) = match *operation {
+        Operation::BinaryMul(lhs, rhs) => {
+            match (context.int_constant(lhs), context.int_constant(rhs)) {
+                (None, Some(factor)) => (lhs, factor),
+                (Some(factor), None) => (rhs, factor),
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+
+    if factor > 1 && (factor as u32).is_power_of_two() {
+        let shift = context.constant(Literal::Int(factor.trailing_zeros() as i32));
+        Some(Rewrite::Operation(Operation::BinaryShiftLeft(value, shift)))
+    } else {
+        None
+    }
+}
+
+/// `x / 1` -> `x`
+fn div_one(context: &mut RuleContext, operation: &Operation) -> Option<Rewrite> {
+    match *operation {
+        Operation::BinaryDivide(lhs, rhs) if context.int_constant(rhs) == Some(1) => {
+            Some(Rewrite::Value(lhs))
+        }
+        _ => None,
+    }
+}
+
+/// `c < x` -> `x > c`: constants go to the right-hand side of comparisons
+fn canonicalize_comparison(context: &mut RuleContext, operation: &Operation) -> Option<Rewrite> {
+    match *operation {
+        Operation::Compare(comparison, lhs, rhs) => {
+            let is_constant = |context: &RuleContext, value| {
+                matches!(context.definition(value), Some(Operation::Literal(_)))
+            };
+            if is_constant(context, lhs) && !is_constant(context, rhs) {
+                Some(Rewrite::Operation(Operation::Compare(
+                    comparison.swapped(),
+                    rhs,
+                    lhs,
+                )))
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+/// `x == x` -> `true`, `x < x` -> `false`, ...
+fn compare_self(_context: &mut RuleContext, operation: &Operation) -> Option<Rewrite> {
+    match *operation {
+        Operation::Compare(comparison, lhs, rhs) if lhs == rhs => {
+            Some(boolean(comparison.evaluate(0, 0)))
+        }
+        _ => None,
+    }
+}
+
+/// `b == true`, `b != false` -> `b`
+fn compare_boolean(context: &mut RuleContext, operation: &Operation) -> Option<Rewrite> {
+    match *operation {
+        Operation::Compare(Comparison::Equal, lhs, rhs)
+            if context.bool_constant(rhs) == Some(true) =>
+        {
+            Some(Rewrite::Value(lhs))
+        }
+        Operation::Compare(Comparison::NotEqual, lhs, rhs)
+            if context.bool_constant(rhs) == Some(false) =>
+        {
+            Some(Rewrite::Value(lhs))
+        }
+        _ => None,
+    }
+}
+
+/// `br true, bb1, bb2` -> `jmp bb1`, `br %c, bb1, bb1` -> `jmp bb1`
+fn simplify_terminator(
+    definitions: &HashMap<Value, Operation>,
+    terminator: &Terminator,
+) -> Option<Terminator> {
+    match *terminator {
+        Terminator::Branch(_, then_bb, else_bb) if then_bb == else_bb => {
+            Some(Terminator::Jump(then_bb))
+        }
+        Terminator::Branch(cond, then_bb, else_bb) => match definitions.get(&cond) {
+            Some(&Operation::Literal(Literal::Boolean(true))) => Some(Terminator::Jump(then_bb)),
+            Some(&Operation::Literal(Literal::Boolean(false))) => Some(Terminator::Jump(else_bb)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ir::text::parse_module;
+
+    fn check(input: &str, expected: &str) {
+        let mut module = parse_module(input).unwrap();
+        for function in &mut module.functions {
+            combine_instructions(function);
+            dce::eliminate_dead_code(function);
+        }
+        assert_eq!(module.to_string(), expected);
+    }
+
+    #[test]
+    fn test_fold_constants() {
+        check(
+            "fn f() -> int {
+bb0:
+    %0 = const 6
+    %1 = const 7
+    %2 = mul %0, %1
+    %3 = const 2
+    %4 = sub %2, %3
+    ret %4
+}
+",
+            "fn f() -> int {
+bb0:
+    %4 = const 40
+    ret %4
+}
+",
+        );
+    }
+
+    #[test]
+    fn test_fold_constants_keeps_trapping_division() {
+        check(
+            "fn f() -> int {
+bb0:
+    %0 = const 6
+    %1 = const 0
+    %2 = div %0, %1
+    ret %2
+}
+",
+            "fn f() -> int {
+bb0:
+    %0 = const 6
+    %1 = const 0
+    %2 = div %0, %1
+    ret %2
+}
+",
+        );
+    }
+
+    #[test]
+    fn test_add_zero() {
+        check(
+            "fn f(int) -> int {
+bb0:
+    %0 = param 0
+    %1 = const 0
+    %2 = add %1, %0
+    %3 = add %2, %1
+    ret %3
+}
+",
+            "fn f(int) -> int {
+bb0:
+    %0 = param 0
+    ret %0
+}
+",
+        );
+    }
+
+    #[test]
+    fn test_sub_zero() {
+        check(
+            "fn f(int) -> int {
+bb0:
+    %0 = param 0
+    %1 = const 0
+    %2 = sub %0, %1
+    ret %2
+}
+",
+            "fn f(int) -> int {
+bb0:
+    %0 = param 0
+    ret %0
+}
+",
+        );
+    }
+
+    #[test]
+    fn test_sub_self() {
+        check(
+            "fn f(int) -> int {
+bb0:
+    %0 = param 0
+    %1 = sub %0, %0
+    ret %1
+}
+",
+            "fn f(int) -> int {
+bb0:
+    %1 = const 0
+    ret %1
+}
+",
+        );
+    }
+
+    #[test]
+    fn test_sub_from_zero() {
+        check(
+            "fn f(int) -> int {
+bb0:
+    %0 = param 0
+    %1 = const 0
+    %2 = sub %1, %0
+    ret %2
+}
+",
+            "fn f(int) -> int {
+bb0:
+    %0 = param 0
+    %2 = neg %0
+    ret %2
+}
+",
+        );
+    }
+
+    #[test]
+    fn test_double_negation() {
+        check(
+            "fn f(int) -> int {
+bb0:
+    %0 = param 0
+    %1 = neg %0
+    %2 = const 0
+    %3 = sub %2, %1
+    ret %3
+}
+",
+            "fn f(int) -> int {
+bb0:
+    %0 = param 0
+    ret %0
+}
+",
+        );
+    }
+
+    #[test]
+    fn test_mul_one() {
+        check(
+            "fn f(int) -> int {
+bb0:
+    %0 = param 0
+    %1 = const 1
+    %2 = mul %1, %0
+    ret %2
+}
+",
+            "fn f(int) -> int {
+bb0:
+    %0 = param 0
+    ret %0
+}
+",
+        );
+    }
+
+    #[test]
+    fn test_mul_zero() {
+        check(
+            "fn f(int) -> int {
+bb0:
+    %0 = param 0
+    %1 = const 0
+    %2 = mul %0, %1
+    ret %2
+}
+",
+            "fn f(int) -> int {
+bb0:
+    %2 = const 0
+    ret %2
+}
+",
+        );
+    }
+
+    #[test]
+    fn test_mul_minus_one() {
+        check(
+            "fn f(int) -> int {
+bb0:
+    %0 = param 0
+    %1 = const -1
+    %2 = mul %0, %1
+    ret %2
+}
+",
+            "fn f(int) -> int {
+bb0:
+    %0 = param 0
+    %2 = neg %0
+    ret %2
+}
+",
+        );
+    }
+
+    #[test]
+    fn test_mul_power_of_two() {
+        check(
+            "fn f(int) -> int {
+bb0:
+    %0 = param 0
+    %1 = const 8
+    %2 = mul %1, %0
+    %3 = const 6
+    %4 = mul %0, %3
+    %5 = add %2, %4
+    ret %5
+}
+",
+            "fn f(int) -> int {
+bb0:
+    %0 = param 0
+    %6 = const 3
+    %2 = shl %0, %6
+    %3 = const 6
+    %4 = mul %0, %3
+    %5 = add %2, %4
+    ret %5
+}
+",
+        );
+    }
+
+    #[test]
+    fn test_div_one() {
+        check(
+            "fn f(int) -> int {
+bb0:
+    %0 = param 0
+    %1 = const 1
+    %2 = div %0, %1
+    ret %2
+}
+",
+            "fn f(int) -> int {
+bb0:
+    %0 = param 0
+    ret %0
+}
+",
+        );
+    }
+
+    #[test]
+    fn test_div_minus_one_keeps_overflow_trap() {
+        // `i32::MIN / -1` traps, while `-i32::MIN` wraps
+        let input = "fn f(int) -> int {
+bb0:
+    %0 = param 0
+    %1 = const -1
+    %2 = div %0, %1
+    ret %2
+}
+";
+        check(input, input);
+    }
+
+    #[test]
+    fn test_canonicalize_comparison() {
+        check(
+            "fn f(int) -> bool {
+bb0:
+    %0 = param 0
+    %1 = const 3
+    %2 = lt %1, %0
+    ret %2
+}
+",
+            "fn f(int) -> bool {
+bb0:
+    %0 = param 0
+    %1 = const 3
+    %2 = gt %0, %1
+    ret %2
+}
+",
+        );
+    }
+
+    #[test]
+    fn test_compare_self() {
+        check(
+            "fn f(int) -> bool {
+bb0:
+    %0 = param 0
+    %1 = le %0, %0
+    ret %1
+}
+",
+            "fn f(int) -> bool {
+bb0:
+    %1 = const true
+    ret %1
+}
+",
+        );
+    }
+
+    #[test]
+    fn test_compare_boolean() {
+        check(
+            "fn f(bool) -> bool {
+bb0:
+    %0 = param 0
+    %1 = const false
+    %2 = ne %0, %1
+    ret %2
+}
+",
+            "fn f(bool) -> bool {
+bb0:
+    %0 = param 0
+    ret %0
+}
+",
+        );
+    }
+
+    #[test]
+    fn test_constant_branch() {
+        check(
+            "fn f() -> int {
+bb0:
+    %0 = const 1
+    %1 = const 2
+    %2 = lt %0, %1
+    br %2, bb1, bb2
+bb1:
+    ret %0
+bb2:
+    ret %1
+}
+",
+            "fn f() -> int {
+bb0:
+    %0 = const 1
+    jmp bb1
+bb1:
+    ret %0
+}
+",
+        );
+    }
+}
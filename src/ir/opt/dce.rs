@@ -0,0 +1,372 @@
+use super::*;
+
+use crate::ir::analysis::call_graph::CallGraph;
+
+use std::collections::HashSet;
+
+/// Runs all the dead code elimination passes on `function`.
+pub fn eliminate_dead_code(function: &mut Function) {
+    eliminate_unreachable_blocks(function);
+    while eliminate_dead_values(function) | eliminate_dead_stores(function) {}
+    eliminate_unused_variables(function);
+}
+
+/// Removes the basic blocks that can't be reached from the entry block `BB(0)`, and renumbers
+/// the remaining ones.
+pub fn eliminate_unreachable_blocks(function: &mut Function) -> bool {
+    let mut reachable = vec![false; function.basic_blocks.len()];
+    reachable[0] = true;
+    let mut worklist = vec![BB(0)];
+    while let Some(bb) = worklist.pop() {
+        for succ in function.basic_blocks[bb.0].terminator.successors() {
+            if !reachable[succ.0] {
+                reachable[succ.0] = true;
+                worklist.push(succ);
+            }
+        }
+    }
+
+    if reachable.iter().all(|&r| r) {
+        return false;
+    }
+
+    let mut mapping = Vec::with_capacity(reachable.len());
+    let mut next_index = 0;
+    for &r in &reachable {
+        mapping.push(next_index);
+        if r {
+            next_index += 1;
+        }
+    }
+
+    let basic_blocks = std::mem::take(&mut function.basic_blocks);
+    function.basic_blocks = basic_blocks
+        .into_iter()
+        .zip(reachable)
+        .filter_map(|(bb, r)| if r { Some(bb) } else { None })
+        .collect();
+
+    for bb in &mut function.basic_blocks {
+        bb.terminator.map_successors(|succ| BB(mapping[succ.0]));
+    }
+    true
+}
+
+/// Removes the side-effect free operations whose result is never used. Operations that may
+/// trap are kept, as removing them would change the behavior of the program.
+pub fn eliminate_dead_values(function: &mut Function) -> bool {
+    let mut changed = false;
+    let constants = function.int_constants();
+    loop {
+        let mut used = HashSet::new();
+        for bb in &function.basic_blocks {
+            for statement in &bb.statements {
+                let operation = match *statement {
+                    Statement::WithDestination(_, ref operation) => operation,
+                    Statement::Operation(ref operation) => operation,
+                };
+                used.extend(operation.operands());
+            }
+            used.extend(bb.terminator.operands());
+        }
+
+        let mut removed = false;
+        for bb in &mut function.basic_blocks {
+            let before = bb.statements.len();
+            bb.statements.retain(|statement| match *statement {
+                Statement::WithDestination(value, ref operation) => {
+                    used.contains(&value) || !operation.is_pure(&constants)
+                }
+                Statement::Operation(ref operation) => !operation.is_pure(&constants),
+            });
+            removed |= bb.statements.len() != before;
+        }
+
+        if !removed {
+            return changed;
+        }
+        changed = true;
+    }
+}
+
+/// Removes the stores to variables that are never loaded.
+pub fn eliminate_dead_stores(function: &mut Function) -> bool {
+    let mut loaded = HashSet::new();
+    for bb in &function.basic_blocks {
+        for statement in &bb.statements {
+            if let Statement::WithDestination(_, Operation::LoadVariable(var)) = *statement {
+                loaded.insert(var);
+            }
+        }
+    }
+
+    let mut changed = false;
+    for bb in &mut function.basic_blocks {
+        let before = bb.statements.len();
+        bb.statements.retain(|statement| match *statement {
+            Statement::WithDestination(_, Operation::StoreVariable(var, _))
+            | Statement::Operation(Operation::StoreVariable(var, _)) => loaded.contains(&var),
+            _ => true,
+        });
+        changed |= bb.statements.len() != before;
+    }
+    changed
+}
+
+/// Removes the entries of `function.variables` that are neither loaded nor stored, and
+/// renumbers the remaining ones.
+pub fn eliminate_unused_variables(function: &mut Function) -> bool {
+    let mut used = vec![false; function.variables.len()];
+    for bb in &function.basic_blocks {
+        for statement in &bb.statements {
+            let operation = match *statement {
+                Statement::WithDestination(_, ref operation) => operation,
+                Statement::Operation(ref operation) => operation,
+            };
+            match *operation {
+                Operation::LoadVariable(var) | Operation::StoreVariable(var, _) => {
+                    used[var.0 as usize] = true
+                }
+                _ => {}
+            }
+        }
+    }
+
+    if used.iter().all(|&u| u) {
+        return false;
+    }
+
+    let mut mapping = Vec::with_capacity(used.len());
+    let mut next_index = 0;
+    for &u in &used {
+        mapping.push(Variable(next_index));
+        if u {
+            next_index += 1;
+        }
+    }
+
+    let variables = std::mem::take(&mut function.variables);
+    function.variables = variables
+        .into_iter()
+        .zip(used)
+        .filter_map(|(ty, u)| if u { Some(ty) } else { None })
+        .collect();
+
+    for bb in &mut function.basic_blocks {
+        for statement in &mut bb.statements {
+            let operation = match *statement {
+                Statement::WithDestination(_, ref mut operation) => operation,
+                Statement::Operation(ref mut operation) => operation,
+            };
+            match *operation {
+                Operation::LoadVariable(ref mut var) | Operation::StoreVariable(ref mut var, _) => {
+                    *var = mapping[var.0 as usize]
+                }
+                _ => {}
+            }
+        }
+    }
+    true
+}
+
+/// Removes the functions that can't be called from `main`, and renumbers the remaining ones.
+/// Modules without a `main` function are left untouched.
+pub fn eliminate_dead_functions(module: &mut Module) -> bool {
+    let main = match module.functions.iter().position(|f| f.name == "main") {
+        Some(main) => main,
+        None => return false,
+    };
+
+    let reachable = CallGraph::new(module).reachable_from(main);
+    if reachable.iter().all(|&r| r) {
+        return false;
+    }
+
+    let mut mapping = Vec::with_capacity(reachable.len());
+    let mut next_index = 0;
+    for &r in &reachable {
+        mapping.push(next_index);
+        if r {
+            next_index += 1;
+        }
+    }
+
+    let functions = std::mem::take(&mut module.functions);
+    module.functions = functions
+        .into_iter()
+        .zip(reachable)
+        .filter_map(|(function, r)| if r { Some(function) } else { None })
+        .collect();
+
+    for function in &mut module.functions {
+        for bb in &mut function.basic_blocks {
+            for statement in &mut bb.statements {
+                match *statement {
+                    Statement::WithDestination(_, Operation::Call { ref mut callee, .. })
+                    | Statement::Operation(Operation::Call { ref mut callee, .. }) => {
+                        *callee = mapping[*callee]
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ir::text::parse_module;
+
+    fn check(input: &str, expected: &str) {
+        let mut module = parse_module(input).unwrap();
+        for function in &mut module.functions {
+            eliminate_dead_code(function);
+        }
+        assert_eq!(module.to_string(), expected);
+    }
+
+    #[test]
+    fn test_dead_values() {
+        check(
+            "fn f(int) -> int {
+bb0:
+    %0 = param 0
+    %1 = const 2
+    %2 = mul %0, %1
+    %3 = add %2, %0
+    ret %0
+}
+",
+            "fn f(int) -> int {
+bb0:
+    %0 = param 0
+    ret %0
+}
+",
+        );
+    }
+
+    #[test]
+    fn test_dead_values_keep_trapping_divisions() {
+        check(
+            "fn f(int) -> int {
+bb0:
+    %0 = param 0
+    %1 = const 0
+    %2 = div %0, %1
+    %3 = const -1
+    %4 = div %0, %3
+    %5 = div %0, %0
+    %6 = const 2
+    %7 = div %0, %6
+    ret %0
+}
+",
+            "fn f(int) -> int {
+bb0:
+    %0 = param 0
+    %1 = const 0
+    %2 = div %0, %1
+    %3 = const -1
+    %4 = div %0, %3
+    %5 = div %0, %0
+    ret %0
+}
+",
+        );
+    }
+
+    #[test]
+    fn test_unreachable_blocks() {
+        check(
+            "fn f() -> int {
+bb0:
+    jmp bb2
+bb1:
+    %0 = const 1
+    ret %0
+bb2:
+    %1 = const 2
+    ret %1
+}
+",
+            "fn f() -> int {
+bb0:
+    jmp bb1
+bb1:
+    %1 = const 2
+    ret %1
+}
+",
+        );
+    }
+
+    #[test]
+    fn test_dead_stores_and_unused_variables() {
+        check(
+            "fn f(int) -> int {
+    vars: int, int
+bb0:
+    %0 = param 0
+    store $0, %0
+    store $1, %0
+    %1 = load $1
+    ret %1
+}
+",
+            "fn f(int) -> int {
+    vars: int
+bb0:
+    %0 = param 0
+    store $0, %0
+    %1 = load $0
+    ret %1
+}
+",
+        );
+    }
+
+    #[test]
+    fn test_dead_functions() {
+        let mut module = parse_module(
+            "fn unused() -> int {
+bb0:
+    %0 = const 1
+    ret %0
+}
+
+fn used() -> int {
+bb0:
+    %0 = const 2
+    ret %0
+}
+
+fn main() -> int {
+bb0:
+    %0 = call @1()
+    ret %0
+}
+",
+        )
+        .unwrap();
+        assert!(eliminate_dead_functions(&mut module));
+        assert_eq!(
+            module.to_string(),
+            "fn used() -> int {
+bb0:
+    %0 = const 2
+    ret %0
+}
+
+fn main() -> int {
+bb0:
+    %0 = call @0()
+    ret %0
+}
+"
+        );
+    }
+}
@@ -0,0 +1,220 @@
+use super::*;
+
+use crate::ir::analysis::dominators::DominatorTree;
+
+use std::collections::HashMap;
+
+/// Global value numbering over the dominator tree: a pure operation computing a value that is
+/// already available in a dominating block is removed, and its uses are rewritten to the
+/// existing value. Inside a block, loads are also replaced by the last value stored to, or
+/// loaded from, the same variable.
+///
+/// Unreachable blocks are not visited, they must be removed before running this pass.
+pub fn number_values(function: &mut Function) -> bool {
+    let domtree = DominatorTree::new(function);
+    let mut numbering = ValueNumbering::default();
+    numbering.visit_block(function, &domtree, BB(0));
+    !numbering.replacements.is_empty()
+}
+
+#[derive(Debug, Default)]
+struct ValueNumbering {
+    available: HashMap<Operation, Value>,
+    replacements: HashMap<Value, Value>,
+}
+
+impl ValueNumbering {
+    fn lookup(&self, value: Value) -> Value {
+        self.replacements.get(&value).cloned().unwrap_or(value)
+    }
+
+    fn visit_block(&mut self, function: &mut Function, domtree: &DominatorTree, bb: BB) {
+        let mut inserted = Vec::new();
+        let mut variable_values = HashMap::new();
+
+        let statements = std::mem::take(&mut function.basic_blocks[bb.0].statements);
+        let mut kept = Vec::with_capacity(statements.len());
+        for mut statement in statements {
+            match statement {
+                Statement::WithDestination(value, ref mut operation) => {
+                    operation.map_operands(|v| self.lookup(v));
+                    match *operation {
+                        Operation::LoadVariable(var) => {
+                            if let Some(&known) = variable_values.get(&var) {
+                                self.replacements.insert(value, known);
+                                continue;
+                            }
+                            variable_values.insert(var, value);
+                        }
+                        Operation::StoreVariable(var, stored) => {
+                            variable_values.insert(var, stored);
+                        }
+                        // a trapping operation can be reused: the dominating one traps first
+                        _ if !operation.has_side_effects() => {
+                            let key = normalize(operation.clone());
+                            if let Some(&known) = self.available.get(&key) {
+                                self.replacements.insert(value, known);
+                                continue;
+                            }
+                            self.available.insert(key.clone(), value);
+                            inserted.push(key);
+                        }
+                        _ => {}
+                    }
+                }
+                Statement::Operation(ref mut operation) => {
+                    operation.map_operands(|v| self.lookup(v));
+                    if let Operation::StoreVariable(var, stored) = *operation {
+                        variable_values.insert(var, stored);
+                    }
+                }
+            }
+            kept.push(statement);
+        }
+
+        let block = &mut function.basic_blocks[bb.0];
+        block.statements = kept;
+        block.terminator.map_operands(|v| self.lookup(v));
+
+        for &child in domtree.children(bb) {
+            self.visit_block(function, domtree, child);
+        }
+
+        for key in inserted {
+            self.available.remove(&key);
+        }
+    }
+}
+
+/// Orders the operands of commutative operations so that `a + b` and `b + a` share the same key.
+fn normalize(operation: Operation) -> Operation {
+    match operation {
+        Operation::BinaryAdd(lhs, rhs) if rhs.0 < lhs.0 => Operation::BinaryAdd(rhs, lhs),
+        Operation::BinaryMul(lhs, rhs) if rhs.0 < lhs.0 => Operation::BinaryMul(rhs, lhs),
+        operation => operation,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ir::text::parse_module;
+
+    fn check(input: &str, expected: &str) {
+        let mut module = parse_module(input).unwrap();
+        for function in &mut module.functions {
+            number_values(function);
+        }
+        assert_eq!(module.to_string(), expected);
+    }
+
+    #[test]
+    fn test_commutative_operations() {
+        check(
+            "fn f(int, int) -> int {
+bb0:
+    %0 = param 0
+    %1 = param 1
+    %2 = add %0, %1
+    %3 = add %1, %0
+    %4 = mul %2, %3
+    ret %4
+}
+",
+            "fn f(int, int) -> int {
+bb0:
+    %0 = param 0
+    %1 = param 1
+    %2 = add %0, %1
+    %4 = mul %2, %2
+    ret %4
+}
+",
+        );
+    }
+
+    #[test]
+    fn test_only_dominating_values_are_reused() {
+        check(
+            "fn f(int, bool) -> int {
+bb0:
+    %0 = param 0
+    %1 = param 1
+    %2 = div %0, %0
+    br %1, bb1, bb2
+bb1:
+    %3 = div %0, %0
+    %4 = sub %0, %3
+    ret %4
+bb2:
+    %5 = sub %0, %2
+    jmp bb3
+bb3:
+    %6 = sub %0, %2
+    ret %6
+}
+",
+            "fn f(int, bool) -> int {
+bb0:
+    %0 = param 0
+    %1 = param 1
+    %2 = div %0, %0
+    br %1, bb1, bb2
+bb1:
+    %4 = sub %0, %2
+    ret %4
+bb2:
+    %5 = sub %0, %2
+    jmp bb3
+bb3:
+    ret %5
+}
+",
+        );
+    }
+
+    #[test]
+    fn test_siblings_dont_share_values() {
+        let input = "fn f(int, bool) -> int {
+bb0:
+    %0 = param 0
+    %1 = param 1
+    br %1, bb1, bb2
+bb1:
+    %2 = mul %0, %0
+    ret %2
+bb2:
+    %3 = mul %0, %0
+    ret %3
+}
+";
+        check(input, input);
+    }
+
+    #[test]
+    fn test_loads_are_forwarded() {
+        check(
+            "fn f(int) -> int {
+    vars: int
+bb0:
+    %0 = param 0
+    store $0, %0
+    %1 = load $0
+    %2 = load $0
+    %3 = add %1, %2
+    ret %3
+}
+",
+            "fn f(int) -> int {
+    vars: int
+bb0:
+    %0 = param 0
+    store $0, %0
+    %3 = add %0, %0
+    ret %3
+}
+",
+        );
+    }
+}
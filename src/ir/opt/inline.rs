@@ -0,0 +1,351 @@
+use super::*;
+
+use crate::ir::analysis::call_graph::CallGraph;
+
+use std::collections::HashMap;
+
+/// Callees with more statements than this are never inlined.
+const INLINE_THRESHOLD: usize = 40;
+/// Inlining stops once a caller grows past this number of statements.
+const CALLER_SIZE_LIMIT: usize = 2000;
+
+/// Inlines calls to small, non-recursive functions into their callers. Functions are visited
+/// bottom-up in the call graph, so the calls inside a callee are inlined before its body is
+/// copied.
+pub fn inline_calls(module: &mut Module) -> bool {
+    let call_graph = CallGraph::new(module);
+    let recursive: Vec<bool> = (0..module.functions.len())
+        .map(|index| call_graph.is_recursive(index))
+        .collect();
+
+    let mut changed = false;
+    for &caller in call_graph.sccs().iter().flatten() {
+        while let Some((bb, index, callee)) = find_inlinable_call(module, &recursive, caller) {
+            let callee = module.functions[callee].clone();
+            inline_call(&mut module.functions[caller], bb, index, &callee);
+            changed = true;
+        }
+    }
+    changed
+}
+
+fn find_inlinable_call(
+    module: &Module,
+    recursive: &[bool],
+    caller: usize,
+) -> Option<(BB, usize, usize)> {
+    let caller_size = module.functions[caller].size();
+    for (bb_index, bb) in module.functions[caller].basic_blocks.iter().enumerate() {
+        for (index, statement) in bb.statements.iter().enumerate() {
+            if let Statement::WithDestination(_, Operation::Call { callee, .. }) = *statement {
+                let callee_size = module.functions[callee].size();
+                if !recursive[callee]
+                    && callee_size <= INLINE_THRESHOLD
+                    && caller_size + callee_size <= CALLER_SIZE_LIMIT
+                {
+                    return Some((BB(bb_index), index, callee));
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Replaces the call at `caller.basic_blocks[bb].statements[index]` by the body of `callee`.
+fn inline_call(caller: &mut Function, bb: BB, index: usize, callee: &Function) {
+    let (destination, args) = match caller.basic_blocks[bb.0].statements[index] {
+        Statement::WithDestination(destination, Operation::Call { ref args, .. }) => {
+            (destination, args.clone())
+        }
+        _ => unreachable!(),
+    };
+
+    // parameters are replaced by the arguments, every other value gets a fresh number
+    let mut values = HashMap::new();
+    for statement in callee.basic_blocks.iter().flat_map(|bb| &bb.statements) {
+        match *statement {
+            Statement::WithDestination(value, Operation::LoadParam(param)) => {
+                values.insert(value, args[param]);
+            }
+            Statement::WithDestination(value, _) => {
+                values.insert(value, caller.create_value());
+            }
+            Statement::Operation(_) => {}
+        }
+    }
+
+    let variable_offset = caller.variables.len() as u32;
+    caller.variables.extend(callee.variables.iter().cloned());
+
+    let copy_statements = |statements: &[Statement]| -> Vec<Statement> {
+        statements
+            .iter()
+            .filter(|statement| {
+                !matches!(
+                    statement,
+                    Statement::WithDestination(_, Operation::LoadParam(_))
+                )
+            })
+            .map(|statement| {
+                let mut statement = statement.clone();
+                let operation = match statement {
+                    Statement::WithDestination(ref mut value, ref mut operation) => {
+                        *value = values[value];
+                        operation
+                    }
+                    Statement::Operation(ref mut operation) => operation,
+                };
+                operation.map_operands(|value| values[&value]);
+                match *operation {
+                    Operation::LoadVariable(ref mut var)
+                    | Operation::StoreVariable(ref mut var, _) => var.0 += variable_offset,
+                    _ => {}
+                }
+                statement
+            })
+            .collect()
+    };
+
+    if let [ref callee_bb] = callee.basic_blocks[..] {
+        if let Terminator::Return(result) = callee_bb.terminator {
+            // straight-line callee: splice its body in place of the call
+            let body = copy_statements(&callee_bb.statements);
+            caller.basic_blocks[bb.0]
+                .statements
+                .splice(index..=index, body);
+
+            let result = values[&result];
+            for bb in &mut caller.basic_blocks {
+                for statement in &mut bb.statements {
+                    match *statement {
+                        Statement::WithDestination(_, ref mut operation)
+                        | Statement::Operation(ref mut operation) => {
+                            operation.map_operands(|v| if v == destination { result } else { v })
+                        }
+                    }
+                }
+                bb.terminator
+                    .map_operands(|v| if v == destination { result } else { v });
+            }
+            return;
+        }
+    }
+
+    // general case: split the block around the call, the callee blocks are appended to the
+    // caller and their return value goes through a fresh variable
+    let bb_offset = caller.basic_blocks.len();
+    let continuation = BB(bb_offset + callee.basic_blocks.len());
+    let result_var = caller.create_variable(callee.return_ty);
+
+    let block = &mut caller.basic_blocks[bb.0];
+    let mut tail = block.statements.split_off(index + 1);
+    block.statements.pop();
+    let terminator = std::mem::replace(&mut block.terminator, Terminator::Jump(BB(bb_offset)));
+
+    for callee_bb in &callee.basic_blocks {
+        let mut statements = copy_statements(&callee_bb.statements);
+        let terminator = match callee_bb.terminator {
+            Terminator::Return(value) => {
+                statements.push(Statement::Operation(Operation::StoreVariable(
+                    result_var,
+                    values[&value],
+                )));
+                Terminator::Jump(continuation)
+            }
+            Terminator::Jump(target) => Terminator::Jump(BB(target.0 + bb_offset)),
+            Terminator::Branch(cond, then_bb, else_bb) => Terminator::Branch(
+                values[&cond],
+                BB(then_bb.0 + bb_offset),
+                BB(else_bb.0 + bb_offset),
+            ),
+            Terminator::Unreachable => Terminator::Unreachable,
+        };
+        caller.basic_blocks.push(BasicBlock {
+            statements,
+            terminator,
+        });
+    }
+
+    let mut statements = vec![Statement::WithDestination(
+        destination,
+        Operation::LoadVariable(result_var),
+    )];
+    statements.append(&mut tail);
+    caller.basic_blocks.push(BasicBlock {
+        statements,
+        terminator,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ir::text::parse_module;
+
+    fn inline(input: &str) -> String {
+        let mut module = parse_module(input).unwrap();
+        inline_calls(&mut module);
+        module.to_string()
+    }
+
+    #[test]
+    fn test_straight_line_callee() {
+        assert_eq!(
+            inline(
+                "fn double(int) -> int {
+bb0:
+    %0 = param 0
+    %1 = add %0, %0
+    ret %1
+}
+
+fn main() -> int {
+bb0:
+    %0 = const 3
+    %1 = call @0(%0)
+    %2 = mul %1, %1
+    ret %2
+}
+"
+            ),
+            "fn double(int) -> int {
+bb0:
+    %0 = param 0
+    %1 = add %0, %0
+    ret %1
+}
+
+fn main() -> int {
+bb0:
+    %0 = const 3
+    %3 = add %0, %0
+    %2 = mul %3, %3
+    ret %2
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_callee_with_branches() {
+        assert_eq!(
+            inline(
+                "fn abs(int) -> int {
+bb0:
+    %0 = param 0
+    %1 = const 0
+    %2 = lt %0, %1
+    br %2, bb1, bb2
+bb1:
+    %3 = neg %0
+    ret %3
+bb2:
+    ret %0
+}
+
+fn main() -> int {
+bb0:
+    %0 = const -3
+    %1 = call @0(%0)
+    %2 = add %1, %0
+    ret %2
+}
+"
+            ),
+            "fn abs(int) -> int {
+bb0:
+    %0 = param 0
+    %1 = const 0
+    %2 = lt %0, %1
+    br %2, bb1, bb2
+bb1:
+    %3 = neg %0
+    ret %3
+bb2:
+    ret %0
+}
+
+fn main() -> int {
+    vars: int
+bb0:
+    %0 = const -3
+    jmp bb1
+bb1:
+    %3 = const 0
+    %4 = lt %0, %3
+    br %4, bb2, bb3
+bb2:
+    %5 = neg %0
+    store $0, %5
+    jmp bb4
+bb3:
+    store $0, %0
+    jmp bb4
+bb4:
+    %1 = load $0
+    %2 = add %1, %0
+    ret %2
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_recursive_functions_are_not_inlined() {
+        let input = "fn f(int) -> int {
+bb0:
+    %0 = param 0
+    %1 = call @0(%0)
+    ret %1
+}
+
+fn g(int) -> int {
+bb0:
+    %0 = param 0
+    %1 = call @2(%0)
+    ret %1
+}
+
+fn h(int) -> int {
+bb0:
+    %0 = param 0
+    %1 = call @1(%0)
+    ret %1
+}
+
+fn main() -> int {
+bb0:
+    %0 = const 1
+    %1 = call @0(%0)
+    %2 = call @1(%1)
+    ret %2
+}
+";
+        assert_eq!(inline(input), input);
+    }
+
+    #[test]
+    fn test_large_callees_are_not_inlined() {
+        let mut body = String::from("    %0 = param 0\n");
+        for value in 1..=INLINE_THRESHOLD {
+            body.push_str(&format!("    %{} = add %{}, %0\n", value, value - 1));
+        }
+        let input = format!(
+            "fn big(int) -> int {{
+bb0:
+{}    ret %{}
+}}
+
+fn main() -> int {{
+bb0:
+    %0 = const 1
+    %1 = call @0(%0)
+    ret %1
+}}
+",
+            body, INLINE_THRESHOLD
+        );
+        assert_eq!(inline(&input), input);
+    }
+}
@@ -0,0 +1,245 @@
+use super::*;
+
+use crate::ir::analysis::cfg;
+use crate::ir::analysis::dominators::DominatorTree;
+use crate::ir::analysis::loops::{self, Loop};
+
+use std::collections::HashSet;
+
+/// Loop-invariant code motion: operations whose operands are all defined outside of a loop are
+/// moved to the loop preheader, which is created if needed. Only operations that can't trap
+/// are hoisted, since the loop body may never be executed.
+pub fn hoist_loop_invariants(function: &mut Function) -> bool {
+    let domtree = DominatorTree::new(function);
+    let headers: Vec<BB> = loops::find_loops(function, &domtree)
+        .iter()
+        .map(|l| l.header)
+        .collect();
+
+    let mut changed = false;
+    for header in headers {
+        // preheader insertion changes the CFG, so the loops are computed again for each header
+        let domtree = DominatorTree::new(function);
+        let l = loops::find_loops(function, &domtree)
+            .into_iter()
+            .find(|l| l.header == header)
+            .unwrap();
+
+        if let Some(preheader) = insert_preheader(function, &l) {
+            changed |= hoist(function, &l, preheader);
+        }
+    }
+    changed
+}
+
+/// Returns the single block outside of the loop jumping to its header, creating it if needed.
+fn insert_preheader(function: &mut Function, l: &Loop) -> Option<BB> {
+    if l.header == BB(0) {
+        // the entry block can't get a predecessor
+        return None;
+    }
+
+    let preds = cfg::predecessors(function);
+    let outside: Vec<BB> = preds[l.header.0]
+        .iter()
+        .cloned()
+        .filter(|&pred| !l.contains(pred))
+        .collect();
+
+    if let [pred] = outside[..] {
+        if function.basic_blocks[pred.0].terminator.successors() == [l.header] {
+            return Some(pred);
+        }
+    }
+
+    let preheader = BB(function.basic_blocks.len());
+    function.basic_blocks.push(BasicBlock {
+        statements: Vec::new(),
+        terminator: Terminator::Jump(l.header),
+    });
+    for pred in outside {
+        function.basic_blocks[pred.0]
+            .terminator
+            .map_successors(|succ| if succ == l.header { preheader } else { succ });
+    }
+    Some(preheader)
+}
+
+fn hoist(function: &mut Function, l: &Loop, preheader: BB) -> bool {
+    let constants = function.int_constants();
+    let mut defined_in_loop = HashSet::new();
+    let mut stored_in_loop = HashSet::new();
+    for (index, bb) in function.basic_blocks.iter().enumerate() {
+        let in_loop = l.contains(BB(index));
+        for statement in &bb.statements {
+            match *statement {
+                Statement::WithDestination(value, _) => {
+                    if in_loop {
+                        defined_in_loop.insert(value);
+                    }
+                }
+                Statement::Operation(Operation::StoreVariable(var, _)) if in_loop => {
+                    stored_in_loop.insert(var);
+                }
+                Statement::Operation(_) => {}
+            }
+        }
+    }
+
+    let is_invariant = |operation: &Operation, defined_in_loop: &HashSet<Value>| {
+        let speculatable = match *operation {
+            Operation::Literal(_)
+            | Operation::LoadParam(_)
+            | Operation::BinaryAdd(..)
+            | Operation::BinarySub(..)
+            | Operation::BinaryMul(..)
+            | Operation::BinaryShiftLeft(..)
+            | Operation::Negate(_)
+            | Operation::Compare(..) => true,
+            Operation::BinaryDivide(..) => !operation.can_trap(&constants),
+            Operation::LoadVariable(var) => !stored_in_loop.contains(&var),
+            Operation::StoreVariable(..) | Operation::Call { .. } => false,
+        };
+        speculatable
+            && operation
+                .operands()
+                .iter()
+                .all(|operand| !defined_in_loop.contains(operand))
+    };
+
+    let mut hoisted = Vec::new();
+    loop {
+        let mut moved = false;
+        for &bb in &l.blocks {
+            let statements = std::mem::take(&mut function.basic_blocks[bb.0].statements);
+            let mut kept = Vec::with_capacity(statements.len());
+            for statement in statements {
+                match statement {
+                    Statement::WithDestination(value, ref operation)
+                        if is_invariant(operation, &defined_in_loop) =>
+                    {
+                        defined_in_loop.remove(&value);
+                        hoisted.push(statement);
+                        moved = true;
+                    }
+                    statement => kept.push(statement),
+                }
+            }
+            function.basic_blocks[bb.0].statements = kept;
+        }
+
+        if !moved {
+            break;
+        }
+    }
+
+    let changed = !hoisted.is_empty();
+    function.basic_blocks[preheader.0]
+        .statements
+        .append(&mut hoisted);
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ir::text::parse_module;
+
+    fn check(input: &str, expected: &str) {
+        let mut module = parse_module(input).unwrap();
+        for function in &mut module.functions {
+            hoist_loop_invariants(function);
+        }
+        assert_eq!(module.to_string(), expected);
+    }
+
+    #[test]
+    fn test_zero_trip_loop() {
+        // the loop body may never run, only what can't trap is hoisted
+        check(
+            "fn f(int, int) -> int {
+    vars: int
+bb0:
+    %0 = param 0
+    %1 = param 1
+    %2 = const 0
+    store $0, %2
+    jmp bb1
+bb1:
+    %3 = load $0
+    %4 = lt %3, %0
+    br %4, bb2, bb3
+bb2:
+    %5 = mul %1, %1
+    %6 = div %1, %0
+    %7 = const 2
+    %8 = div %5, %7
+    %9 = add %3, %8
+    %10 = add %9, %6
+    store $0, %10
+    jmp bb1
+bb3:
+    ret %3
+}
+",
+            "fn f(int, int) -> int {
+    vars: int
+bb0:
+    %0 = param 0
+    %1 = param 1
+    %2 = const 0
+    store $0, %2
+    %5 = mul %1, %1
+    %7 = const 2
+    %8 = div %5, %7
+    jmp bb1
+bb1:
+    %3 = load $0
+    %4 = lt %3, %0
+    br %4, bb2, bb3
+bb2:
+    %6 = div %1, %0
+    %9 = add %3, %8
+    %10 = add %9, %6
+    store $0, %10
+    jmp bb1
+bb3:
+    ret %3
+}
+",
+        );
+    }
+
+    #[test]
+    fn test_preheader_insertion() {
+        check(
+            "fn f(int, bool) -> int {
+bb0:
+    %0 = param 0
+    %1 = param 1
+    br %1, bb1, bb2
+bb1:
+    %2 = add %0, %0
+    br %1, bb1, bb2
+bb2:
+    ret %0
+}
+",
+            "fn f(int, bool) -> int {
+bb0:
+    %0 = param 0
+    %1 = param 1
+    br %1, bb3, bb2
+bb1:
+    br %1, bb1, bb2
+bb2:
+    ret %0
+bb3:
+    %2 = add %0, %0
+    jmp bb1
+}
+",
+        );
+    }
+}
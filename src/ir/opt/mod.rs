@@ -0,0 +1,36 @@
+use super::*;
+
+pub mod combine;
+pub mod dce;
+pub mod gvn;
+pub mod inline;
+pub mod licm;
+
+/// Optimizes `module` according to `level`:
+/// - 0: no optimization
+/// - 1: instruction combining, dead code elimination, value numbering and loop-invariant code
+///   motion
+/// - 2: level 1 and inlining
+pub fn optimize_module(module: &mut Module, level: u32) {
+    if level == 0 {
+        return;
+    }
+
+    simplify_functions(module);
+    if level >= 2 && inline::inline_calls(module) {
+        simplify_functions(module);
+    }
+    dce::eliminate_dead_functions(module);
+}
+
+fn simplify_functions(module: &mut Module) {
+    for function in &mut module.functions {
+        dce::eliminate_dead_code(function);
+        gvn::number_values(function);
+        combine::combine_instructions(function);
+        dce::eliminate_dead_code(function);
+        licm::hoist_loop_invariants(function);
+        gvn::number_values(function);
+        dce::eliminate_dead_code(function);
+    }
+}
@@ -0,0 +1,397 @@
+//! Parser for the textual form of the IR, as printed by the `Display` implementations.
+
+use super::*;
+
+/// Parses a module and checks that it is well-formed, see [`verify::verify_module`].
+pub fn parse_module(input: &str) -> Result<Module, String> {
+    let mut parser = TextParser::new(input)?;
+    let mut functions = Vec::new();
+    while parser.peek().is_some() {
+        functions.push(parser.parse_function()?);
+    }
+    let module = Module { functions };
+    verify::verify_module(&module)?;
+    Ok(module)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Identifier(String),
+    Integer(i64),
+    Value(u32),
+    Variable(u32),
+    Function(usize),
+    Punct(&'static str),
+}
+
+#[derive(Debug, Clone)]
+struct TextParser {
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+    value_counter: u32,
+}
+
+impl TextParser {
+    fn new(input: &str) -> Result<Self, String> {
+        let mut tokens = Vec::new();
+        for (line_index, line) in input.lines().enumerate() {
+            let line_number = line_index + 1;
+            let mut chars = line.char_indices().peekable();
+            while let Some(&(start, c)) = chars.peek() {
+                if c.is_whitespace() {
+                    chars.next();
+                    continue;
+                }
+                if c == ';' {
+                    // comment until the end of the line
+                    break;
+                }
+
+                if c == '-' && line[start..].starts_with("->") {
+                    chars.next();
+                    chars.next();
+                    tokens.push((Token::Punct("->"), line_number));
+                    continue;
+                }
+
+                if let Some(punct) = ["(", ")", "{", "}", ",", ":", "="]
+                    .iter()
+                    .find(|p| p.starts_with(c))
+                {
+                    chars.next();
+                    tokens.push((Token::Punct(punct), line_number));
+                    continue;
+                }
+
+                let mut end = start + c.len_utf8();
+                chars.next();
+                while let Some(&(index, c)) = chars.peek() {
+                    if c.is_alphanumeric() || c == '_' {
+                        end = index + c.len_utf8();
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                let word = &line[start..end];
+                let parse_index = |digits: &str| {
+                    digits
+                        .parse::<u32>()
+                        .map_err(|_| format!("line {}: invalid token `{}`", line_number, word))
+                };
+
+                let token = match c {
+                    '%' => Token::Value(parse_index(&word[1..])?),
+                    '$' => Token::Variable(parse_index(&word[1..])?),
+                    '@' => Token::Function(parse_index(&word[1..])? as usize),
+                    '-' | '0'..='9' => Token::Integer(word.parse().map_err(|_| {
+                        format!("line {}: invalid integer `{}`", line_number, word)
+                    })?),
+                    c if c.is_alphabetic() || c == '_' => Token::Identifier(word.to_string()),
+                    _ => return Err(format!("line {}: unexpected `{}`", line_number, word)),
+                };
+                tokens.push((token, line_number));
+            }
+        }
+
+        Ok(TextParser {
+            tokens,
+            position: 0,
+            value_counter: 0,
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(token, _)| token)
+    }
+
+    fn error<T>(&self, message: &str) -> Result<T, String> {
+        match self.tokens.get(self.position) {
+            Some((token, line)) => Err(format!("line {}: {}, found {:?}", line, message, token)),
+            None => Err(format!("{}, found end of input", message)),
+        }
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.position += 1;
+        token
+    }
+
+    fn eat_punct(&mut self, punct: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Punct(p)) if *p == punct) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_punct(&mut self, punct: &str) -> Result<(), String> {
+        if self.eat_punct(punct) {
+            Ok(())
+        } else {
+            self.error(&format!("expected `{}`", punct))
+        }
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Identifier(id)) if id == keyword => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), String> {
+        if self.eat_keyword(keyword) {
+            Ok(())
+        } else {
+            self.error(&format!("expected `{}`", keyword))
+        }
+    }
+
+    fn parse_identifier(&mut self) -> Result<String, String> {
+        match self.peek().cloned() {
+            Some(Token::Identifier(id)) => {
+                self.position += 1;
+                Ok(id)
+            }
+            _ => self.error("expected an identifier"),
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Value, String> {
+        match self.peek() {
+            Some(&Token::Value(index)) => {
+                self.position += 1;
+                self.value_counter = self.value_counter.max(index + 1);
+                Ok(Value(index))
+            }
+            _ => self.error("expected a value"),
+        }
+    }
+
+    fn parse_variable(&mut self) -> Result<Variable, String> {
+        match self.peek() {
+            Some(&Token::Variable(index)) => {
+                self.position += 1;
+                Ok(Variable(index))
+            }
+            _ => self.error("expected a variable"),
+        }
+    }
+
+    fn parse_bb(&mut self) -> Result<BB, String> {
+        if let Some(Token::Identifier(id)) = self.peek() {
+            if let Some(Ok(index)) = id.strip_prefix("bb").map(str::parse) {
+                self.position += 1;
+                return Ok(BB(index));
+            }
+        }
+        self.error("expected a basic block")
+    }
+
+    fn parse_usize(&mut self) -> Result<usize, String> {
+        match self.peek() {
+            Some(&Token::Integer(i)) if i >= 0 => {
+                self.position += 1;
+                Ok(i as usize)
+            }
+            _ => self.error("expected an index"),
+        }
+    }
+
+    fn parse_type(&mut self) -> Result<Type, String> {
+        if self.eat_punct("(") {
+            self.expect_punct(")")?;
+            Ok(Type::Unit)
+        } else if self.eat_keyword("int") {
+            Ok(Type::Int)
+        } else if self.eat_keyword("bool") {
+            Ok(Type::Boolean)
+        } else {
+            self.error("expected a type")
+        }
+    }
+
+    fn parse_list<T, F>(&mut self, closing: &str, mut parse_item: F) -> Result<Vec<T>, String>
+    where
+        F: FnMut(&mut Self) -> Result<T, String>,
+    {
+        let mut items = Vec::new();
+        if self.eat_punct(closing) {
+            return Ok(items);
+        }
+        loop {
+            items.push(parse_item(self)?);
+            if self.eat_punct(closing) {
+                return Ok(items);
+            }
+            self.expect_punct(",")?;
+        }
+    }
+
+    fn parse_function(&mut self) -> Result<Function, String> {
+        self.value_counter = 0;
+
+        self.expect_keyword("fn")?;
+        let name = self.parse_identifier()?;
+        self.expect_punct("(")?;
+        let params = self.parse_list(")", Self::parse_type)?;
+        self.expect_punct("->")?;
+        let return_ty = self.parse_type()?;
+        self.expect_punct("{")?;
+
+        let mut variables = Vec::new();
+        if self.eat_keyword("vars") {
+            self.expect_punct(":")?;
+            variables.push(self.parse_type()?);
+            while self.eat_punct(",") {
+                variables.push(self.parse_type()?);
+            }
+        }
+
+        let mut basic_blocks = Vec::new();
+        while !self.eat_punct("}") {
+            let bb = self.parse_bb()?;
+            if bb.0 != basic_blocks.len() {
+                return Err(format!(
+                    "basic blocks must be numbered in order, expected bb{} but found {}",
+                    basic_blocks.len(),
+                    bb
+                ));
+            }
+            self.expect_punct(":")?;
+            basic_blocks.push(self.parse_basic_block()?);
+        }
+
+        if basic_blocks.is_empty() {
+            return Err(format!("function `{}` has no basic block", name));
+        }
+
+        Ok(Function {
+            name,
+            params,
+            return_ty,
+            variables,
+            basic_blocks,
+            value_counter: self.value_counter,
+        })
+    }
+
+    fn parse_basic_block(&mut self) -> Result<BasicBlock, String> {
+        let mut statements = Vec::new();
+        loop {
+            if let Some(terminator) = self.parse_terminator()? {
+                return Ok(BasicBlock {
+                    statements,
+                    terminator,
+                });
+            }
+
+            let statement = if let Some(Token::Value(_)) = self.peek() {
+                let value = self.parse_value()?;
+                self.expect_punct("=")?;
+                Statement::WithDestination(value, self.parse_operation()?)
+            } else {
+                Statement::Operation(self.parse_operation()?)
+            };
+            statements.push(statement);
+        }
+    }
+
+    fn parse_terminator(&mut self) -> Result<Option<Terminator>, String> {
+        let terminator = if self.eat_keyword("ret") {
+            Terminator::Return(self.parse_value()?)
+        } else if self.eat_keyword("jmp") {
+            Terminator::Jump(self.parse_bb()?)
+        } else if self.eat_keyword("br") {
+            let cond = self.parse_value()?;
+            self.expect_punct(",")?;
+            let then_bb = self.parse_bb()?;
+            self.expect_punct(",")?;
+            let else_bb = self.parse_bb()?;
+            Terminator::Branch(cond, then_bb, else_bb)
+        } else if self.eat_keyword("unreachable") {
+            Terminator::Unreachable
+        } else {
+            return Ok(None);
+        };
+        Ok(Some(terminator))
+    }
+
+    fn parse_operation(&mut self) -> Result<Operation, String> {
+        let name = self.parse_identifier()?;
+        let operation = match name.as_str() {
+            "const" => Operation::Literal(self.parse_literal()?),
+            "add" | "sub" | "mul" | "div" | "shl" | "eq" | "ne" | "lt" | "le" | "gt" | "ge" => {
+                let lhs = self.parse_value()?;
+                self.expect_punct(",")?;
+                let rhs = self.parse_value()?;
+                match name.as_str() {
+                    "add" => Operation::BinaryAdd(lhs, rhs),
+                    "sub" => Operation::BinarySub(lhs, rhs),
+                    "mul" => Operation::BinaryMul(lhs, rhs),
+                    "div" => Operation::BinaryDivide(lhs, rhs),
+                    "shl" => Operation::BinaryShiftLeft(lhs, rhs),
+                    "eq" => Operation::Compare(Comparison::Equal, lhs, rhs),
+                    "ne" => Operation::Compare(Comparison::NotEqual, lhs, rhs),
+                    "lt" => Operation::Compare(Comparison::Less, lhs, rhs),
+                    "le" => Operation::Compare(Comparison::LessEqual, lhs, rhs),
+                    "gt" => Operation::Compare(Comparison::Greater, lhs, rhs),
+                    _ => Operation::Compare(Comparison::GreaterEqual, lhs, rhs),
+                }
+            }
+            "neg" => Operation::Negate(self.parse_value()?),
+            "param" => Operation::LoadParam(self.parse_usize()?),
+            "load" => Operation::LoadVariable(self.parse_variable()?),
+            "store" => {
+                let var = self.parse_variable()?;
+                self.expect_punct(",")?;
+                Operation::StoreVariable(var, self.parse_value()?)
+            }
+            "call" => {
+                let callee = match self.next() {
+                    Some(Token::Function(callee)) => callee,
+                    _ => {
+                        self.position -= 1;
+                        return self.error("expected a function");
+                    }
+                };
+                self.expect_punct("(")?;
+                let args = self.parse_list(")", Self::parse_value)?;
+                Operation::Call { callee, args }
+            }
+            _ => {
+                self.position -= 1;
+                return self.error("expected an operation");
+            }
+        };
+        Ok(operation)
+    }
+
+    fn parse_literal(&mut self) -> Result<Literal, String> {
+        if self.eat_keyword("true") {
+            return Ok(Literal::Boolean(true));
+        }
+        if self.eat_keyword("false") {
+            return Ok(Literal::Boolean(false));
+        }
+
+        match self.peek() {
+            Some(&Token::Integer(i)) if i >= i64::from(i32::MIN) && i <= i64::from(i32::MAX) => {
+                self.position += 1;
+                Ok(Literal::Int(i as i32))
+            }
+            Some(Token::Punct("(")) => {
+                self.position += 1;
+                self.expect_punct(")")?;
+                Ok(Literal::Unit)
+            }
+            _ => self.error("expected a literal"),
+        }
+    }
+}
@@ -0,0 +1,363 @@
+//! Checks that a module is well-formed before it is optimized or compiled: the convertor always
+//! builds valid modules, but the textual IR can refer to anything.
+
+use super::*;
+
+use crate::ir::analysis::dominators::DominatorTree;
+
+/// Largest number of values of a function: passes index tables by value.
+pub const MAX_VALUES: usize = 1 << 24;
+
+/// Returns a description of the first problem found in `module`: a basic block, variable,
+/// parameter, function or value index out of range, a call with the wrong number of arguments,
+/// a value that is used without being defined before, or that is defined twice, or an operand
+/// of the wrong type.
+pub fn verify_module(module: &Module) -> Result<(), String> {
+    for function in &module.functions {
+        verify_function(module, function)
+            .map_err(|message| format!("function `{}`: {}", function.name, message))?;
+    }
+    Ok(())
+}
+
+fn verify_function(module: &Module, function: &Function) -> Result<(), String> {
+    let block_count = function.basic_blocks.len();
+    // where each value is defined: its block and the index of the statement
+    let mut definitions = HashMap::new();
+    let mut types = HashMap::new();
+    let value_count = function.value_count().min(MAX_VALUES);
+    let in_range = |value: Value| {
+        if value.index() < value_count {
+            Ok(())
+        } else {
+            Err(format!("{} is out of range", value))
+        }
+    };
+
+    for (index, bb) in function.basic_blocks.iter().enumerate() {
+        let in_block = |message: String| format!("{}: {}", BB(index), message);
+
+        for (position, statement) in bb.statements.iter().enumerate() {
+            let operation = match *statement {
+                Statement::WithDestination(value, ref operation) => {
+                    in_range(value).map_err(in_block)?;
+                    if definitions.insert(value, (index, position)).is_some() {
+                        return Err(in_block(format!("{} is defined twice", value)));
+                    }
+                    operation
+                }
+                Statement::Operation(ref operation) => operation,
+            };
+            verify_operation(module, function, operation).map_err(in_block)?;
+            if let Statement::WithDestination(value, _) = *statement {
+                types.insert(value, result_type(module, function, operation));
+            }
+        }
+
+        for succ in bb.terminator.successors() {
+            if succ.0 >= block_count {
+                return Err(in_block(format!("unknown basic block {}", succ)));
+            }
+        }
+    }
+
+    // every use must come after its definition, on all the paths from the entry block
+    let domtree = DominatorTree::new(function);
+    for (index, bb) in function.basic_blocks.iter().enumerate() {
+        let uses = bb
+            .statements
+            .iter()
+            .enumerate()
+            .flat_map(|(position, statement)| {
+                let operation = match *statement {
+                    Statement::WithDestination(_, ref operation) => operation,
+                    Statement::Operation(ref operation) => operation,
+                };
+                operation
+                    .operands()
+                    .into_iter()
+                    .map(move |value| (position, value))
+            })
+            .chain(
+                bb.terminator
+                    .operands()
+                    .into_iter()
+                    .map(|value| (bb.statements.len(), value)),
+            );
+
+        for (position, value) in uses {
+            in_range(value).map_err(|message| format!("{}: {}", BB(index), message))?;
+            let available = match definitions.get(&value) {
+                Some(&(def_index, def_position)) if def_index == index => def_position < position,
+                Some(&(def_index, _)) => domtree.dominates(BB(def_index), BB(index)),
+                None => return Err(format!("{}: {} is never defined", BB(index), value)),
+            };
+            // unreachable blocks have no dominator, they are removed before any use of the IR
+            let reachable = index == 0 || domtree.immediate_dominator(BB(index)).is_some();
+            if !available && reachable {
+                return Err(format!(
+                    "{}: {} is used before its definition",
+                    BB(index),
+                    value
+                ));
+            }
+        }
+    }
+
+    // every value is defined, so its type is known
+    for (index, bb) in function.basic_blocks.iter().enumerate() {
+        let in_block = |message: String| format!("{}: {}", BB(index), message);
+        for statement in &bb.statements {
+            let operation = match *statement {
+                Statement::WithDestination(_, ref operation) => operation,
+                Statement::Operation(ref operation) => operation,
+            };
+            verify_operand_types(module, function, &types, operation).map_err(in_block)?;
+        }
+
+        match bb.terminator {
+            Terminator::Return(value) => {
+                expect_type(&types, value, function.return_ty).map_err(in_block)?
+            }
+            Terminator::Branch(cond, _, _) => {
+                expect_type(&types, cond, Type::Boolean).map_err(in_block)?
+            }
+            Terminator::Jump(_) | Terminator::Unreachable => {}
+        }
+    }
+    Ok(())
+}
+
+fn verify_operation(
+    module: &Module,
+    function: &Function,
+    operation: &Operation,
+) -> Result<(), String> {
+    match *operation {
+        Operation::LoadParam(param) if param >= function.params.len() => {
+            Err(format!("unknown parameter {}", param))
+        }
+        Operation::LoadVariable(var) | Operation::StoreVariable(var, _)
+            if var.0 as usize >= function.variables.len() =>
+        {
+            Err(format!("unknown variable {}", var))
+        }
+        Operation::Call { callee, ref args } => match module.functions.get(callee) {
+            None => Err(format!("unknown function @{}", callee)),
+            Some(callee) if callee.params.len() != args.len() => Err(format!(
+                "`{}` takes {} arguments but {} were supplied",
+                callee.name,
+                callee.params.len(),
+                args.len()
+            )),
+            Some(_) => Ok(()),
+        },
+        _ => Ok(()),
+    }
+}
+
+/// Returns the type of the value computed by `operation`, which must be valid.
+fn result_type(module: &Module, function: &Function, operation: &Operation) -> Type {
+    match *operation {
+        Operation::Literal(Literal::Unit) | Operation::StoreVariable(..) => Type::Unit,
+        Operation::Literal(Literal::Int(_))
+        | Operation::BinaryAdd(..)
+        | Operation::BinarySub(..)
+        | Operation::BinaryMul(..)
+        | Operation::BinaryDivide(..)
+        | Operation::BinaryShiftLeft(..)
+        | Operation::Negate(_) => Type::Int,
+        Operation::Literal(Literal::Boolean(_)) | Operation::Compare(..) => Type::Boolean,
+        Operation::LoadParam(param) => function.params[param],
+        Operation::LoadVariable(var) => function.variables[var.0 as usize],
+        Operation::Call { callee, .. } => module.functions[callee].return_ty,
+    }
+}
+
+fn verify_operand_types(
+    module: &Module,
+    function: &Function,
+    types: &HashMap<Value, Type>,
+    operation: &Operation,
+) -> Result<(), String> {
+    match *operation {
+        Operation::BinaryAdd(lhs, rhs)
+        | Operation::BinarySub(lhs, rhs)
+        | Operation::BinaryMul(lhs, rhs)
+        | Operation::BinaryDivide(lhs, rhs)
+        | Operation::BinaryShiftLeft(lhs, rhs) => {
+            expect_type(types, lhs, Type::Int)?;
+            expect_type(types, rhs, Type::Int)
+        }
+        Operation::Negate(value) => expect_type(types, value, Type::Int),
+        Operation::Compare(comparison, lhs, rhs) => match comparison {
+            // equality is defined on every type, as long as both sides agree
+            Comparison::Equal | Comparison::NotEqual => expect_type(types, rhs, types[&lhs]),
+            _ => {
+                expect_type(types, lhs, Type::Int)?;
+                expect_type(types, rhs, Type::Int)
+            }
+        },
+        Operation::StoreVariable(var, value) => {
+            expect_type(types, value, function.variables[var.0 as usize])
+        }
+        Operation::Call { callee, ref args } => {
+            let params = &module.functions[callee].params;
+            for (&arg, &ty) in args.iter().zip(params) {
+                expect_type(types, arg, ty)?;
+            }
+            Ok(())
+        }
+        Operation::Literal(_) | Operation::LoadParam(_) | Operation::LoadVariable(_) => Ok(()),
+    }
+}
+
+fn expect_type(types: &HashMap<Value, Type>, value: Value, expected: Type) -> Result<(), String> {
+    let ty = types[&value];
+    if ty == expected {
+        Ok(())
+    } else {
+        Err(format!("{} has type {} instead of {}", value, ty, expected))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ir::text::parse_module;
+
+    fn error(input: &str) -> String {
+        parse_module(input).unwrap_err()
+    }
+
+    #[test]
+    fn test_unknown_indices() {
+        assert_eq!(
+            error("fn main() -> int {\nbb0:\n    jmp bb3\n}\n"),
+            "function `main`: bb0: unknown basic block bb3"
+        );
+        assert_eq!(
+            error("fn main() -> int {\nbb0:\n    %0 = load $4\n    ret %0\n}\n"),
+            "function `main`: bb0: unknown variable $4"
+        );
+        assert_eq!(
+            error("fn main() -> int {\nbb0:\n    %0 = call @3()\n    ret %0\n}\n"),
+            "function `main`: bb0: unknown function @3"
+        );
+        assert_eq!(
+            error("fn main() -> int {\nbb0:\n    %0 = param 0\n    ret %0\n}\n"),
+            "function `main`: bb0: unknown parameter 0"
+        );
+        assert_eq!(
+            error("fn main() -> int {\nbb0:\n    %0 = call @0(%0)\n    ret %0\n}\n"),
+            "function `main`: bb0: `main` takes 0 arguments but 1 were supplied"
+        );
+    }
+
+    #[test]
+    fn test_definitions() {
+        assert_eq!(
+            error("fn main() -> int {\nbb0:\n    ret %2\n}\n"),
+            "function `main`: bb0: %2 is never defined"
+        );
+        assert_eq!(
+            error("fn main() -> int {\nbb0:\n    %0 = const 1\n    %0 = const 2\n    ret %0\n}\n"),
+            "function `main`: bb0: %0 is defined twice"
+        );
+        assert_eq!(
+            error(
+                "fn f(bool) -> int {
+bb0:
+    %0 = param 0
+    br %0, bb1, bb2
+bb1:
+    %1 = const 1
+    jmp bb2
+bb2:
+    ret %1
+}
+"
+            ),
+            "function `f`: bb2: %1 is used before its definition"
+        );
+    }
+
+    #[test]
+    fn test_value_range() {
+        assert_eq!(
+            error("fn main() -> int {\nbb0:\n    %4000000000 = const 1\n    ret %4000000000\n}\n"),
+            "function `main`: bb0: %4000000000 is out of range"
+        );
+    }
+
+    #[test]
+    fn test_types() {
+        assert_eq!(
+            error("fn main() -> int {\nbb0:\n    %0 = const true\n    ret %0\n}\n"),
+            "function `main`: bb0: %0 has type bool instead of int"
+        );
+        assert_eq!(
+            error(
+                "fn main() -> int {
+bb0:
+    %0 = const 1
+    br %0, bb1, bb1
+bb1:
+    ret %0
+}
+"
+            ),
+            "function `main`: bb0: %0 has type int instead of bool"
+        );
+        assert_eq!(
+            error(
+                "fn f(int, bool) -> int {
+bb0:
+    %0 = param 0
+    %1 = param 1
+    %2 = add %0, %1
+    ret %2
+}
+"
+            ),
+            "function `f`: bb0: %1 has type bool instead of int"
+        );
+        assert_eq!(
+            error(
+                "fn f(bool) -> bool {
+bb0:
+    %0 = param 0
+    %1 = const 1
+    %2 = eq %0, %1
+    ret %2
+}
+"
+            ),
+            "function `f`: bb0: %1 has type int instead of bool"
+        );
+        assert_eq!(
+            error(
+                "fn f() -> () {
+    vars: int
+bb0:
+    %0 = const ()
+    store $0, %0
+    ret %0
+}
+"
+            ),
+            "function `f`: bb0: %0 has type () instead of int"
+        );
+        assert_eq!(
+            error(
+                "fn f(int) -> int {
+bb0:
+    %0 = const false
+    %1 = call @0(%0)
+    ret %1
+}
+"
+            ),
+            "function `f`: bb0: %0 has type bool instead of int"
+        );
+    }
+}
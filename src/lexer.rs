@@ -0,0 +1,130 @@
+//! Splitting of hai source into tokens, keeping the whitespace and comments as trivia tokens so
+//! that the source can be rebuilt from them.
+
+use crate::cst::SyntaxKind;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub kind: SyntaxKind,
+    pub text: String,
+    /// The byte offset of the token in the source.
+    pub offset: usize,
+}
+
+impl Token {
+    pub fn end(&self) -> usize {
+        self.offset + self.text.len()
+    }
+}
+
+/// Splits `source[start..end]` into tokens. Every character ends up in a token, characters that
+/// can't start one and unterminated block comments are lexed as `Error` tokens.
+pub fn tokenize_range(source: &str, start: usize, end: usize) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut offset = start;
+    while offset < end {
+        let rest = &source[offset..end];
+        let (kind, len) = lex_token(rest);
+        tokens.push(Token {
+            kind,
+            text: rest[..len].to_string(),
+            offset,
+        });
+        offset += len;
+    }
+    tokens
+}
+
+pub fn tokenize(source: &str) -> Vec<Token> {
+    tokenize_range(source, 0, source.len())
+}
+
+/// Returns the kind and the length of the token at the start of `text`.
+fn lex_token(text: &str) -> (SyntaxKind, usize) {
+    let bytes = text.as_bytes();
+    let first = text.chars().next().unwrap();
+
+    if first.is_whitespace() {
+        let len = text
+            .find(|c: char| !c.is_whitespace())
+            .unwrap_or(text.len());
+        return (SyntaxKind::Whitespace, len);
+    }
+    if text.starts_with("//") {
+        return (
+            SyntaxKind::LineComment,
+            text.find('\n').unwrap_or(text.len()),
+        );
+    }
+    if text.starts_with("/*") {
+        return match block_comment_len(bytes) {
+            Some(len) => (SyntaxKind::BlockComment, len),
+            None => (SyntaxKind::Error, text.len()),
+        };
+    }
+    if first.is_ascii_alphabetic() || first == '_' {
+        let len = text
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(text.len());
+        return (SyntaxKind::from_keyword(&text[..len]), len);
+    }
+    if first.is_ascii_digit() {
+        let len = text
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(text.len());
+        return (SyntaxKind::Integer, len);
+    }
+
+    let two = match bytes.get(..2) {
+        Some(b"->") => Some(SyntaxKind::Arrow),
+        Some(b"==") => Some(SyntaxKind::EqualEqual),
+        Some(b"!=") => Some(SyntaxKind::NotEqual),
+        Some(b"<=") => Some(SyntaxKind::LessEqual),
+        Some(b">=") => Some(SyntaxKind::GreaterEqual),
+        _ => None,
+    };
+    if let Some(kind) = two {
+        return (kind, 2);
+    }
+
+    let kind = match first {
+        '(' => SyntaxKind::LeftParen,
+        ')' => SyntaxKind::RightParen,
+        '{' => SyntaxKind::LeftBrace,
+        '}' => SyntaxKind::RightBrace,
+        ',' => SyntaxKind::Comma,
+        ':' => SyntaxKind::Colon,
+        ';' => SyntaxKind::Semicolon,
+        '=' => SyntaxKind::Equal,
+        '+' => SyntaxKind::Plus,
+        '-' => SyntaxKind::Minus,
+        '*' => SyntaxKind::Star,
+        '/' => SyntaxKind::Slash,
+        '<' => SyntaxKind::Less,
+        '>' => SyntaxKind::Greater,
+        _ => SyntaxKind::Error,
+    };
+    (kind, first.len_utf8())
+}
+
+/// Returns the length of the block comment at the start of `bytes`, block comments nest. `None`
+/// is returned when the comment is unterminated.
+fn block_comment_len(bytes: &[u8]) -> Option<usize> {
+    let mut depth = 0;
+    let mut index = 0;
+    while index < bytes.len() {
+        if bytes[index..].starts_with(b"/*") {
+            depth += 1;
+            index += 2;
+        } else if bytes[index..].starts_with(b"*/") {
+            depth -= 1;
+            index += 2;
+            if depth == 0 {
+                return Some(index);
+            }
+        } else {
+            index += 1;
+        }
+    }
+    None
+}
@@ -0,0 +1,109 @@
+//! The hai compiler.
+//!
+//! [`parse`] turns a source file into an [`ast::Program`], derived from the lossless
+//! [`cst::Node`] returned by [`parse_cst`], [`lower`] turns a program into an
+//! [`ir::Module`] and [`compile`] does both before optimizing the module. The modules behind them
+//! are public for the tools that need more control, like the back-ends.
+//!
+//! [`tokenize`] and [`parse_ir`] give access to the other inputs of the command-line driver.
+//! [`interpreter`] runs a program without compiling it, and [`driver`] implements the `hai`
+//! command.
+
+extern crate id_arena;
+
+use std::fmt;
+
+pub mod ast;
+pub mod backend;
+pub mod convertor;
+pub mod cst;
+pub mod driver;
+pub mod interpreter;
+pub mod ir;
+pub mod lexer;
+pub mod parser;
+pub mod vm;
+
+/// An error stopping the compilation of a source file.
+#[derive(Debug)]
+pub enum Error {
+    /// The source doesn't follow the grammar.
+    Syntax(Vec<parser::SyntaxError>),
+    /// The program is well-formed but refers to unknown names or mismatches types.
+    Semantic(Vec<convertor::Error>),
+    /// The textual IR is invalid.
+    Ir(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Syntax(ref errors) => {
+                for (index, error) in errors.iter().enumerate() {
+                    if index != 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "syntax error: {}", error)?;
+                }
+                Ok(())
+            }
+            Error::Semantic(ref errors) => {
+                for (index, error) in errors.iter().enumerate() {
+                    if index != 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "{}", error)?;
+                }
+                Ok(())
+            }
+            Error::Ir(ref message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Splits a source file into tokens, the whitespace and the comments included. Characters that
+/// can't start a token are `Error` tokens, they are reported when parsing.
+pub fn tokenize(source: &str) -> Vec<lexer::Token> {
+    lexer::tokenize(source)
+}
+
+/// Parses a source file into its concrete syntax tree, from which the source can be written back.
+/// Every syntax error of the file is reported.
+pub fn parse_cst(source: &str) -> Result<cst::Node, Error> {
+    match parser::parse(source) {
+        (tree, ref errors) if errors.is_empty() => Ok(tree),
+        (_, errors) => Err(Error::Syntax(errors)),
+    }
+}
+
+/// Parses a source file into its syntax tree.
+pub fn parse(source: &str) -> Result<ast::Program, Error> {
+    Ok(parser::convert_program(&parse_cst(source)?))
+}
+
+/// Formats a source file as canonical hai code, keeping its comments.
+pub fn format(source: &str) -> Result<String, Error> {
+    let tree = parse_cst(source)?;
+    let program = parser::convert_program(&tree);
+    Ok(ast::format::format_program(&program, &tree))
+}
+
+/// Lowers a program to the IR, reporting every error found on the way.
+pub fn lower(program: &ast::Program) -> Result<ir::Module, Error> {
+    convertor::ast2ir(program).map_err(Error::Semantic)
+}
+
+/// Parses a module written in the textual IR.
+pub fn parse_ir(source: &str) -> Result<ir::Module, Error> {
+    ir::text::parse_module(source).map_err(Error::Ir)
+}
+
+/// Parses and lowers a source file, then optimizes the module at `opt_level` (0 to 2).
+pub fn compile(source: &str, opt_level: u32) -> Result<ir::Module, Error> {
+    let program = parse(source)?;
+    let mut module = lower(&program)?;
+    ir::opt::optimize_module(&mut module, opt_level);
+    Ok(module)
+}
@@ -1,246 +1,1078 @@
-use pest::iterators::Pair;
-use pest::prec_climber::*;
+//! Parsing of hai source into its concrete syntax tree, and conversion of the tree to the AST.
+//!
+//! The parser is a recursive descent one, binary expressions are parsed by precedence climbing.
+//! The grammar is:
+//!
+//! ```text
+//! program              = function_declaration+
+//! function_declaration = "fn" identifier "(" parameter_list ")" ("->" type)? block_statement
+//! parameter_list       = (parameter ("," parameter)*)?
+//! parameter            = identifier ":" type
+//! type                 = "int" | "bool" | "(" ")"
+//!
+//! statement            = let_statement | return_statement | while_statement | if_statement
+//!                      | assign_statement | expression_statement | block_statement
+//! block_statement      = "{" statement* "}"
+//! let_statement        = "let" identifier "=" expression ";"
+//! return_statement     = "return" expression? ";"
+//! while_statement      = "while" expression block_statement
+//! if_statement         = "if" expression block_statement ("else" (if_statement | block_statement))?
+//! assign_statement     = identifier "=" expression ";"
+//! expression_statement = expression ";"
+//!
+//! expression           = term (binary_operator term)*
+//! term                 = integer | "true" | "false" | identifier | function_call
+//!                      | "(" expression ")"
+//! function_call        = identifier "(" (expression ("," expression)*)? ")"
+//! ```
+//!
+//! The operators are left associative, `*` and `/` bind tighter than `+` and `-`, which bind
+//! tighter than the comparisons.
+//!
+//! Parsing goes on after an error: a missing token is reported and skipped, code that can't
+//! start a statement or a declaration is wrapped in an `Error` node up to the next `;` or `}`, or
+//! the next `fn` at the top level. Only one error is reported per position, so a missing token
+//! doesn't also report the tokens expected after it.
 
 use crate::ast;
+use crate::cst::{Element, Node, SyntaxKind, Token};
+use crate::lexer;
 
-#[derive(Parser)]
-#[grammar = "grammar.pest"]
-pub struct HaiParser;
+use std::fmt;
 
-pub fn convert_program<'i>(pair: Pair<'i, Rule>) -> ast::Program {
-    assert_eq!(pair.as_rule(), Rule::program);
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxError {
+    pub span: ast::Span,
+    pub message: String,
+    /// How to fix the error, when it looks like a common mistake.
+    pub hint: Option<String>,
+}
 
-    let mut program = ast::Program::new();
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
 
-    for pair in pair.into_inner() {
-        let declaration = convert_declaration(pair, &mut program);
-        program.declarations.push(declaration);
+/// Parses a source file into its concrete syntax tree. The tree holds the whole source even when
+/// there are errors, but it only has the shape expected by the `convert_*` functions without them.
+pub fn parse(source: &str) -> (Node, Vec<SyntaxError>) {
+    let mut parser = Parser {
+        source,
+        tokens: lexer::tokenize(source),
+        position: 0,
+        last_end: 0,
+        frames: Vec::new(),
+        errors: Vec::new(),
+        expected: Vec::new(),
+        too_deep: false,
+        deepest: 0,
+    };
+    let program = parser.program();
+    // invalid characters are reported when they are added to the tree, after the errors found
+    // by looking past them
+    parser.errors.sort_by_key(|error| error.span.start);
+    (program, parser.errors)
+}
+
+/// The depth of the tree beyond which the rest of the file is skipped, each operator of a chain
+/// like `1 + 1 + 1` counting as one level. The parser and the passes after it are recursive,
+/// this keeps them from overflowing the stack.
+const MAX_NESTING: usize = 256;
+
+const EXPRESSION_START: [SyntaxKind; 5] = [
+    SyntaxKind::Identifier,
+    SyntaxKind::Integer,
+    SyntaxKind::TrueKeyword,
+    SyntaxKind::FalseKeyword,
+    SyntaxKind::LeftParen,
+];
+
+const STATEMENT_START: [SyntaxKind; 5] = [
+    SyntaxKind::LetKeyword,
+    SyntaxKind::ReturnKeyword,
+    SyntaxKind::WhileKeyword,
+    SyntaxKind::IfKeyword,
+    SyntaxKind::LeftBrace,
+];
+
+/// Lists the expected kinds for an error message, like "`,` or `)`". Kinds starting expressions
+/// or statements are grouped when they are all expected.
+fn describe_expected(kinds: &[SyntaxKind]) -> String {
+    let expects_all = |group: &[SyntaxKind]| group.iter().all(|kind| kinds.contains(kind));
+    let statement = expects_all(&STATEMENT_START) && expects_all(&EXPRESSION_START);
+    let expression = expects_all(&EXPRESSION_START);
+
+    let mut descriptions: Vec<&str> = Vec::new();
+    for kind in kinds {
+        let description =
+            if statement && (STATEMENT_START.contains(kind) || EXPRESSION_START.contains(kind)) {
+                "a statement"
+            } else if expression && EXPRESSION_START.contains(kind) {
+                "an expression"
+            } else {
+                kind.description()
+            };
+        if !descriptions.contains(&description) {
+            descriptions.push(description);
+        }
     }
 
-    program
+    match descriptions.split_last() {
+        Some((last, [])) => last.to_string(),
+        Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+        None => "something else".to_string(),
+    }
 }
 
-pub fn convert_declaration<'i>(
-    pair: Pair<'i, Rule>,
-    program: &mut ast::Program,
-) -> ast::Declaration {
-    assert_eq!(pair.as_rule(), Rule::declaration);
+/// Returns the binding strength of a binary operator, higher binds tighter.
+fn binary_precedence(kind: SyntaxKind) -> Option<u8> {
+    match kind {
+        SyntaxKind::Star | SyntaxKind::Slash => Some(3),
+        SyntaxKind::Plus | SyntaxKind::Minus => Some(2),
+        SyntaxKind::EqualEqual
+        | SyntaxKind::NotEqual
+        | SyntaxKind::Less
+        | SyntaxKind::LessEqual
+        | SyntaxKind::Greater
+        | SyntaxKind::GreaterEqual => Some(1),
+        _ => None,
+    }
+}
 
-    let pair = pair.into_inner().next().unwrap();
-    match pair.as_rule() {
-        Rule::function_declaration => convert_function_declaration(pair, program),
-        _ => unreachable!(),
+struct Parser<'s> {
+    source: &'s str,
+    tokens: Vec<Token>,
+    /// The index of the next token to add to the tree, trivia included.
+    position: usize,
+    /// The end of the last token added to the tree, trivia excluded.
+    last_end: usize,
+    /// The nodes being built, from the root to the innermost one, with their children so far.
+    frames: Vec<(SyntaxKind, Vec<Element>)>,
+    errors: Vec<SyntaxError>,
+    /// The kinds looked for at the current token, listed if none of them is there.
+    expected: Vec<SyntaxKind>,
+    /// Whether the nesting went past `MAX_NESTING`, the errors after it are not reported.
+    too_deep: bool,
+    /// The depth of the deepest node started in the current binary expression, which each
+    /// operator pushes one level down.
+    deepest: usize,
+}
+
+impl<'s> Parser<'s> {
+    /// Returns the `n`-th token from the position, skipping the trivia and invalid characters.
+    fn nth(&self, n: usize) -> Option<&Token> {
+        self.tokens[self.position..]
+            .iter()
+            .filter(|token| !token.kind.is_trivia() && token.kind != SyntaxKind::Error)
+            .nth(n)
+    }
+
+    fn current(&self) -> Option<SyntaxKind> {
+        self.nth(0).map(|token| token.kind)
     }
+
+    fn at(&mut self, kind: SyntaxKind) -> bool {
+        self.expected.push(kind);
+        self.current() == Some(kind)
+    }
+
+    fn at_any(&mut self, kinds: &[SyntaxKind]) -> bool {
+        self.expected.extend_from_slice(kinds);
+        self.current().is_some_and(|kind| kinds.contains(&kind))
+    }
+
+    fn eat(&mut self, kind: SyntaxKind) -> bool {
+        let found = self.at(kind);
+        if found {
+            self.bump();
+        }
+        found
+    }
+
+    fn expect(&mut self, kind: SyntaxKind) {
+        if self.eat(kind) {
+            return;
+        }
+        if kind == SyntaxKind::Semicolon {
+            // the `;` is missing right after the statement, not at the code following it
+            let span = ast::Span::new(self.last_end, self.last_end);
+            if self.error_expected_at(span) {
+                self.hint("add a `;` at the end of the statement".to_string());
+            }
+        } else {
+            self.error_expected();
+        }
+    }
+
+    fn push(&mut self, element: Element) {
+        self.frames.last_mut().unwrap().1.push(element);
+    }
+
+    /// Moves the token at the position to the tree.
+    fn take(&mut self) {
+        let token = &mut self.tokens[self.position];
+        let token = Token {
+            kind: token.kind,
+            text: std::mem::take(&mut token.text),
+            offset: token.offset,
+        };
+        self.push(Element::Token(token));
+        self.position += 1;
+    }
+
+    /// Adds the trivia and the invalid characters before the next token to the current node.
+    fn skip_trivia(&mut self) {
+        while let Some(token) = self.tokens.get(self.position) {
+            if token.kind == SyntaxKind::Error {
+                let message = if token.text.starts_with("/*") {
+                    "unterminated block comment".to_string()
+                } else {
+                    format!("unexpected character `{}`", token.text)
+                };
+                let span = ast::Span::new(token.offset, token.end());
+                self.error(span, message);
+            } else if !token.kind.is_trivia() {
+                break;
+            }
+            self.take();
+        }
+    }
+
+    /// Adds the next token to the current node.
+    fn bump(&mut self) {
+        self.skip_trivia();
+        self.last_end = self.tokens[self.position].end();
+        self.take();
+        self.expected.clear();
+    }
+
+    fn start_node(&mut self, kind: SyntaxKind) {
+        if !self.frames.is_empty() {
+            self.skip_trivia();
+        }
+        self.frames.push((kind, Vec::new()));
+        self.deepest = self.deepest.max(self.frames.len());
+    }
+
+    /// Returns a point of the current node where a node can be started later, with
+    /// `start_node_at`, to wrap what was parsed since.
+    fn checkpoint(&mut self) -> usize {
+        self.skip_trivia();
+        self.frames.last().unwrap().1.len()
+    }
+
+    fn start_node_at(&mut self, checkpoint: usize, kind: SyntaxKind) {
+        let children = self.frames.last_mut().unwrap().1.split_off(checkpoint);
+        self.frames.push((kind, children));
+    }
+
+    fn finish_node(&mut self) {
+        let (kind, children) = self.frames.pop().unwrap();
+        self.push(Element::Node(Node::new(kind, children)));
+    }
+
+    /// Reports an error, unless one was already reported at the same position. Returns whether
+    /// it was reported.
+    fn error(&mut self, span: ast::Span, message: String) -> bool {
+        if self.too_deep {
+            return false;
+        }
+        if self.errors.last().map(|error| error.span.start) == Some(span.start) {
+            return false;
+        }
+        self.errors.push(SyntaxError {
+            span,
+            message,
+            hint: None,
+        });
+        true
+    }
+
+    /// Adds a hint to the last error reported.
+    fn hint(&mut self, hint: String) {
+        self.errors.last_mut().unwrap().hint = Some(hint);
+    }
+
+    /// Reports that none of the expected kinds is at the current token.
+    fn error_expected(&mut self) -> bool {
+        let span = match self.nth(0) {
+            Some(token) => ast::Span::new(token.offset, token.end()),
+            None => ast::Span::new(self.source.len(), self.source.len()),
+        };
+        self.error_expected_at(span)
+    }
+
+    fn error_expected_at(&mut self, span: ast::Span) -> bool {
+        let expected = describe_expected(&self.expected);
+        self.expected.clear();
+        let found = match self.nth(0) {
+            Some(token) => format!("`{}`", token.text),
+            None => "the end of the file".to_string(),
+        };
+        self.error(span, format!("expected {}, found {}", expected, found))
+    }
+
+    /// Reports an error and skips the rest of the file if the nesting is too deep to go on.
+    /// Returns whether it did.
+    fn check_nesting(&mut self) -> bool {
+        self.check_depth(self.frames.len())
+    }
+
+    /// Like `check_nesting`, for a node to be nested at `depth`.
+    fn check_depth(&mut self, depth: usize) -> bool {
+        if depth < MAX_NESTING {
+            return false;
+        }
+        let span = match self.nth(0) {
+            Some(token) => ast::Span::new(token.offset, token.end()),
+            None => ast::Span::new(self.source.len(), self.source.len()),
+        };
+        if self.error(span, "the code is nested too deeply".to_string()) {
+            self.hint("move parts of it to variables or functions".to_string());
+        }
+        self.too_deep = true;
+
+        self.start_node(SyntaxKind::Error);
+        while self.position < self.tokens.len() {
+            self.take();
+        }
+        self.finish_node();
+        true
+    }
+
+    /// Returns the 1-based line of a position.
+    fn line(&self, offset: usize) -> usize {
+        self.source[..offset].matches('\n').count() + 1
+    }
+
+    /// Wraps the tokens up to `stop` in an `Error` node. Braces are skipped in pairs, so that
+    /// the `}` closing the code around isn't skipped.
+    fn skip_until(&mut self, stop: impl Fn(SyntaxKind) -> bool) {
+        self.start_node(SyntaxKind::Error);
+        let mut depth = 0;
+        while let Some(kind) = self.current() {
+            if depth == 0 && stop(kind) {
+                break;
+            }
+            match kind {
+                SyntaxKind::LeftBrace => depth += 1,
+                SyntaxKind::RightBrace if depth > 0 => depth -= 1,
+                SyntaxKind::RightBrace => break,
+                _ => {}
+            }
+            self.bump();
+            if depth == 0 && kind == SyntaxKind::Semicolon {
+                break;
+            }
+        }
+        self.finish_node();
+    }
+
+    fn program(&mut self) -> Node {
+        self.start_node(SyntaxKind::Program);
+        let mut has_declaration = false;
+        loop {
+            if self.at(SyntaxKind::FnKeyword) {
+                self.function_declaration();
+                has_declaration = true;
+            } else if self.current().is_none() {
+                if !has_declaration {
+                    self.error_expected();
+                }
+                break;
+            } else {
+                if self.error_expected() && self.current() == Some(SyntaxKind::RightBrace) {
+                    self.hint("this `}` has no matching `{`".to_string());
+                }
+                // a `}` closes nothing at the top level, so it is skipped too
+                self.start_node(SyntaxKind::Error);
+                while self.current().is_some() && self.current() != Some(SyntaxKind::FnKeyword) {
+                    self.bump();
+                }
+                self.finish_node();
+            }
+        }
+        self.skip_trivia();
+        let (kind, children) = self.frames.pop().unwrap();
+        Node::new(kind, children)
+    }
+
+    fn function_declaration(&mut self) {
+        self.start_node(SyntaxKind::FunctionDeclaration);
+        self.bump();
+        self.expect(SyntaxKind::Identifier);
+        self.expect(SyntaxKind::LeftParen);
+
+        self.start_node(SyntaxKind::ParameterList);
+        if self.at(SyntaxKind::Identifier) {
+            self.parameter();
+            while self.eat(SyntaxKind::Comma) {
+                self.parameter();
+            }
+        }
+        self.finish_node();
+        self.expect(SyntaxKind::RightParen);
+
+        if self.eat(SyntaxKind::Arrow) {
+            self.ty();
+        }
+        if self.at(SyntaxKind::LeftBrace) {
+            self.block_statement();
+        } else {
+            if self.error_expected() {
+                self.hint("a function needs a body, even an empty one like `{}`".to_string());
+            }
+            // `fn f();` declares nothing, the `;` is skipped with the function
+            if self.current() == Some(SyntaxKind::Semicolon) {
+                self.start_node(SyntaxKind::Error);
+                self.bump();
+                self.finish_node();
+            }
+        }
+        self.finish_node();
+    }
+
+    fn parameter(&mut self) {
+        self.start_node(SyntaxKind::Parameter);
+        self.expect(SyntaxKind::Identifier);
+        self.expect(SyntaxKind::Colon);
+        self.ty();
+        self.finish_node();
+    }
+
+    fn ty(&mut self) {
+        self.start_node(SyntaxKind::Type);
+        match self.nth(0) {
+            Some(token) if token.text == "int" || token.text == "bool" => self.bump(),
+            Some(token) if token.kind == SyntaxKind::LeftParen => {
+                self.bump();
+                self.expect(SyntaxKind::RightParen);
+            }
+            token => {
+                let is_identifier = token.is_some_and(|token| token.kind == SyntaxKind::Identifier);
+                self.expected.push(SyntaxKind::Type);
+                self.error_expected();
+                // an unknown type name is still a type, for the parsing to go on
+                if is_identifier {
+                    self.bump();
+                }
+            }
+        }
+        self.finish_node();
+    }
+
+    /// Parses a block, or reports its absence.
+    fn block_statement(&mut self) {
+        if !self.at(SyntaxKind::LeftBrace) {
+            self.error_expected();
+            return;
+        }
+
+        self.start_node(SyntaxKind::BlockStatement);
+        let open_line = self.line(self.nth(0).unwrap().offset);
+        self.bump();
+        loop {
+            if self.at_any(&STATEMENT_START) || self.at_any(&EXPRESSION_START) {
+                self.statement();
+            } else if self.at(SyntaxKind::RightBrace)
+                || self.current().is_none()
+                || self.current() == Some(SyntaxKind::FnKeyword)
+            {
+                // a `fn` is most likely the next declaration after a missing `}`
+                break;
+            } else {
+                self.error_expected();
+                self.skip_until(|kind| kind == SyntaxKind::FnKeyword);
+            }
+        }
+        if !self.eat(SyntaxKind::RightBrace) && self.error_expected() {
+            let message = format!("the `{{` on line {} is never closed", open_line);
+            self.hint(message);
+        }
+        self.finish_node();
+    }
+
+    fn statement(&mut self) {
+        if self.check_nesting() {
+            return;
+        }
+        match self.current() {
+            Some(SyntaxKind::LetKeyword) => self.let_statement(),
+            Some(SyntaxKind::ReturnKeyword) => self.return_statement(),
+            Some(SyntaxKind::WhileKeyword) => self.while_statement(),
+            Some(SyntaxKind::IfKeyword) => self.if_statement(),
+            Some(SyntaxKind::LeftBrace) => self.block_statement(),
+            Some(SyntaxKind::Identifier)
+                if self.nth(1).map(|token| token.kind) == Some(SyntaxKind::Equal) =>
+            {
+                self.assign_statement()
+            }
+            _ => self.expression_statement(),
+        }
+    }
+
+    fn let_statement(&mut self) {
+        self.start_node(SyntaxKind::LetStatement);
+        self.bump();
+        self.expect(SyntaxKind::Identifier);
+        self.expect(SyntaxKind::Equal);
+        self.expression();
+        self.expect(SyntaxKind::Semicolon);
+        self.finish_node();
+    }
+
+    fn return_statement(&mut self) {
+        self.start_node(SyntaxKind::ReturnStatement);
+        self.bump();
+        if !self.at(SyntaxKind::Semicolon) {
+            self.expression();
+        }
+        self.expect(SyntaxKind::Semicolon);
+        self.finish_node();
+    }
+
+    fn while_statement(&mut self) {
+        self.start_node(SyntaxKind::WhileStatement);
+        self.bump();
+        self.expression();
+        self.block_statement();
+        self.finish_node();
+    }
+
+    fn if_statement(&mut self) {
+        self.start_node(SyntaxKind::IfStatement);
+        self.bump();
+        self.expression();
+        self.block_statement();
+        if self.eat(SyntaxKind::ElseKeyword) {
+            if self.at(SyntaxKind::IfKeyword) {
+                self.if_statement();
+            } else {
+                self.block_statement();
+            }
+        }
+        self.finish_node();
+    }
+
+    fn assign_statement(&mut self) {
+        self.start_node(SyntaxKind::AssignStatement);
+        self.bump();
+        self.bump();
+        self.expression();
+        self.expect(SyntaxKind::Semicolon);
+        self.finish_node();
+    }
+
+    fn expression_statement(&mut self) {
+        self.start_node(SyntaxKind::ExpressionStatement);
+        self.expression();
+        self.expect(SyntaxKind::Semicolon);
+        self.finish_node();
+    }
+
+    fn expression(&mut self) {
+        if self.check_nesting() {
+            return;
+        }
+        self.binary_expression(1);
+    }
+
+    /// Parses the operators binding at least as tightly as `min_precedence`, the ones binding
+    /// less are left to the callers.
+    fn binary_expression(&mut self, min_precedence: u8) {
+        let checkpoint = self.checkpoint();
+        let outer_deepest = std::mem::replace(&mut self.deepest, 0);
+        self.term();
+        while let Some(precedence) = self.current().and_then(binary_precedence) {
+            if precedence < min_precedence {
+                break;
+            }
+            // the new node wraps the operands so far, a long chain nests as much as parentheses
+            if self.check_depth(self.deepest + 1) {
+                break;
+            }
+            self.deepest += 1;
+            self.start_node_at(checkpoint, SyntaxKind::BinaryExpression);
+            self.bump();
+            // operators are left associative, the right operand stops at the same precedence
+            self.binary_expression(precedence + 1);
+            self.finish_node();
+        }
+        self.deepest = self.deepest.max(outer_deepest);
+    }
+
+    fn term(&mut self) {
+        match self.current() {
+            Some(SyntaxKind::Integer) => {
+                self.start_node(SyntaxKind::Literal);
+                let token = self.nth(0).unwrap();
+                if token.text.parse::<i32>().is_err() {
+                    let span = ast::Span::new(token.offset, token.end());
+                    self.error(span, "integer literal is too large".to_string());
+                }
+                self.bump();
+                self.finish_node();
+            }
+            Some(SyntaxKind::TrueKeyword) | Some(SyntaxKind::FalseKeyword) => {
+                self.start_node(SyntaxKind::Literal);
+                self.bump();
+                self.finish_node();
+            }
+            Some(SyntaxKind::Identifier)
+                if self.nth(1).map(|token| token.kind) == Some(SyntaxKind::LeftParen) =>
+            {
+                self.function_call()
+            }
+            Some(SyntaxKind::Identifier) => {
+                self.start_node(SyntaxKind::Name);
+                self.bump();
+                self.finish_node();
+            }
+            Some(SyntaxKind::LeftParen) => {
+                self.start_node(SyntaxKind::ParenExpression);
+                self.bump();
+                self.expression();
+                self.expect(SyntaxKind::RightParen);
+                self.finish_node();
+            }
+            _ => {
+                self.at_any(&EXPRESSION_START);
+                self.error_expected();
+                // the token is kept in the expression unless the code around needs it
+                self.start_node(SyntaxKind::Error);
+                let is_boundary = match self.current() {
+                    Some(kind) => {
+                        STATEMENT_START.contains(&kind)
+                            || matches!(
+                                kind,
+                                SyntaxKind::Semicolon
+                                    | SyntaxKind::Comma
+                                    | SyntaxKind::RightParen
+                                    | SyntaxKind::RightBrace
+                                    | SyntaxKind::FnKeyword
+                            )
+                    }
+                    None => true,
+                };
+                if !is_boundary {
+                    self.bump();
+                }
+                self.finish_node();
+            }
+        }
+    }
+
+    fn function_call(&mut self) {
+        self.start_node(SyntaxKind::FunctionCall);
+        self.start_node(SyntaxKind::Name);
+        self.bump();
+        self.finish_node();
+        self.bump();
+
+        self.start_node(SyntaxKind::ExpressionList);
+        if !self.at(SyntaxKind::RightParen) {
+            self.expression();
+            while self.eat(SyntaxKind::Comma) {
+                self.expression();
+            }
+        }
+        self.finish_node();
+        self.expect(SyntaxKind::RightParen);
+        self.finish_node();
+    }
+}
+
+fn span(token: &Token) -> ast::Span {
+    ast::Span::new(token.offset, token.end())
+}
+
+fn identifier(node: &Node) -> &Token {
+    node.child_token(SyntaxKind::Identifier).unwrap()
 }
 
-pub fn convert_function_declaration<'i>(
-    pair: Pair<'i, Rule>,
-    program: &mut ast::Program,
-) -> ast::Declaration {
-    assert_eq!(pair.as_rule(), Rule::function_declaration);
+pub fn convert_program(node: &Node) -> ast::Program {
+    assert_eq!(node.kind, SyntaxKind::Program);
+
+    let mut program = ast::Program::new();
+
+    for node in node.child_nodes() {
+        let declaration = convert_function_declaration(node, &mut program);
+        program.declarations.push(declaration);
+    }
+
+    program
+}
 
-    let mut iter = pair.into_inner();
+pub fn convert_function_declaration(node: &Node, program: &mut ast::Program) -> ast::Declaration {
+    assert_eq!(node.kind, SyntaxKind::FunctionDeclaration);
 
-    let name = String::from(iter.next().unwrap().as_str());
-    let parameters = convert_parameter_list(iter.next().unwrap());
+    let name = identifier(node);
+    let (parameters, parameter_spans) =
+        convert_parameter_list(node.child_node(SyntaxKind::ParameterList).unwrap());
+    let return_ty = node
+        .child_node(SyntaxKind::Type)
+        .map_or(ast::Type::Unit, convert_type);
 
-    let statement = convert_block_statement(iter.next().unwrap(), program);
-    let statement = program.create_statement(statement);
+    let body = node.child_node(SyntaxKind::BlockStatement).unwrap();
+    let statement = convert_block_statement(body, program);
+    let statement = program.create_statement(statement, body.span());
 
     ast::Declaration::FunctionDeclaration {
-        name,
+        name: name.text.clone(),
         parameters,
+        return_ty,
         statement,
+        span: node.span(),
+        name_span: span(name),
+        parameter_spans,
     }
 }
 
-pub fn convert_parameter_list<'i>(pair: Pair<'i, Rule>) -> Vec<(String, ast::Type)> {
-    assert_eq!(pair.as_rule(), Rule::parameter_list);
-    pair.into_inner().map(|p| convert_parameter(p)).collect()
+/// Returns the parameters, and the span of each parameter name.
+pub fn convert_parameter_list(node: &Node) -> (Vec<(String, ast::Type)>, Vec<ast::Span>) {
+    assert_eq!(node.kind, SyntaxKind::ParameterList);
+    node.child_nodes().map(convert_parameter).unzip()
 }
 
-pub fn convert_parameter<'i>(pair: Pair<'i, Rule>) -> (String, ast::Type) {
-    assert_eq!(pair.as_rule(), Rule::parameter);
+pub fn convert_parameter(node: &Node) -> ((String, ast::Type), ast::Span) {
+    assert_eq!(node.kind, SyntaxKind::Parameter);
 
-    let mut iter = pair.into_inner();
-    let name = String::from(iter.next().unwrap().as_str());
-    let ty = convert_type(iter.next().unwrap());
+    let name = identifier(node);
+    let ty = convert_type(node.child_node(SyntaxKind::Type).unwrap());
 
-    (name, ty)
+    ((name.text.clone(), ty), span(name))
 }
 
-pub fn convert_statement<'i>(pair: Pair<'i, Rule>, program: &mut ast::Program) -> ast::Statement {
-    assert_eq!(pair.as_rule(), Rule::statement);
+/// Converts a statement node and stores it in the program.
+fn create_statement(node: &Node, program: &mut ast::Program) -> ast::StatementId {
+    let statement = convert_statement(node, program);
+    program.create_statement(statement, node.span())
+}
 
-    let statement_pair = pair.into_inner().next().unwrap();
-    match statement_pair.as_rule() {
-        Rule::expression_statement => convert_expression_statement(statement_pair, program),
-        Rule::let_statement => convert_let_statement(statement_pair, program),
-        Rule::block_statement => convert_block_statement(statement_pair, program),
+pub fn convert_statement(node: &Node, program: &mut ast::Program) -> ast::Statement {
+    match node.kind {
+        SyntaxKind::ExpressionStatement => convert_expression_statement(node, program),
+        SyntaxKind::LetStatement => convert_let_statement(node, program),
+        SyntaxKind::ReturnStatement => convert_return_statement(node, program),
+        SyntaxKind::WhileStatement => convert_while_statement(node, program),
+        SyntaxKind::IfStatement => convert_if_statement(node, program),
+        SyntaxKind::AssignStatement => convert_assign_statement(node, program),
+        SyntaxKind::BlockStatement => convert_block_statement(node, program),
         _ => unreachable!(),
     }
 }
 
-pub fn convert_expression_statement<'i>(
-    pair: Pair<'i, Rule>,
-    program: &mut ast::Program,
-) -> ast::Statement {
-    assert_eq!(pair.as_rule(), Rule::expression_statement);
-    let pair = pair.into_inner().next().unwrap();
-    let expression = convert_expression(pair, program);
-    let id = program.create_expression(expression);
+pub fn convert_expression_statement(node: &Node, program: &mut ast::Program) -> ast::Statement {
+    assert_eq!(node.kind, SyntaxKind::ExpressionStatement);
+    let id = convert_expression(node.child_nodes().next().unwrap(), program);
     ast::Statement::ExpressionStatement(id)
 }
 
-pub fn convert_let_statement<'i>(
-    pair: Pair<'i, Rule>,
-    program: &mut ast::Program,
-) -> ast::Statement {
-    assert_eq!(pair.as_rule(), Rule::let_statement);
+pub fn convert_let_statement(node: &Node, program: &mut ast::Program) -> ast::Statement {
+    assert_eq!(node.kind, SyntaxKind::LetStatement);
 
-    let mut inner = pair.into_inner();
-    let identifier = String::from(inner.next().unwrap().as_str());
-    let expression = convert_expression(inner.next().unwrap(), program);
-    let expression = program.create_expression(expression);
+    let identifier = identifier(node);
+    let expression = convert_expression(node.child_nodes().next().unwrap(), program);
     ast::Statement::LetStatement {
-        identifier,
+        identifier: identifier.text.clone(),
+        identifier_span: span(identifier),
         expression,
     }
 }
 
-pub fn convert_block_statement<'i>(
-    pair: Pair<'i, Rule>,
-    program: &mut ast::Program,
-) -> ast::Statement {
-    assert_eq!(pair.as_rule(), Rule::block_statement);
-
-    let mut stmts = Vec::new();
-
-    for s in pair.into_inner() {
-        let s = convert_statement(s, program);
-        let s = program.create_statement(s);
-        stmts.push(s);
-    }
+pub fn convert_return_statement(node: &Node, program: &mut ast::Program) -> ast::Statement {
+    assert_eq!(node.kind, SyntaxKind::ReturnStatement);
 
-    ast::Statement::BlockStatement(stmts)
+    let expression = node
+        .child_nodes()
+        .next()
+        .map(|node| convert_expression(node, program));
+    ast::Statement::ReturnStatement(expression)
 }
 
-pub fn convert_expression<'i>(pair: Pair<'i, Rule>, program: &mut ast::Program) -> ast::Expression {
-    assert_eq!(pair.as_rule(), Rule::expression);
+pub fn convert_while_statement(node: &Node, program: &mut ast::Program) -> ast::Statement {
+    assert_eq!(node.kind, SyntaxKind::WhileStatement);
 
-    let pairs = pair.into_inner();
+    let mut inner = node.child_nodes();
+    let condition = convert_expression(inner.next().unwrap(), program);
+    let body = create_statement(inner.next().unwrap(), program);
+    ast::Statement::WhileStatement { condition, body }
+}
 
-    let program_mutex = std::sync::Mutex::new(program);
+pub fn convert_if_statement(node: &Node, program: &mut ast::Program) -> ast::Statement {
+    assert_eq!(node.kind, SyntaxKind::IfStatement);
 
-    let climber = PrecClimber::new(vec![
-        Operator::new(Rule::plus, Assoc::Left) | Operator::new(Rule::minus, Assoc::Left),
-        Operator::new(Rule::star, Assoc::Left) | Operator::new(Rule::slash, Assoc::Left),
-    ]);
+    let mut inner = node.child_nodes();
+    let condition = convert_expression(inner.next().unwrap(), program);
+    let then_statement = create_statement(inner.next().unwrap(), program);
+    let else_statement = inner.next().map(|node| create_statement(node, program));
+    ast::Statement::IfStatement {
+        condition,
+        then_statement,
+        else_statement,
+    }
+}
 
-    let primary = |pair| {
-        let program = &mut **program_mutex.lock().unwrap();
+pub fn convert_assign_statement(node: &Node, program: &mut ast::Program) -> ast::Statement {
+    assert_eq!(node.kind, SyntaxKind::AssignStatement);
 
-        convert_term(pair, program)
-    };
+    let identifier = identifier(node);
+    let expression = convert_expression(node.child_nodes().next().unwrap(), program);
+    ast::Statement::AssignStatement {
+        identifier: identifier.text.clone(),
+        identifier_span: span(identifier),
+        expression,
+    }
+}
 
-    let infix = |lhs, op: Pair<'i, Rule>, rhs| {
-        let program = &mut **program_mutex.lock().unwrap();
+pub fn convert_block_statement(node: &Node, program: &mut ast::Program) -> ast::Statement {
+    assert_eq!(node.kind, SyntaxKind::BlockStatement);
 
-        let op = match op.as_rule() {
-            Rule::star => ast::BinOp::Mul,
-            Rule::slash => ast::BinOp::Divide,
-            Rule::plus => ast::BinOp::Add,
-            Rule::minus => ast::BinOp::Sub,
-            _ => unreachable!(),
-        };
+    let stmts = node
+        .child_nodes()
+        .map(|s| create_statement(s, program))
+        .collect();
 
-        let lhs_id = program.create_expression(lhs);
-        let rhs_id = program.create_expression(rhs);
+    ast::Statement::BlockStatement(stmts)
+}
 
-        ast::Expression::BinOp {
-            op,
-            lhs: lhs_id,
-            rhs: rhs_id,
-        }
+pub fn convert_expression(node: &Node, program: &mut ast::Program) -> ast::ExpressionId {
+    let expression = match node.kind {
+        SyntaxKind::BinaryExpression => convert_binary_expression(node, program),
+        SyntaxKind::ParenExpression => return convert_paren_expression(node, program),
+        SyntaxKind::FunctionCall => convert_function_call(node, program),
+        SyntaxKind::Literal => convert_literal(node),
+        SyntaxKind::Name => convert_name(node),
+        _ => unreachable!(),
     };
-
-    climber.climb(pairs, primary, infix)
+    program.create_expression(expression, node.span())
 }
 
-pub fn convert_term<'i>(pair: Pair<'i, Rule>, program: &mut ast::Program) -> ast::Expression {
-    let inner = pair.into_inner().next().unwrap();
-    match inner.as_rule() {
-        Rule::leaf => convert_leaf(inner),
-        Rule::paren_expression => convert_paren_expression(inner, program),
-        Rule::function_call => convert_function_call(inner, program),
+pub fn convert_binary_expression(node: &Node, program: &mut ast::Program) -> ast::Expression {
+    assert_eq!(node.kind, SyntaxKind::BinaryExpression);
+
+    let mut operands = node.child_nodes();
+    let lhs = convert_expression(operands.next().unwrap(), program);
+    let rhs = convert_expression(operands.next().unwrap(), program);
+    let op = match node.child_tokens().next().unwrap().kind {
+        SyntaxKind::Star => ast::BinOp::Mul,
+        SyntaxKind::Slash => ast::BinOp::Divide,
+        SyntaxKind::Plus => ast::BinOp::Add,
+        SyntaxKind::Minus => ast::BinOp::Sub,
+        SyntaxKind::EqualEqual => ast::BinOp::Equal,
+        SyntaxKind::NotEqual => ast::BinOp::NotEqual,
+        SyntaxKind::Less => ast::BinOp::Less,
+        SyntaxKind::LessEqual => ast::BinOp::LessEqual,
+        SyntaxKind::Greater => ast::BinOp::Greater,
+        SyntaxKind::GreaterEqual => ast::BinOp::GreaterEqual,
         _ => unreachable!(),
-    }
+    };
+
+    ast::Expression::BinOp { op, lhs, rhs }
 }
 
-pub fn convert_paren_expression<'i>(
-    pair: Pair<'i, Rule>,
-    program: &mut ast::Program,
-) -> ast::Expression {
-    assert_eq!(pair.as_rule(), Rule::paren_expression);
-    let expression = pair.into_inner().next().unwrap();
-    convert_expression(expression, program)
+pub fn convert_paren_expression(node: &Node, program: &mut ast::Program) -> ast::ExpressionId {
+    assert_eq!(node.kind, SyntaxKind::ParenExpression);
+    convert_expression(node.child_nodes().next().unwrap(), program)
 }
 
-pub fn convert_function_call<'i>(
-    pair: Pair<'i, Rule>,
-    program: &mut ast::Program,
-) -> ast::Expression {
-    assert_eq!(pair.as_rule(), Rule::function_call);
-    let mut iter = pair.into_inner();
-    let func = convert_identifier(iter.next().unwrap());
-    let args = convert_expression_list(iter.next().unwrap(), program);
+pub fn convert_function_call(node: &Node, program: &mut ast::Program) -> ast::Expression {
+    assert_eq!(node.kind, SyntaxKind::FunctionCall);
 
-    let func = program.create_expression(func);
-    let args = args
-        .into_iter()
-        .map(|e| program.create_expression(e))
-        .collect();
+    let mut inner = node.child_nodes();
+    let func = convert_expression(inner.next().unwrap(), program);
+    let args = convert_expression_list(inner.next().unwrap(), program);
 
     ast::Expression::FunctionCall { func, args }
 }
 
-pub fn convert_expression_list<'i>(
-    pair: Pair<'i, Rule>,
-    program: &mut ast::Program,
-) -> Vec<ast::Expression> {
-    assert_eq!(pair.as_rule(), Rule::expression_list);
-    pair.into_inner()
-        .map(|p| convert_expression(p, program))
+pub fn convert_expression_list(node: &Node, program: &mut ast::Program) -> Vec<ast::ExpressionId> {
+    assert_eq!(node.kind, SyntaxKind::ExpressionList);
+    node.child_nodes()
+        .map(|node| convert_expression(node, program))
         .collect()
 }
 
-pub fn convert_leaf<'i>(pair: Pair<'i, Rule>) -> ast::Expression {
-    let inner = pair.into_inner().next().unwrap();
-    match inner.as_rule() {
-        Rule::integer => convert_integer(inner),
-        Rule::identifier => convert_identifier(inner),
+pub fn convert_literal(node: &Node) -> ast::Expression {
+    assert_eq!(node.kind, SyntaxKind::Literal);
+    let token = node.child_tokens().next().unwrap();
+    match token.kind {
+        SyntaxKind::Integer => ast::Expression::Integer(token.text.parse().unwrap()),
+        SyntaxKind::TrueKeyword => ast::Expression::Boolean(true),
+        SyntaxKind::FalseKeyword => ast::Expression::Boolean(false),
         _ => unreachable!(),
     }
 }
 
-pub fn convert_identifier<'i>(pair: Pair<'i, Rule>) -> ast::Expression {
-    assert_eq!(pair.as_rule(), Rule::identifier);
-    ast::Expression::Identifier(String::from(pair.as_str()))
+pub fn convert_name(node: &Node) -> ast::Expression {
+    assert_eq!(node.kind, SyntaxKind::Name);
+    ast::Expression::Identifier(identifier(node).text.clone())
 }
 
-pub fn convert_integer<'i>(pair: Pair<'i, Rule>) -> ast::Expression {
-    assert_eq!(pair.as_rule(), Rule::integer);
-    ast::Expression::Integer(pair.as_str().parse().unwrap())
-}
-
-pub fn convert_type<'i>(pair: Pair<'i, Rule>) -> ast::Type {
-    assert_eq!(pair.as_rule(), Rule::ty);
-    match pair.as_str() {
+pub fn convert_type(node: &Node) -> ast::Type {
+    assert_eq!(node.kind, SyntaxKind::Type);
+    match node.child_tokens().next().unwrap().text.as_str() {
         "int" => ast::Type::Int,
         "bool" => ast::Type::Boolean,
-        _ => match pair.into_inner().next().unwrap().as_rule() {
-            Rule::unit_ty => ast::Type::Unit,
-            _ => unreachable!(),
-        },
+        "(" => ast::Type::Unit,
+        _ => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn syntax_errors(source: &str) -> Vec<String> {
+        let (tree, errors) = parse(source);
+        assert_eq!(tree.to_string(), source);
+        errors.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn the_tree_keeps_the_source() {
+        let source = "// header\nfn f(x: int,y:bool) -> int {\n  /* a /* nested */ one */ \
+                      let z = (x+1) * f(x , true) ; if y { return z; } // trailing\n}\n\n";
+        let (tree, errors) = parse(source);
+        assert!(errors.is_empty());
+        assert_eq!(tree.to_string(), source);
+
+        let declaration = tree.child_nodes().next().unwrap();
+        assert_eq!(declaration.kind, SyntaxKind::FunctionDeclaration);
+        assert_eq!(declaration.span().start, source.find("fn").unwrap());
+        assert!(tree.tokens()[0].kind.is_comment());
+    }
+
+    #[test]
+    fn expressions_nest_by_precedence() {
+        let (tree, _) = parse("fn f() { a - b * c == d - e; }");
+        let program = convert_program(&tree);
+        let mut visitor = ast::pretty_print_visitor::PrettyPrintVisitor::new();
+        program.accept_program_visitor(&mut visitor);
+        let output = visitor.into_string();
+        let position = |op| output.find(op).unwrap();
+        assert!(position("op: Equal") < position("op: Sub"));
+        assert!(position("op: Sub") < position("op: Mul"));
+    }
+
+    #[test]
+    fn errors_are_recovered_from() {
+        let errors = syntax_errors(
+            "fn f() -> int {\n    let x = 1 +;\n    x = x $ 2;\n    return x\n}\n\
+             }\nfn g(a: int {\n    f(a;\n}\n",
+        );
+        assert_eq!(
+            errors,
+            [
+                "expected an expression, found `;`",
+                "expected `;`, found `2`",
+                "unexpected character `$`",
+                "expected `;`, found `}`",
+                "expected `fn`, found `}`",
+                "expected `,` or `)`, found `{`",
+                "expected `,` or `)`, found `;`",
+            ]
+        );
+        assert_eq!(
+            syntax_errors("fn f() {} /* open"),
+            ["unterminated block comment"]
+        );
+    }
+
+    #[test]
+    fn common_mistakes_have_hints() {
+        let hints = |source| -> Vec<(String, Option<String>)> {
+            let (_, errors) = parse(source);
+            errors
+                .into_iter()
+                .map(|error| (error.message, error.hint))
+                .collect()
+        };
+        let hint = |text: &str| Some(text.to_string());
+
+        let source = "fn f() -> int {\n    return 1\n}\n";
+        assert_eq!(parse(source).1[0].span.start, source.find('1').unwrap() + 1);
+        assert_eq!(
+            hints(source),
+            [(
+                "expected `;`, found `}`".to_string(),
+                hint("add a `;` at the end of the statement")
+            )]
+        );
+        assert_eq!(
+            hints("fn f() {\n    while true {\n        let x = 1;\n}\n"),
+            [(
+                "expected a statement or `}`, found the end of the file".to_string(),
+                hint("the `{` on line 1 is never closed")
+            )]
+        );
+        assert_eq!(
+            hints("fn f() {}\n}\n"),
+            [(
+                "expected `fn`, found `}`".to_string(),
+                hint("this `}` has no matching `{`")
+            )]
+        );
+        assert_eq!(
+            hints("fn f(x: int) -> int;\nfn g() {}\n"),
+            [(
+                "expected `{`, found `;`".to_string(),
+                hint("a function needs a body, even an empty one like `{}`")
+            )]
+        );
+    }
+
+    #[test]
+    fn deep_nesting_is_an_error() {
+        let nested = |depth| {
+            format!(
+                "fn main() -> int {{\n    {}return {}1{};{}\n}}\n",
+                "{ ".repeat(depth),
+                "(".repeat(depth),
+                ")".repeat(depth),
+                " }".repeat(depth)
+            )
+        };
+        assert_eq!(syntax_errors(&nested(50)), Vec::<String>::new());
+        assert!(crate::compile(&nested(50), 2).is_ok());
+
+        assert_eq!(
+            syntax_errors(&nested(50_000)),
+            ["the code is nested too deeply"]
+        );
+        let source = format!("fn f() -> int {{ return {}1; }}", "(".repeat(50_000));
+        assert_eq!(syntax_errors(&source), ["the code is nested too deeply"]);
+    }
+
+    #[test]
+    fn long_operator_chains_are_nested() {
+        let chain = |operators: usize, operator| {
+            format!(
+                "fn main() -> int {{\n    return 1{};\n}}\n",
+                format!(" {} 1", operator).repeat(operators)
+            )
+        };
+        let source = chain(200, "+");
+        assert_eq!(syntax_errors(&source), Vec::<String>::new());
+        assert!(crate::format(&source).is_ok());
+        let program = crate::parse(&source).unwrap();
+        assert_eq!(crate::interpreter::run_main(&program), Ok(201));
+        assert!(crate::compile(&source, 2).is_ok());
+
+        for &operators in &[5_000, 20_000] {
+            assert_eq!(
+                syntax_errors(&chain(operators, "+")),
+                ["the code is nested too deeply"]
+            );
+        }
+        // parentheses and operators add up
+        let source = format!(
+            "fn f() -> int {{ return {}1{}{}; }}",
+            "(".repeat(200),
+            ")".repeat(200),
+            " * 1".repeat(100)
+        );
+        assert_eq!(syntax_errors(&source), ["the code is nested too deeply"]);
     }
 }
@@ -0,0 +1,349 @@
+//! Compilation of the IR to bytecode.
+
+use super::opcode;
+use super::{Bytecode, FunctionInfo};
+use crate::ir::*;
+
+/// Compiles `module`, fails if there are more functions or locals than the bytecode can
+/// address.
+pub fn compile_module(module: &Module) -> Result<Bytecode, String> {
+    if module.functions.len() > usize::from(u16::MAX) + 1 {
+        return Err("too many functions for the bytecode".to_string());
+    }
+
+    let mut bytecode = Bytecode {
+        functions: Vec::new(),
+        code: Vec::new(),
+    };
+
+    for function in &module.functions {
+        let locals = function.params().len() + function.value_count() + function.variables().len();
+        if locals > usize::from(u16::MAX) + 1 {
+            return Err(format!(
+                "function `{}` has too many locals for the bytecode",
+                function.name()
+            ));
+        }
+
+        bytecode.functions.push(FunctionInfo {
+            name: function.name().to_string(),
+            arity: function.params().len(),
+            locals,
+            entry: bytecode.code.len(),
+        });
+        FunctionCompiler::new(function, &mut bytecode.code).compile();
+    }
+
+    Ok(bytecode)
+}
+
+struct FunctionCompiler<'a> {
+    function: &'a Function,
+    code: &'a mut Vec<u8>,
+    block_offsets: Vec<usize>,
+    /// Offsets of the jump operands to patch with the offset of a block.
+    fixups: Vec<(usize, BB)>,
+    use_counts: Vec<usize>,
+    /// A value left on the operand stack for the next instruction, instead of being stored and
+    /// loaded back.
+    on_stack: Option<Value>,
+}
+
+impl<'a> FunctionCompiler<'a> {
+    fn new(function: &'a Function, code: &'a mut Vec<u8>) -> Self {
+        let mut use_counts = vec![0; function.value_count()];
+        for bb in function.basic_blocks() {
+            for statement in bb.statements() {
+                let operation = match *statement {
+                    Statement::WithDestination(_, ref operation)
+                    | Statement::Operation(ref operation) => operation,
+                };
+                for operand in operation.operands() {
+                    use_counts[operand.index()] += 1;
+                }
+            }
+            for operand in bb.terminator().operands() {
+                use_counts[operand.index()] += 1;
+            }
+        }
+
+        FunctionCompiler {
+            function,
+            code,
+            block_offsets: Vec::new(),
+            fixups: Vec::new(),
+            use_counts,
+            on_stack: None,
+        }
+    }
+
+    fn compile(mut self) {
+        for (index, bb) in self.function.basic_blocks().iter().enumerate() {
+            self.block_offsets.push(self.code.len());
+            let statements = bb.statements();
+            for (position, statement) in statements.iter().enumerate() {
+                // the first operand loaded by the next instruction
+                let next_operand = match statements.get(position + 1) {
+                    Some(Statement::WithDestination(_, operation))
+                    | Some(Statement::Operation(operation)) => {
+                        operation.operands().first().cloned()
+                    }
+                    None => bb.terminator().operands().first().cloned(),
+                };
+                self.compile_statement(statement, next_operand);
+            }
+
+            let next = BB(index + 1);
+            match *bb.terminator() {
+                Terminator::Return(value) => {
+                    self.load(value);
+                    self.code.push(opcode::RET);
+                }
+                Terminator::Jump(target) => {
+                    if target != next {
+                        self.jump(opcode::JMP, target);
+                    }
+                }
+                Terminator::Branch(cond, then_bb, else_bb) => {
+                    self.load(cond);
+                    self.jump(opcode::JZ, else_bb);
+                    if then_bb != next {
+                        self.jump(opcode::JMP, then_bb);
+                    }
+                }
+                Terminator::Unreachable => self.code.push(opcode::TRAP),
+            }
+        }
+
+        for (position, target) in self.fixups {
+            let offset = self.block_offsets[target.0] as u32;
+            self.code[position..position + 4].copy_from_slice(&offset.to_le_bytes());
+        }
+    }
+
+    fn compile_statement(&mut self, statement: &Statement, next_operand: Option<Value>) {
+        let (destination, operation) = match *statement {
+            Statement::WithDestination(value, ref operation) => (Some(value), operation),
+            Statement::Operation(ref operation) => (None, operation),
+        };
+
+        match *operation {
+            Operation::Literal(ref literal) => {
+                let constant = match *literal {
+                    Literal::Unit => 0,
+                    Literal::Int(i) => i,
+                    Literal::Boolean(b) => b as i32,
+                };
+                self.code.push(opcode::CONST);
+                self.code.extend_from_slice(&constant.to_le_bytes());
+            }
+            Operation::BinaryAdd(lhs, rhs) => self.binary(opcode::ADD, lhs, rhs),
+            Operation::BinarySub(lhs, rhs) => self.binary(opcode::SUB, lhs, rhs),
+            Operation::BinaryMul(lhs, rhs) => self.binary(opcode::MUL, lhs, rhs),
+            Operation::BinaryDivide(lhs, rhs) => self.binary(opcode::DIV, lhs, rhs),
+            Operation::BinaryShiftLeft(lhs, rhs) => self.binary(opcode::SHL, lhs, rhs),
+            Operation::Negate(value) => {
+                self.load(value);
+                self.code.push(opcode::NEG);
+            }
+            Operation::Compare(comparison, lhs, rhs) => {
+                let opcode = match comparison {
+                    Comparison::Equal => opcode::EQ,
+                    Comparison::NotEqual => opcode::NE,
+                    Comparison::Less => opcode::LT,
+                    Comparison::LessEqual => opcode::LE,
+                    Comparison::Greater => opcode::GT,
+                    Comparison::GreaterEqual => opcode::GE,
+                };
+                self.binary(opcode, lhs, rhs);
+            }
+            Operation::LoadParam(index) => self.local(opcode::LOAD, index),
+            Operation::LoadVariable(var) => self.local(opcode::LOAD, self.variable_local(var)),
+            Operation::StoreVariable(var, value) => {
+                self.load(value);
+                self.local(opcode::STORE, self.variable_local(var));
+            }
+            Operation::Call { callee, ref args } => {
+                for &arg in args {
+                    self.load(arg);
+                }
+                self.code.push(opcode::CALL);
+                self.code.extend_from_slice(&(callee as u16).to_le_bytes());
+                if destination.is_none() {
+                    self.code.push(opcode::POP);
+                }
+            }
+        }
+
+        if let Some(destination) = destination {
+            if next_operand == Some(destination) && self.use_counts[destination.index()] == 1 {
+                self.on_stack = Some(destination);
+            } else {
+                self.local(opcode::STORE, self.value_local(destination));
+            }
+        }
+    }
+
+    fn binary(&mut self, opcode: u8, lhs: Value, rhs: Value) {
+        self.load(lhs);
+        self.load(rhs);
+        self.code.push(opcode);
+    }
+
+    fn load(&mut self, value: Value) {
+        if self.on_stack == Some(value) {
+            self.on_stack = None;
+            return;
+        }
+        self.local(opcode::LOAD, self.value_local(value));
+    }
+
+    fn local(&mut self, opcode: u8, index: usize) {
+        self.code.push(opcode);
+        self.code.extend_from_slice(&(index as u16).to_le_bytes());
+    }
+
+    fn jump(&mut self, opcode: u8, target: BB) {
+        self.code.push(opcode);
+        self.fixups.push((self.code.len(), target));
+        self.code.extend_from_slice(&[0; 4]);
+    }
+
+    fn value_local(&self, value: Value) -> usize {
+        self.function.params().len() + value.index()
+    }
+
+    fn variable_local(&self, var: Variable) -> usize {
+        self.function.params().len() + self.function.value_count() + var.index()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ir::text::parse_module;
+
+    fn compile(input: &str) -> Result<Bytecode, String> {
+        compile_module(&parse_module(input).unwrap())
+    }
+
+    #[test]
+    fn test_values_used_once_stay_on_the_stack() {
+        let bytecode = compile(
+            "fn f(int, int) -> int {
+bb0:
+    %0 = param 0
+    %1 = param 1
+    %2 = add %0, %1
+    %3 = mul %2, %0
+    ret %3
+}
+",
+        )
+        .unwrap();
+        assert_eq!(
+            bytecode.to_string(),
+            "f: ; arity 2, 6 locals
+     0  load 0
+     3  store 2
+     6  load 1
+     9  store 3
+    12  load 2
+    15  load 3
+    18  add
+    19  load 2
+    22  mul
+    23  ret
+"
+        );
+    }
+
+    #[test]
+    fn test_values_used_twice_are_stored() {
+        let bytecode = compile(
+            "fn f(int) -> int {
+bb0:
+    %0 = param 0
+    %1 = neg %0
+    %2 = add %1, %1
+    ret %2
+}
+",
+        )
+        .unwrap();
+        assert_eq!(
+            bytecode.to_string(),
+            "f: ; arity 1, 4 locals
+     0  load 0
+     3  neg
+     4  store 2
+     7  load 2
+    10  load 2
+    13  add
+    14  ret
+"
+        );
+    }
+
+    #[test]
+    fn test_branches() {
+        let bytecode = compile(
+            "fn f(bool) -> int {
+bb0:
+    %0 = param 0
+    br %0, bb1, bb2
+bb1:
+    %1 = const 1
+    ret %1
+bb2:
+    %2 = const 2
+    ret %2
+}
+",
+        )
+        .unwrap();
+        assert_eq!(
+            bytecode.to_string(),
+            "f: ; arity 1, 4 locals
+     0  load 0
+     3  jz 14
+     8  const 1
+    13  ret
+    14  const 2
+    19  ret
+"
+        );
+    }
+
+    fn with_variables(count: usize) -> String {
+        format!(
+            "fn f() -> int {{
+    vars: {}
+bb0:
+    %0 = const 1
+    store ${}, %0
+    %1 = load ${}
+    ret %1
+}}
+",
+            vec!["int"; count].join(", "),
+            count - 1,
+            count - 1
+        )
+    }
+
+    #[test]
+    fn test_locals_limit() {
+        // two values and the variables
+        let limit = usize::from(u16::MAX) + 1 - 2;
+        let bytecode = compile(&with_variables(limit)).unwrap();
+        assert_eq!(bytecode.functions[0].locals, usize::from(u16::MAX) + 1);
+        assert!(bytecode.to_string().contains("store 65535"));
+
+        assert_eq!(
+            compile(&with_variables(limit + 1)).unwrap_err(),
+            "function `f` has too many locals for the bytecode"
+        );
+    }
+}
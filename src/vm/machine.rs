@@ -0,0 +1,364 @@
+//! The virtual machine running the bytecode.
+
+use super::opcode;
+use super::Bytecode;
+
+use std::fmt;
+
+/// Maximum number of values on the operand stack.
+pub const STACK_SIZE: usize = 1 << 16;
+/// Maximum number of nested calls.
+pub const MAX_CALL_DEPTH: usize = 1 << 14;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VmError {
+    UnknownFunction(String),
+    ArityMismatch { expected: usize, found: usize },
+    StackOverflow,
+    StackUnderflow,
+    CallDepthExceeded,
+    DivisionByZero,
+    DivisionOverflow,
+    Unreachable,
+    InvalidOpcode { opcode: u8, pc: usize },
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            VmError::UnknownFunction(ref name) => write!(f, "unknown function `{}`", name),
+            VmError::ArityMismatch { expected, found } => write!(
+                f,
+                "expected {} arguments but {} were given",
+                expected, found
+            ),
+            VmError::StackOverflow => write!(f, "operand stack overflow"),
+            VmError::StackUnderflow => write!(f, "operand stack underflow"),
+            VmError::CallDepthExceeded => write!(f, "maximum call depth exceeded"),
+            VmError::DivisionByZero => write!(f, "division by zero"),
+            VmError::DivisionOverflow => write!(f, "division overflow"),
+            VmError::Unreachable => write!(f, "reached unreachable code"),
+            VmError::InvalidOpcode { opcode, pc } => {
+                write!(f, "invalid opcode {} at offset {}", opcode, pc)
+            }
+        }
+    }
+}
+
+/// The state of a caller, restored when the callee returns.
+#[derive(Debug, Clone, Copy)]
+struct Frame {
+    return_pc: usize,
+    base: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Machine<'a> {
+    bytecode: &'a Bytecode,
+    stack: Box<[i32]>,
+    /// Number of values on the operand stack.
+    stack_len: usize,
+    locals: Vec<i32>,
+    frames: Vec<Frame>,
+}
+
+impl<'a> Machine<'a> {
+    pub fn new(bytecode: &'a Bytecode) -> Self {
+        Machine {
+            bytecode,
+            stack: vec![0; STACK_SIZE].into_boxed_slice(),
+            stack_len: 0,
+            locals: Vec::new(),
+            frames: Vec::new(),
+        }
+    }
+
+    /// Calls the function `name` with `args`, and returns its result.
+    pub fn run(&mut self, name: &str, args: &[i32]) -> Result<i32, VmError> {
+        let index = self
+            .bytecode
+            .function_index(name)
+            .ok_or_else(|| VmError::UnknownFunction(name.to_string()))?;
+        let function = &self.bytecode.functions[index];
+        if function.arity != args.len() {
+            return Err(VmError::ArityMismatch {
+                expected: function.arity,
+                found: args.len(),
+            });
+        }
+
+        self.stack_len = 0;
+        self.locals.clear();
+        self.frames.clear();
+        self.locals.resize(function.locals, 0);
+        self.locals[..args.len()].copy_from_slice(args);
+        self.execute(function.entry)
+    }
+
+    fn execute(&mut self, entry: usize) -> Result<i32, VmError> {
+        let bytecode = self.bytecode;
+        let code = &bytecode.code;
+        let mut pc = entry;
+        let mut base = 0;
+
+        loop {
+            let op = code[pc];
+            pc += 1;
+            match op {
+                opcode::CONST => {
+                    let constant = read_i32(code, pc);
+                    pc += 4;
+                    self.push(constant)?;
+                }
+                opcode::LOAD => {
+                    let index = read_u16(code, pc);
+                    pc += 2;
+                    let value = self.locals[base + index];
+                    self.push(value)?;
+                }
+                opcode::STORE => {
+                    let index = read_u16(code, pc);
+                    pc += 2;
+                    self.locals[base + index] = self.pop()?;
+                }
+                opcode::ADD => self.binary(i32::wrapping_add)?,
+                opcode::SUB => self.binary(i32::wrapping_sub)?,
+                opcode::MUL => self.binary(i32::wrapping_mul)?,
+                opcode::DIV => {
+                    let rhs = self.pop()?;
+                    let lhs = self.pop()?;
+                    if rhs == 0 {
+                        return Err(VmError::DivisionByZero);
+                    }
+                    let result = lhs.checked_div(rhs).ok_or(VmError::DivisionOverflow)?;
+                    self.push(result)?;
+                }
+                opcode::SHL => self.binary(|lhs, rhs| lhs.wrapping_shl(rhs as u32))?,
+                opcode::NEG => {
+                    let value = self.pop()?;
+                    self.push(value.wrapping_neg())?;
+                }
+                opcode::EQ => self.binary(|lhs, rhs| (lhs == rhs) as i32)?,
+                opcode::NE => self.binary(|lhs, rhs| (lhs != rhs) as i32)?,
+                opcode::LT => self.binary(|lhs, rhs| (lhs < rhs) as i32)?,
+                opcode::LE => self.binary(|lhs, rhs| (lhs <= rhs) as i32)?,
+                opcode::GT => self.binary(|lhs, rhs| (lhs > rhs) as i32)?,
+                opcode::GE => self.binary(|lhs, rhs| (lhs >= rhs) as i32)?,
+                opcode::JMP => pc = read_u32(code, pc),
+                opcode::JZ => {
+                    if self.pop()? == 0 {
+                        pc = read_u32(code, pc);
+                    } else {
+                        pc += 4;
+                    }
+                }
+                opcode::CALL => {
+                    let callee = &bytecode.functions[read_u16(code, pc)];
+                    pc += 2;
+                    if self.frames.len() == MAX_CALL_DEPTH {
+                        return Err(VmError::CallDepthExceeded);
+                    }
+                    if self.stack_len < callee.arity {
+                        return Err(VmError::StackUnderflow);
+                    }
+
+                    self.frames.push(Frame {
+                        return_pc: pc,
+                        base,
+                    });
+                    base = self.locals.len();
+                    self.locals.resize(base + callee.locals, 0);
+                    let args_start = self.stack_len - callee.arity;
+                    self.locals[base..base + callee.arity]
+                        .copy_from_slice(&self.stack[args_start..self.stack_len]);
+                    self.stack_len = args_start;
+                    pc = callee.entry;
+                }
+                opcode::RET => {
+                    let value = self.pop()?;
+                    self.locals.truncate(base);
+                    match self.frames.pop() {
+                        Some(frame) => {
+                            pc = frame.return_pc;
+                            base = frame.base;
+                            self.push(value)?;
+                        }
+                        None => return Ok(value),
+                    }
+                }
+                opcode::POP => {
+                    self.pop()?;
+                }
+                opcode::TRAP => return Err(VmError::Unreachable),
+                _ => {
+                    return Err(VmError::InvalidOpcode {
+                        opcode: op,
+                        pc: pc - 1,
+                    })
+                }
+            }
+        }
+    }
+
+    #[inline]
+    fn push(&mut self, value: i32) -> Result<(), VmError> {
+        if self.stack_len == STACK_SIZE {
+            return Err(VmError::StackOverflow);
+        }
+        self.stack[self.stack_len] = value;
+        self.stack_len += 1;
+        Ok(())
+    }
+
+    #[inline]
+    fn pop(&mut self) -> Result<i32, VmError> {
+        if self.stack_len == 0 {
+            return Err(VmError::StackUnderflow);
+        }
+        self.stack_len -= 1;
+        Ok(self.stack[self.stack_len])
+    }
+
+    #[inline]
+    fn binary<F: Fn(i32, i32) -> i32>(&mut self, f: F) -> Result<(), VmError> {
+        let rhs = self.pop()?;
+        let lhs = self.pop()?;
+        self.push(f(lhs, rhs))
+    }
+}
+
+fn read_u16(code: &[u8], pc: usize) -> usize {
+    usize::from(u16::from_le_bytes([code[pc], code[pc + 1]]))
+}
+
+fn read_u32(code: &[u8], pc: usize) -> usize {
+    u32::from_le_bytes([code[pc], code[pc + 1], code[pc + 2], code[pc + 3]]) as usize
+}
+
+fn read_i32(code: &[u8], pc: usize) -> i32 {
+    i32::from_le_bytes([code[pc], code[pc + 1], code[pc + 2], code[pc + 3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ir::text::parse_module;
+    use crate::vm::compiler::compile_module;
+
+    fn run(input: &str, name: &str, args: &[i32]) -> Result<i32, VmError> {
+        let bytecode = compile_module(&parse_module(input).unwrap()).unwrap();
+        Machine::new(&bytecode).run(name, args)
+    }
+
+    const DIVIDE: &str = "fn div(int, int) -> int {
+bb0:
+    %0 = param 0
+    %1 = param 1
+    %2 = div %0, %1
+    ret %2
+}
+";
+
+    #[test]
+    fn test_division() {
+        assert_eq!(run(DIVIDE, "div", &[-7, 2]), Ok(-3));
+        assert_eq!(run(DIVIDE, "div", &[7, 0]), Err(VmError::DivisionByZero));
+        assert_eq!(
+            run(DIVIDE, "div", &[i32::MIN, -1]),
+            Err(VmError::DivisionOverflow)
+        );
+        assert_eq!(run(DIVIDE, "div", &[i32::MIN, 1]), Ok(i32::MIN));
+    }
+
+    #[test]
+    fn test_arithmetic_wraps() {
+        let input = "fn f(int) -> int {
+bb0:
+    %0 = param 0
+    %1 = const 1
+    %2 = add %0, %1
+    %3 = neg %0
+    %4 = mul %2, %3
+    ret %4
+}
+";
+        assert_eq!(
+            run(input, "f", &[i32::MAX]),
+            Ok(i32::MIN.wrapping_mul(-i32::MAX))
+        );
+        assert_eq!(
+            run(input, "f", &[i32::MIN]),
+            Ok(i32::MIN.wrapping_add(1).wrapping_mul(i32::MIN))
+        );
+    }
+
+    const FACTORIAL: &str = "fn fact(int) -> int {
+bb0:
+    %0 = param 0
+    %1 = const 1
+    %2 = le %0, %1
+    br %2, bb1, bb2
+bb1:
+    ret %1
+bb2:
+    %3 = sub %0, %1
+    %4 = call @0(%3)
+    %5 = mul %0, %4
+    ret %5
+}
+
+fn main() -> int {
+bb0:
+    %0 = const 10
+    %1 = call @0(%0)
+    %2 = const 3
+    %3 = call @0(%2)
+    %4 = add %1, %3
+    ret %4
+}
+";
+
+    #[test]
+    fn test_recursion() {
+        assert_eq!(run(FACTORIAL, "fact", &[5]), Ok(120));
+        // the locals of the caller are restored after each call
+        assert_eq!(run(FACTORIAL, "main", &[]), Ok(3_628_800 + 6));
+    }
+
+    #[test]
+    fn test_call_depth() {
+        // `fact(n)` makes `n - 1` nested calls
+        let depth = MAX_CALL_DEPTH as i32;
+        assert_eq!(run(FACTORIAL, "fact", &[depth + 1]), Ok(0));
+        assert_eq!(
+            run(FACTORIAL, "fact", &[depth + 2]),
+            Err(VmError::CallDepthExceeded)
+        );
+    }
+
+    #[test]
+    fn test_calls_from_the_host() {
+        assert_eq!(
+            run(FACTORIAL, "fib", &[]),
+            Err(VmError::UnknownFunction("fib".to_string()))
+        );
+        assert_eq!(
+            run(FACTORIAL, "fact", &[1, 2]),
+            Err(VmError::ArityMismatch {
+                expected: 1,
+                found: 2
+            })
+        );
+    }
+
+    #[test]
+    fn test_unreachable() {
+        let input = "fn f() -> int {
+bb0:
+    unreachable
+}
+";
+        assert_eq!(run(input, "f", &[]), Err(VmError::Unreachable));
+    }
+}
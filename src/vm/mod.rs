@@ -0,0 +1,138 @@
+//! A compact bytecode compiled from the IR and a stack-based virtual machine running it.
+//!
+//! Every function has a frame of 32-bit locals holding its parameters first, then the IR values
+//! and variables. Instructions operate on an operand stack, and are encoded as a one-byte opcode
+//! followed by little-endian operands:
+//! - `const i32`, `load u16`, `store u16`, `call u16`, `jmp u32`, `jz u32`
+//! - `add`, `sub`, `mul`, `div`, `shl`, `neg`, `eq`, `ne`, `lt`, `le`, `gt`, `ge`, `pop`,
+//!   `ret`, `trap`
+
+pub mod compiler;
+pub mod machine;
+
+use std::fmt;
+
+pub mod opcode {
+    pub const CONST: u8 = 0;
+    pub const LOAD: u8 = 1;
+    pub const STORE: u8 = 2;
+    pub const ADD: u8 = 3;
+    pub const SUB: u8 = 4;
+    pub const MUL: u8 = 5;
+    pub const DIV: u8 = 6;
+    pub const SHL: u8 = 7;
+    pub const NEG: u8 = 8;
+    pub const EQ: u8 = 9;
+    pub const NE: u8 = 10;
+    pub const LT: u8 = 11;
+    pub const LE: u8 = 12;
+    pub const GT: u8 = 13;
+    pub const GE: u8 = 14;
+    pub const JMP: u8 = 15;
+    pub const JZ: u8 = 16;
+    pub const CALL: u8 = 17;
+    pub const RET: u8 = 18;
+    pub const POP: u8 = 19;
+    pub const TRAP: u8 = 20;
+
+    /// Returns the mnemonic and the size of the operand of `opcode`.
+    pub fn info(opcode: u8) -> Option<(&'static str, usize)> {
+        let info = match opcode {
+            CONST => ("const", 4),
+            LOAD => ("load", 2),
+            STORE => ("store", 2),
+            ADD => ("add", 0),
+            SUB => ("sub", 0),
+            MUL => ("mul", 0),
+            DIV => ("div", 0),
+            SHL => ("shl", 0),
+            NEG => ("neg", 0),
+            EQ => ("eq", 0),
+            NE => ("ne", 0),
+            LT => ("lt", 0),
+            LE => ("le", 0),
+            GT => ("gt", 0),
+            GE => ("ge", 0),
+            JMP => ("jmp", 4),
+            JZ => ("jz", 4),
+            CALL => ("call", 2),
+            RET => ("ret", 0),
+            POP => ("pop", 0),
+            TRAP => ("trap", 0),
+            _ => return None,
+        };
+        Some(info)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FunctionInfo {
+    pub name: String,
+    pub arity: usize,
+    /// Number of locals of the frame, parameters included.
+    pub locals: usize,
+    /// Offset of the first instruction in the code.
+    pub entry: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Bytecode {
+    pub functions: Vec<FunctionInfo>,
+    pub code: Vec<u8>,
+}
+
+impl Bytecode {
+    pub fn function_index(&self, name: &str) -> Option<usize> {
+        self.functions
+            .iter()
+            .position(|function| function.name == name)
+    }
+}
+
+impl fmt::Display for Bytecode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, function) in self.functions.iter().enumerate() {
+            if index != 0 {
+                writeln!(f)?;
+            }
+            writeln!(
+                f,
+                "{}: ; arity {}, {} locals",
+                function.name, function.arity, function.locals
+            )?;
+
+            let end = self
+                .functions
+                .get(index + 1)
+                .map_or(self.code.len(), |next| next.entry);
+            let mut pc = function.entry;
+            while pc < end {
+                let (name, size) = match opcode::info(self.code[pc]) {
+                    Some(info) => info,
+                    None => return writeln!(f, "{:6}  <invalid {}>", pc, self.code[pc]),
+                };
+                let operand = &self.code[pc + 1..pc + 1 + size];
+                match size {
+                    0 => writeln!(f, "{:6}  {}", pc, name)?,
+                    2 => writeln!(
+                        f,
+                        "{:6}  {} {}",
+                        pc,
+                        name,
+                        u16::from_le_bytes([operand[0], operand[1]])
+                    )?,
+                    _ => {
+                        let bytes = [operand[0], operand[1], operand[2], operand[3]];
+                        if self.code[pc] == opcode::CONST {
+                            writeln!(f, "{:6}  {} {}", pc, name, i32::from_le_bytes(bytes))?
+                        } else {
+                            writeln!(f, "{:6}  {} {}", pc, name, u32::from_le_bytes(bytes))?
+                        }
+                    }
+                }
+                pc += 1 + size;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,206 @@
+//! Golden-file tests of the compiler.
+//!
+//! Every `.hai` file of `tests/ui` and `examples_hai` goes through `hai build --emit=ast`,
+//! `hai build --emit=ir` and `hai run`, stopping at the first command that fails. The outputs are
+//! compared with the files next to the source, or in `tests/ui/examples` for the examples:
+//!
+//! - `<name>.ast.stdout` and `<name>.ir.stdout`, the printed AST and IR,
+//! - `<name>.run.stdout`, the value returned by `main`,
+//! - `<name>.stderr`, the diagnostics of the command that failed.
+//!
+//! Run `cargo test --test ui -- --bless` to write the outputs to the expectation files instead,
+//! and add names after `--` to only run the cases containing them.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The commands run on each case, with the expectation file of their output.
+const COMMANDS: [(&str, &[&str]); 3] = [
+    ("ast.stdout", &["build", "--emit=ast"]),
+    ("ir.stdout", &["build", "--emit=ir"]),
+    ("run.stdout", &["run"]),
+];
+
+/// The expectation files that can be written for a case.
+const SUFFIXES: [&str; 4] = ["ast.stdout", "ir.stdout", "run.stdout", "stderr"];
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let bless = args.iter().any(|arg| arg == "--bless");
+    let filters: Vec<&String> = args.iter().filter(|arg| !arg.starts_with('-')).collect();
+
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut cases = list_cases(root, "tests/ui", "tests/ui", "");
+    cases.extend(list_cases(
+        root,
+        "examples_hai",
+        "tests/ui/examples",
+        "examples/",
+    ));
+
+    let mut failures = Vec::new();
+    let mut count = 0;
+    for case in &cases {
+        let name = &case.name;
+        if !filters.is_empty() && !filters.iter().any(|filter| name.contains(filter.as_str())) {
+            continue;
+        }
+        count += 1;
+
+        let mismatches = check_case(root, case, bless);
+        if mismatches.is_empty() {
+            println!("test ui/{} ... ok", name);
+        } else {
+            println!("test ui/{} ... FAILED", name);
+            failures.push((name.clone(), mismatches));
+        }
+    }
+
+    for (name, mismatches) in &failures {
+        println!("\n---- ui/{} ----", name);
+        for mismatch in mismatches {
+            println!("{}", mismatch);
+        }
+    }
+    println!(
+        "\nui test result: {}. {} passed; {} failed",
+        if failures.is_empty() { "ok" } else { "FAILED" },
+        count - failures.len(),
+        failures.len()
+    );
+    if !failures.is_empty() {
+        println!("run `cargo test --test ui -- --bless` to update the expectations");
+        std::process::exit(1);
+    }
+}
+
+/// A source file to compile, and where its expectation files are.
+struct Case {
+    name: String,
+    source: PathBuf,
+    /// The path of the expectation files, without their suffix.
+    expectations: PathBuf,
+}
+
+/// Lists the `.hai` files of `directory`, whose expectation files are in `expectation_directory`.
+fn list_cases(
+    root: &Path,
+    directory: &str,
+    expectation_directory: &str,
+    prefix: &str,
+) -> Vec<Case> {
+    let mut cases: Vec<Case> = fs::read_dir(root.join(directory))
+        .unwrap_or_else(|_| panic!("can't list {}", directory))
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "hai"))
+        .map(|source| {
+            let stem = source.file_stem().unwrap().to_string_lossy().into_owned();
+            Case {
+                name: format!("{}{}", prefix, stem),
+                expectations: root.join(expectation_directory).join(&stem),
+                source,
+            }
+        })
+        .collect();
+    cases.sort_by(|a, b| a.source.cmp(&b.source));
+    cases
+}
+
+/// Returns the outputs of the compiler on a case, by expectation file suffix.
+fn run_case(root: &Path, case: &Path) -> Vec<(&'static str, String)> {
+    // a relative path keeps the diagnostics the same on every machine
+    let relative = case.strip_prefix(root).unwrap();
+
+    let mut outputs = Vec::new();
+    for &(suffix, arguments) in &COMMANDS {
+        let output = Command::new(env!("CARGO_BIN_EXE_hai"))
+            .args(arguments)
+            .arg(relative)
+            .current_dir(root)
+            .output()
+            .expect("can't run hai");
+        let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+
+        if output.status.success() {
+            outputs.push((suffix, stdout));
+            continue;
+        }
+
+        outputs.push(("stderr", stderr));
+        break;
+    }
+    outputs
+}
+
+/// Compares the outputs of a case with its expectation files, or writes them when blessing.
+/// Returns the differences found.
+fn check_case(root: &Path, case: &Case, bless: bool) -> Vec<String> {
+    let outputs = run_case(root, &case.source);
+
+    let mut mismatches = Vec::new();
+    for &suffix in &SUFFIXES {
+        let path = case.expectations.with_extension(suffix);
+        let actual = outputs
+            .iter()
+            .find(|&&(output_suffix, _)| output_suffix == suffix)
+            .map(|(_, output)| output.as_str());
+        let expected = fs::read_to_string(&path).ok();
+
+        if bless {
+            match actual {
+                Some(actual) => {
+                    fs::create_dir_all(path.parent().unwrap()).unwrap();
+                    fs::write(&path, actual).unwrap();
+                }
+                None if expected.is_some() => fs::remove_file(&path).unwrap(),
+                None => {}
+            }
+            continue;
+        }
+
+        let file = path.file_name().unwrap().to_string_lossy();
+        match (expected.as_deref(), actual) {
+            (Some(expected), Some(actual)) if expected != actual => {
+                mismatches.push(format!("{} differs:\n{}", file, diff(expected, actual)));
+            }
+            (None, Some(actual)) => {
+                mismatches.push(format!("{} is missing, the output is:\n{}", file, actual));
+            }
+            (Some(_), None) => {
+                mismatches.push(format!("{} is expected, but there is no output", file));
+            }
+            _ => {}
+        }
+    }
+    mismatches
+}
+
+/// Lists the lines that differ, the expected ones with `-` and the actual ones with `+`.
+fn diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+
+    // skip the lines in common at both ends, what remains in the middle is the difference
+    let prefix = expected
+        .iter()
+        .zip(&actual)
+        .take_while(|(expected, actual)| expected == actual)
+        .count();
+    let suffix = expected[prefix..]
+        .iter()
+        .rev()
+        .zip(actual[prefix..].iter().rev())
+        .take_while(|(expected, actual)| expected == actual)
+        .count();
+
+    let mut output = format!("@@ line {} @@\n", prefix + 1);
+    for line in &expected[prefix..expected.len() - suffix] {
+        output.push_str(&format!("-{}\n", line));
+    }
+    for line in &actual[prefix..actual.len() - suffix] {
+        output.push_str(&format!("+{}\n", line));
+    }
+    output
+}